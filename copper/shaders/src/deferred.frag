@@ -6,6 +6,30 @@ layout (binding = 2) uniform sampler2D i_reflectance_ao;
 layout (binding = 3) uniform sampler2D i_lighting;
 layout (binding = 4) uniform sampler2D i_depth;
 
+// --- matches lighting::LightData and lighting::LightBlock
+struct Light {
+    vec4 position_and_type;
+    vec4 direction_and_range;
+    vec4 color_and_intensity;
+    vec4 axis_x_and_cos_inner;
+    vec4 axis_y_and_cos_outer;
+};
+
+layout (std430, binding = 5) readonly buffer LightBuffer {
+    mat4 view;
+    mat4 inverse_projection;
+    vec4 ambient_and_exposure;
+    uvec4 count;
+    Light lights[];
+} light_buffer;
+
+const float PI = 3.14159265;
+const float LIGHT_TYPE_DIRECTIONAL = 0.0;
+const float LIGHT_TYPE_POINT = 1.0;
+const float LIGHT_TYPE_SPOT = 2.0;
+const float LIGHT_TYPE_AREA = 3.0;
+const float MATERIAL_TYPE_PURE_EMISSIVE = 2.0;
+
 layout (location = 0) in vec2 i_uv;
 
 layout (location = 0) out vec4 o_frag_color;
@@ -37,9 +61,96 @@ vec3 sRGB_to_linear(vec3 srgb) {
     return res;
 }
 
+// --- the projection is not adjusted for Vulkan, so depth is its z in normalized device coordinates
+vec3 view_position(vec2 uv, float depth) {
+    vec4 position = light_buffer.inverse_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// --- smooth cut off at range, from Karis, "Real Shading in Unreal Engine 4"
+float distance_attenuation(float distance, float range) {
+    float ratio = distance / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 1e-4);
+}
+
+vec3 fresnel_schlick(vec3 f0, float v_dot_h) {
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+float ggx_distribution(float n_dot_h, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// --- height correlated Smith visibility, already divided by 4 n.l n.v
+float smith_visibility(float n_dot_v, float n_dot_l, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    float ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(ggx_v + ggx_l, 1e-5);
+}
+
+// --- reflected radiance for illuminance arriving from direction l (towards the light)
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 albedo, vec3 f0, float roughness) {
+    vec3 h = normalize(v + l);
+    float n_dot_v = max(dot(n, v), 1e-4);
+    float n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+    float n_dot_h = clamp(dot(n, h), 0.0, 1.0);
+    float alpha = max(roughness * roughness, 0.002);
+    vec3 fresnel = fresnel_schlick(f0, clamp(dot(v, h), 0.0, 1.0));
+    vec3 specular = fresnel * ggx_distribution(n_dot_h, alpha) * smith_visibility(n_dot_v, n_dot_l, alpha);
+    vec3 diffuse = (1.0 - fresnel) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// --- all positions and directions in view space
+vec3 shade(Light light, vec3 position, vec3 n, vec3 v, vec3 albedo, vec3 f0, float roughness) {
+    float type = light.position_and_type.w;
+    vec3 color = light.color_and_intensity.rgb * light.color_and_intensity.w;
+    vec3 light_position = (light_buffer.view * vec4(light.position_and_type.xyz, 1.0)).xyz;
+    vec3 direction = mat3(light_buffer.view) * light.direction_and_range.xyz;
+    float range = abs(light.direction_and_range.w);
+
+    if (type == LIGHT_TYPE_DIRECTIONAL) {
+        return brdf(n, v, -direction, albedo, f0, roughness) * color;
+    }
+
+    if (type == LIGHT_TYPE_AREA) {
+        // --- representative point: the closest point of the rectangle stands in for all of it,
+        // --- with the rectangle's area and facing as its intensity
+        vec3 axis_x = mat3(light_buffer.view) * light.axis_x_and_cos_inner.xyz;
+        vec3 axis_y = mat3(light_buffer.view) * light.axis_y_and_cos_outer.xyz;
+        vec3 offset = position - light_position;
+        float x = clamp(dot(offset, axis_x) / dot(axis_x, axis_x), -1.0, 1.0);
+        float y = clamp(dot(offset, axis_y) / dot(axis_y, axis_y), -1.0, 1.0);
+        vec3 closest = light_position + axis_x * x + axis_y * y;
+        vec3 to_light = closest - position;
+        float distance = length(to_light);
+        vec3 l = to_light / max(distance, 1e-4);
+        float facing = dot(-l, direction);
+        facing = light.direction_and_range.w < 0.0 ? abs(facing) : max(facing, 0.0);
+        float area = 4.0 * length(axis_x) * length(axis_y);
+        return brdf(n, v, l, albedo, f0, roughness) * color * area * facing * distance_attenuation(distance, range);
+    }
+
+    vec3 to_light = light_position - position;
+    float distance = length(to_light);
+    vec3 l = to_light / max(distance, 1e-4);
+    float attenuation = distance_attenuation(distance, range);
+    if (type == LIGHT_TYPE_SPOT) {
+        float cos_inner = light.axis_x_and_cos_inner.w;
+        float cos_outer = light.axis_y_and_cos_outer.w;
+        float cone = clamp((dot(-l, direction) - cos_outer) / max(cos_inner - cos_outer, 1e-4), 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    return brdf(n, v, l, albedo, f0, roughness) * color * attenuation;
+}
+
 void main() {
     vec4 gbuffer0 = texture(i_normal_roughness_id, i_uv);
-    vec3 normal_vs = octahedron_decoding(gbuffer0.xy);
+    vec3 normal_vs = octahedron_decoding(gbuffer0.xy * 2.0 - 1.0);
 
 	vec4 gbuffer1 = texture(i_albedo_data, i_uv);
 	vec4 gbuffer2 = texture(i_reflectance_ao, i_uv);
@@ -50,7 +161,21 @@ void main() {
 
     vec4 final_color = vec4(0.0);
     if (depth.r < 1.0) {
-        final_color = vec4(gbuffer2.xyz, 1.0);
+        vec3 albedo = gbuffer1.rgb;
+        float material_type = round(gbuffer1.a * 255.0);
+        float roughness = gbuffer0.z;
+        vec3 emissive = gbuffer3.rgb;
+
+        vec3 lit = vec3(0.0);
+        if (material_type != MATERIAL_TYPE_PURE_EMISSIVE) {
+            vec3 position = view_position(i_uv, depth.r);
+            vec3 v = normalize(-position);
+            lit = light_buffer.ambient_and_exposure.rgb * albedo;
+            for (uint i = 0; i < light_buffer.count.x; i++) {
+                lit += shade(light_buffer.lights[i], position, normal_vs, v, albedo, reflectance, roughness);
+            }
+        }
+        final_color = vec4(emissive + lit * light_buffer.ambient_and_exposure.w, 1.0);
     } else {
         vec4 color0 = vec4(0.996, 0.349, 0.341, 1.0) * 0.3;
         vec4 color1 = vec4(0.984, 0.16, 0.337, 1.0) * 0.1;
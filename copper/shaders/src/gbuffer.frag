@@ -39,7 +39,8 @@ void main() {
     vec3 emissive_color = PBRInstanceData.type_and_emissive.yzw;
 
     vec2 encoded_normal_vs = octahedron_encoding(i_normal_vs.xyz);
-    o_normal_roughness_id = vec4(encoded_normal_vs, roughness, 1.0);
+    // --- the render target is unsigned normalized, so the -1..1 encoding is stored as 0..1
+    o_normal_roughness_id = vec4(encoded_normal_vs * 0.5 + 0.5, roughness, 1.0);
 
     int counter = 8;
     int shift = int(i_position_ws.y * 64.0) & 15;
@@ -47,7 +48,7 @@ void main() {
     vec4 color0 = vec4(0.235, 0.258, 0.258, 1.0);
     vec4 color1 = vec4(0.984, 0.16, 0.337, 1.0);
     o_albedo_data = vec4(mix(color0, color1, float(on)));
-    o_albedo_data = vec4(albedo, material_type);
+    o_albedo_data = vec4(albedo, material_type / 255.0);
 
     o_reflectance_ao = vec4(reflectance, 1.0);
 
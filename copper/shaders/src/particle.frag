@@ -0,0 +1,35 @@
+#version 450
+
+layout (location = 0) in vec4 i_color;
+layout (location = 1) in vec3 i_normal_vs;
+
+layout (location = 0) out vec4 o_normal_roughness_id;
+layout (location = 1) out vec4 o_albedo_data;
+layout (location = 2) out vec4 o_reflectance_ao;
+layout (location = 3) out vec4 o_lighting;
+
+// --- matches components::MaterialType, see deferred.frag
+const float MATERIAL_TYPE_PURE_EMISSIVE = 2.0;
+
+// --- http://jcgt.org/published/0003/02/01/paper.pdf
+// --- Octahedron Vector Encoding
+vec2 sign_not_zero(vec2 v) {
+    return vec2(
+        v.x >= 0.0 ? 1.0 : -1.0,
+        v.y >= 0.0 ? 1.0 : -1.0
+    );
+}
+
+vec2 octahedron_encoding(vec3 v) {
+    vec2 p = v.xy / (abs(v.x) + abs(v.y) + abs(v.z));
+    return (v.z <= 0.0) ? (1.0 - abs(p.yx)) * sign_not_zero(p) : p;
+}
+
+void main() {
+    // --- particles glow in their own color and are left out of the lighting
+    vec2 encoded_normal_vs = octahedron_encoding(normalize(i_normal_vs));
+    o_normal_roughness_id = vec4(encoded_normal_vs * 0.5 + 0.5, 1.0, 1.0);
+    o_albedo_data = vec4(0.0, 0.0, 0.0, MATERIAL_TYPE_PURE_EMISSIVE / 255.0);
+    o_reflectance_ao = vec4(0.0, 0.0, 0.0, 1.0);
+    o_lighting = vec4(i_color.rgb, 1.0);
+}
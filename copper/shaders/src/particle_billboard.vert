@@ -0,0 +1,28 @@
+#version 450
+
+// --- the quad mesh, spanning 0..1 in x and y
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec3 color;
+
+// --- per instance, matches particles::ParticleInstance
+layout (location = 3) in vec4 i_position_size;
+layout (location = 4) in vec4 i_color_life;
+
+layout (set = 0, binding = 0) uniform UBView
+{
+    mat4 projection;
+    mat4 view;
+} ViewData;
+
+layout (location = 0) out vec4 o_color;
+layout (location = 1) out vec3 o_normal_vs;
+
+void main() {
+    // --- centered on the particle and expanded in view space, so it always faces the camera
+    vec4 center_vs = ViewData.view * vec4(i_position_size.xyz, 1.0);
+    vec2 corner = (position.xy - 0.5) * i_position_size.w;
+    o_color = i_color_life;
+    o_normal_vs = normalize(-center_vs.xyz);
+    gl_Position = ViewData.projection * (center_vs + vec4(corner, 0.0, 0.0));
+}
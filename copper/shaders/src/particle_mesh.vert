@@ -0,0 +1,25 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec3 color;
+
+// --- per instance, matches particles::ParticleInstance
+layout (location = 3) in vec4 i_position_size;
+layout (location = 4) in vec4 i_color_life;
+
+layout (set = 0, binding = 0) uniform UBView
+{
+    mat4 projection;
+    mat4 view;
+} ViewData;
+
+layout (location = 0) out vec4 o_color;
+layout (location = 1) out vec3 o_normal_vs;
+
+void main() {
+    vec3 position_ws = i_position_size.xyz + position.xyz * i_position_size.w;
+    o_color = i_color_life;
+    o_normal_vs = (ViewData.view * vec4(normal.xyz, 0.0)).xyz;
+    gl_Position = ViewData.projection * ViewData.view * vec4(position_ws, 1.0);
+}
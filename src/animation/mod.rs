@@ -0,0 +1,284 @@
+use crate::components;
+use crate::schedule;
+use crate::world::World;
+
+pub mod track;
+
+pub use track::{Animatable, Interpolation, Track};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum WrapMode {
+    Loop,
+    // --- plays forward, then backward, and so on
+    PingPong,
+    // --- stops on the last key
+    Clamp,
+}
+
+// --- keyframe tracks sampled on the fixed step; tracks that are None leave their target
+// --- alone, so an animation can drive only the rotation and physics the rest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub position: Option<Track<cgmath::Vector3<f32>>>,
+    pub rotation: Option<Track<cgmath::Quaternion<f32>>>,
+    pub scale: Option<Track<cgmath::Vector3<f32>>>,
+    // --- written to PBRMaterial when the entity has one
+    pub emissive_color: Option<Track<cgmath::Vector3<f32>>>,
+    pub wrap: WrapMode,
+    pub speed: f32,
+    pub playing: bool,
+    // --- playback position in seconds, kept within one period of the wrap mode
+    pub time: f32,
+}
+
+impl Animation {
+    pub fn new(wrap: WrapMode) -> Animation {
+        Animation {
+            position: None,
+            rotation: None,
+            scale: None,
+            emissive_color: None,
+            wrap: wrap,
+            speed: 1.0,
+            playing: true,
+            time: 0.0,
+        }
+    }
+
+    pub fn position(mut self, track: Track<cgmath::Vector3<f32>>) -> Animation {
+        self.position = Some(track);
+        self
+    }
+
+    pub fn rotation(mut self, track: Track<cgmath::Quaternion<f32>>) -> Animation {
+        self.rotation = Some(track);
+        self
+    }
+
+    pub fn scale(mut self, track: Track<cgmath::Vector3<f32>>) -> Animation {
+        self.scale = Some(track);
+        self
+    }
+
+    pub fn emissive_color(mut self, track: Track<cgmath::Vector3<f32>>) -> Animation {
+        self.emissive_color = Some(track);
+        self
+    }
+
+    pub fn speed(mut self, speed: f32) -> Animation {
+        self.speed = speed;
+        self
+    }
+
+    // --- the longest track's last key
+    pub fn duration(&self) -> f32 {
+        [
+            self.position.as_ref().map(Track::duration),
+            self.rotation.as_ref().map(Track::duration),
+            self.scale.as_ref().map(Track::duration),
+            self.emissive_color.as_ref().map(Track::duration),
+        ]
+        .iter()
+        .filter_map(|duration| *duration)
+        .fold(0.0, f32::max)
+    }
+
+    // --- moves the playback position by dt (scaled by speed, which may be negative)
+    pub fn advance(&mut self, dt: f32) {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            self.time = 0.0;
+            return;
+        }
+
+        let time = self.time + dt * self.speed;
+        self.time = match self.wrap {
+            WrapMode::Loop => time.rem_euclid(duration),
+            WrapMode::PingPong => time.rem_euclid(2.0 * duration),
+            WrapMode::Clamp => time.max(0.0).min(duration),
+        };
+    }
+
+    // --- the time the tracks are sampled at for the current playback position
+    pub fn sample_time(&self) -> f32 {
+        let duration = self.duration();
+        match self.wrap {
+            WrapMode::PingPong if self.time > duration => 2.0 * duration - self.time,
+            _ => self.time,
+        }
+    }
+
+    pub fn apply(&self, transform: &mut components::Transform) {
+        let time = self.sample_time();
+        if let Some(position) = self.position.as_ref().and_then(|track| track.sample(time)) {
+            transform.position = position;
+        }
+        if let Some(rotation) = self.rotation.as_ref().and_then(|track| track.sample(time)) {
+            transform.rotation = rotation;
+        }
+        if let Some(scale) = self.scale.as_ref().and_then(|track| track.sample(time)) {
+            transform.scale = scale;
+        }
+    }
+}
+
+// --- components that take part in snapshots; call once when setting up a world with animations
+pub fn register_components(world: &mut World) {
+    world.register_component_snapshot::<Animation>();
+}
+
+// --- advances every playing Animation by the fixed step and writes the sampled values
+pub struct AnimationSystem;
+
+impl schedule::System for AnimationSystem {
+    fn name(&self) -> &str {
+        "animation"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .write::<Animation>()
+            .write::<components::Transform>()
+            .write::<components::PBRMaterial>()
+            .read_resource::<components::Time>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let dt = ctx.resource::<components::Time>().fixed_dt;
+        ctx.query::<(&mut Animation, Option<&mut components::Transform>, Option<&mut components::PBRMaterial>)>()
+            .for_each(|(mut animation, transform, pbr_material)| {
+                if !animation.playing {
+                    return;
+                }
+                animation.advance(dt);
+
+                let moves = animation.position.is_some() || animation.rotation.is_some() || animation.scale.is_some();
+                if let (Some(mut transform), true) = (transform, moves) {
+                    animation.apply(&mut *transform);
+                }
+                let time = animation.sample_time();
+                let emissive_color = animation.emissive_color.as_ref().and_then(|track| track.sample(time));
+                if let (Some(mut pbr_material), Some(emissive_color)) = (pbr_material, emissive_color) {
+                    pbr_material.emissive_color = emissive_color;
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+    }
+
+    fn vector(x: f32) -> cgmath::Vector3<f32> {
+        cgmath::Vector3 { x: x, y: 0.0, z: 0.0 }
+    }
+
+    // --- position x runs from 0 to 2 over two seconds
+    fn animation(wrap: WrapMode, speed: f32) -> Animation {
+        Animation::new(wrap)
+            .position(Track::new(Interpolation::Linear).key(0.0, vector(0.0)).key(2.0, vector(2.0)))
+            .speed(speed)
+    }
+
+    fn advance(animation: &mut Animation, steps: usize) {
+        for _ in 0..steps {
+            animation.advance(0.5);
+        }
+    }
+
+    #[test]
+    fn loops_wrap_around_in_both_directions() {
+        let mut forward = animation(WrapMode::Loop, 1.0);
+        advance(&mut forward, 5);
+        assert_close(forward.time, 0.5);
+        assert_close(forward.sample_time(), 0.5);
+
+        let mut backward = animation(WrapMode::Loop, -1.0);
+        advance(&mut backward, 1);
+        assert_close(backward.time, 1.5);
+        advance(&mut backward, 4);
+        assert_close(backward.time, 1.5);
+    }
+
+    #[test]
+    fn ping_pong_plays_back_and_forth() {
+        let mut forward = animation(WrapMode::PingPong, 2.0);
+        advance(&mut forward, 2);
+        assert_close(forward.sample_time(), 2.0);
+        advance(&mut forward, 1);
+        assert_close(forward.time, 3.0);
+        assert_close(forward.sample_time(), 1.0);
+        advance(&mut forward, 2);
+        assert_close(forward.time, 1.0);
+        assert_close(forward.sample_time(), 1.0);
+
+        // --- going backward from the start first plays the return half
+        let mut backward = animation(WrapMode::PingPong, -1.0);
+        advance(&mut backward, 1);
+        assert_close(backward.time, 3.5);
+        assert_close(backward.sample_time(), 0.5);
+    }
+
+    #[test]
+    fn clamp_stops_at_either_end() {
+        let mut forward = animation(WrapMode::Clamp, 1.0);
+        advance(&mut forward, 10);
+        assert_close(forward.time, 2.0);
+        assert_close(forward.sample_time(), 2.0);
+
+        let mut backward = animation(WrapMode::Clamp, -3.0);
+        backward.time = 1.0;
+        advance(&mut backward, 1);
+        assert_close(backward.time, 0.0);
+
+        // --- without keys there is nothing to play
+        let mut empty = Animation::new(WrapMode::Loop);
+        empty.time = 1.0;
+        empty.advance(0.5);
+        assert_close(empty.time, 0.0);
+    }
+
+    #[test]
+    fn the_system_writes_transforms_and_emissive_colors() {
+        let mut world = World::new();
+        world.insert_resource(components::Time {
+            fixed_dt: 0.5,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
+        let transform = components::Transform {
+            position: cgmath::Vector3 { x: 0.0, y: 7.0, z: 0.0 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        };
+        let glow = Track::new(Interpolation::Step).key(0.0, vector(0.0)).key(0.5, vector(4.0));
+
+        let moving = world.create_entity().with(animation(WrapMode::Clamp, 1.0)).with(transform).build();
+        let mut stopped = animation(WrapMode::Clamp, 1.0);
+        stopped.playing = false;
+        let paused = world.create_entity().with(stopped).with(transform).build();
+        // --- an animation of the emissive color alone leaves the transform to others
+        let glowing = world
+            .create_entity()
+            .with(Animation::new(WrapMode::Clamp).emissive_color(glow))
+            .with(crate::material::Materials::Plastic.get())
+            .with(components::Transform { position: vector(9.0), ..transform })
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, AnimationSystem);
+        scheduler.run_stage(schedule::FIXED_UPDATE, &mut world);
+
+        let position = world.get::<components::Transform>(moving).unwrap().position;
+        assert_close(position.x, 0.5);
+        assert_close(position.y, 0.0);
+        assert_eq!(world.get::<components::Transform>(paused).unwrap().position, transform.position);
+        assert_eq!(world.get::<Animation>(paused).unwrap().time, 0.0);
+        assert_eq!(world.get::<components::PBRMaterial>(glowing).unwrap().emissive_color, vector(4.0));
+        assert_eq!(world.get::<components::Transform>(glowing).unwrap().position, vector(9.0));
+    }
+}
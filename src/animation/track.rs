@@ -0,0 +1,256 @@
+use cgmath::{InnerSpace, VectorSpace, Zero};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Interpolation {
+    // --- holds each key's value until the next key
+    Step,
+    Linear,
+    // --- uses the keys' tangents, like glTF's CUBICSPLINE
+    CubicHermite,
+}
+
+// --- values a track can hold; rotations blend on the shorter arc and stay unit length
+pub trait Animatable: Copy {
+    fn zero() -> Self;
+    fn scale(value: Self, factor: f32) -> Self;
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    // --- Hermite basis over one segment; tangents are already scaled to the segment length
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32) -> Self;
+}
+
+fn hermite_weights(t: f32) -> (f32, f32, f32, f32) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, -2.0 * t3 + 3.0 * t2, t3 - t2)
+}
+
+impl Animatable for f32 {
+    fn zero() -> f32 {
+        0.0
+    }
+
+    fn scale(value: f32, factor: f32) -> f32 {
+        value * factor
+    }
+
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+
+    fn hermite(p0: f32, m0: f32, p1: f32, m1: f32, t: f32) -> f32 {
+        let (h00, h10, h01, h11) = hermite_weights(t);
+        p0 * h00 + m0 * h10 + p1 * h01 + m1 * h11
+    }
+}
+
+impl Animatable for cgmath::Vector3<f32> {
+    fn zero() -> cgmath::Vector3<f32> {
+        <cgmath::Vector3<f32> as Zero>::zero()
+    }
+
+    fn scale(value: cgmath::Vector3<f32>, factor: f32) -> cgmath::Vector3<f32> {
+        value * factor
+    }
+
+    fn lerp(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>, t: f32) -> cgmath::Vector3<f32> {
+        a.lerp(b, t)
+    }
+
+    fn hermite(
+        p0: cgmath::Vector3<f32>,
+        m0: cgmath::Vector3<f32>,
+        p1: cgmath::Vector3<f32>,
+        m1: cgmath::Vector3<f32>,
+        t: f32,
+    ) -> cgmath::Vector3<f32> {
+        let (h00, h10, h01, h11) = hermite_weights(t);
+        p0 * h00 + m0 * h10 + p1 * h01 + m1 * h11
+    }
+}
+
+impl Animatable for cgmath::Quaternion<f32> {
+    fn zero() -> cgmath::Quaternion<f32> {
+        <cgmath::Quaternion<f32> as Zero>::zero()
+    }
+
+    fn scale(value: cgmath::Quaternion<f32>, factor: f32) -> cgmath::Quaternion<f32> {
+        value * factor
+    }
+
+    fn lerp(a: cgmath::Quaternion<f32>, b: cgmath::Quaternion<f32>, t: f32) -> cgmath::Quaternion<f32> {
+        let b = if a.dot(b) < 0.0 { -b } else { b };
+        a.slerp(b, t).normalize()
+    }
+
+    fn hermite(
+        p0: cgmath::Quaternion<f32>,
+        m0: cgmath::Quaternion<f32>,
+        p1: cgmath::Quaternion<f32>,
+        m1: cgmath::Quaternion<f32>,
+        t: f32,
+    ) -> cgmath::Quaternion<f32> {
+        let (h00, h10, h01, h11) = hermite_weights(t);
+        (p0 * h00 + m0 * h10 + p1 * h01 + m1 * h11).normalize()
+    }
+}
+
+// --- tangents are rates per second and only matter for CubicHermite
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    pub in_tangent: T,
+    pub out_tangent: T,
+}
+
+// --- keyframes are kept sorted by time; a key at an existing time replaces it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track<T> {
+    pub interpolation: Interpolation,
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Track<T> {
+        Track {
+            interpolation: interpolation,
+            keyframes: vec![],
+        }
+    }
+
+    // --- flat tangents, so cubic tracks ease in and out of every key
+    pub fn key(self, time: f32, value: T) -> Track<T> {
+        self.hermite_key(time, value, T::zero(), T::zero())
+    }
+
+    pub fn hermite_key(mut self, time: f32, value: T, in_tangent: T, out_tangent: T) -> Track<T> {
+        let keyframe = Keyframe {
+            time: time,
+            value: value,
+            in_tangent: in_tangent,
+            out_tangent: out_tangent,
+        };
+        match self.keyframes.iter().position(|existing| existing.time >= time) {
+            Some(index) if self.keyframes[index].time == time => self.keyframes[index] = keyframe,
+            Some(index) => self.keyframes.insert(index, keyframe),
+            None => self.keyframes.push(keyframe),
+        }
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    // --- time of the last key
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // --- times outside the keys hold the first or last value; None for an empty track
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first = self.keyframes.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        let next = match self.keyframes.iter().position(|keyframe| keyframe.time > time) {
+            Some(next) => next,
+            None => return self.keyframes.last().map(|keyframe| keyframe.value),
+        };
+
+        let from = &self.keyframes[next - 1];
+        let to = &self.keyframes[next];
+        let length = to.time - from.time;
+        let t = (time - from.time) / length;
+        Some(match self.interpolation {
+            Interpolation::Step => from.value,
+            Interpolation::Linear => T::lerp(from.value, to.value, t),
+            Interpolation::CubicHermite => T::hermite(
+                from.value,
+                T::scale(from.out_tangent, length),
+                to.value,
+                T::scale(to.in_tangent, length),
+                t,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Rotation3;
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("empty track");
+        assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+    }
+
+    fn ramp(interpolation: Interpolation) -> Track<f32> {
+        Track::new(interpolation).key(2.0, 5.0).key(0.0, 1.0).key(1.0, 3.0)
+    }
+
+    #[test]
+    fn step_tracks_hold_each_key() {
+        let track = ramp(Interpolation::Step);
+        assert_close(track.sample(-1.0), 1.0);
+        assert_close(track.sample(0.0), 1.0);
+        assert_close(track.sample(0.99), 1.0);
+        assert_close(track.sample(1.0), 3.0);
+        assert_close(track.sample(1.5), 3.0);
+        assert_close(track.sample(2.0), 5.0);
+        assert_close(track.sample(10.0), 5.0);
+        assert_eq!(Track::<f32>::new(Interpolation::Step).sample(0.0), None);
+    }
+
+    #[test]
+    fn linear_tracks_blend_between_sorted_keys() {
+        let track = ramp(Interpolation::Linear);
+        let times: Vec<f32> = track.keyframes().iter().map(|keyframe| keyframe.time).collect();
+        assert_eq!(times, vec![0.0, 1.0, 2.0]);
+        assert_eq!(track.duration(), 2.0);
+        assert_close(track.sample(-0.5), 1.0);
+        assert_close(track.sample(0.25), 1.5);
+        assert_close(track.sample(1.5), 4.0);
+        assert_close(track.sample(2.5), 5.0);
+
+        // --- a key at an existing time replaces the old one
+        let track = track.key(1.0, 11.0);
+        assert_eq!(track.keyframes().len(), 3);
+        assert_close(track.sample(1.0), 11.0);
+        assert_close(track.sample(0.5), 6.0);
+
+        // --- rotations take the shorter arc even when the keys have opposite signs
+        let rotation = Track::new(Interpolation::Linear)
+            .key(0.0, cgmath::Quaternion::from_angle_z(cgmath::Rad(0.0)))
+            .key(1.0, -cgmath::Quaternion::from_angle_z(cgmath::Rad(0.2)));
+        let halfway = rotation.sample(0.5).unwrap();
+        let expected = cgmath::Quaternion::from_angle_z(cgmath::Rad(0.1));
+        assert!(halfway.dot(expected).abs() > 1.0 - 1e-5, "{:?}", halfway);
+        assert!((halfway.magnitude() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cubic_tracks_use_tangents_per_second() {
+        // --- flat tangents ease in and out
+        let track = Track::new(Interpolation::CubicHermite).key(0.0, 0.0).key(2.0, 1.0);
+        assert_close(track.sample(1.0), 0.5);
+        assert_close(track.sample(0.5), 0.15625);
+        assert_close(track.sample(-1.0), 0.0);
+        assert_close(track.sample(3.0), 1.0);
+
+        // --- tangents matching the slope give a straight line whatever the segment length
+        let track = Track::new(Interpolation::CubicHermite)
+            .hermite_key(0.0, 0.0, 0.0, 1.0)
+            .hermite_key(2.0, 2.0, 1.0, 1.0)
+            .hermite_key(2.5, 2.5, 1.0, 0.0);
+        assert_close(track.sample(0.5), 0.5);
+        assert_close(track.sample(1.7), 1.7);
+        assert_close(track.sample(2.25), 2.25);
+
+        // --- replacing a key keeps its new tangents
+        let track = track.hermite_key(2.0, 2.0, 0.0, 0.0);
+        assert_eq!(track.keyframes()[1].out_tangent, 0.0);
+        assert!(track.sample(1.0).unwrap() > 1.0);
+    }
+}
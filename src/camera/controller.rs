@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Rotation, Rotation3};
+use winit::{MouseButton, VirtualKeyCode};
+
+use crate::components;
+use crate::input::{ActionState, AxisBinding, Button, InputMap};
+use crate::schedule;
+
+// --- keeps cameras from looking straight along up, where yaw is undefined
+const MAX_PITCH_COS: f32 = 0.99;
+
+// --- actions and axes the controllers read from the ActionState
+pub const MOVE_FORWARD: &str = "move_forward";
+pub const MOVE_RIGHT: &str = "move_right";
+// --- positive moves up on screen
+pub const MOVE_UP: &str = "move_up";
+pub const MOVE_FAST: &str = "move_fast";
+pub const LOOK_X: &str = "look_x";
+pub const LOOK_Y: &str = "look_y";
+// --- held to turn a fly camera that has hold_to_look
+pub const LOOK: &str = "look";
+// --- held to turn an orbit camera that has hold_to_rotate
+pub const ORBIT: &str = "orbit";
+pub const ZOOM: &str = "zoom";
+
+// --- WASD moves along the view, Space and LControl move up and down on screen, LShift
+// --- speeds up, dragging with the right button looks around and the left one orbits
+pub fn bindings() -> InputMap {
+    InputMap::new()
+        .axis(MOVE_FORWARD, AxisBinding::buttons(Button::Key(VirtualKeyCode::W), Button::Key(VirtualKeyCode::S)))
+        .axis(MOVE_RIGHT, AxisBinding::buttons(Button::Key(VirtualKeyCode::D), Button::Key(VirtualKeyCode::A)))
+        .axis(MOVE_UP, AxisBinding::buttons(Button::Key(VirtualKeyCode::Space), Button::Key(VirtualKeyCode::LControl)))
+        .action(MOVE_FAST, Button::Key(VirtualKeyCode::LShift))
+        .axis(LOOK_X, AxisBinding::MouseX { scale: 1.0 })
+        .axis(LOOK_Y, AxisBinding::MouseY { scale: 1.0 })
+        .action(LOOK, Button::Mouse(MouseButton::Right))
+        .action(ORBIT, Button::Mouse(MouseButton::Left))
+        .axis(ZOOM, AxisBinding::Scroll { scale: 1.0 })
+}
+
+// --- moves along the MOVE_* axes and turns along the LOOK_* axes, while LOOK is held when
+// --- hold_to_look is set
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct FlyController {
+    // --- world units per second
+    pub move_speed: f32,
+    // --- applied while MOVE_FAST is held
+    pub fast_multiplier: f32,
+    // --- radians per unit of the look axes
+    pub look_sensitivity: f32,
+    pub hold_to_look: bool,
+    // --- the camera's up for look_at; the projection draws it downwards on screen
+    pub up: cgmath::Vector3<f32>,
+}
+
+impl Default for FlyController {
+    fn default() -> FlyController {
+        FlyController {
+            move_speed: 5.0,
+            fast_multiplier: 4.0,
+            look_sensitivity: 0.003,
+            hold_to_look: true,
+            up: cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        }
+    }
+}
+
+// --- circles the target entity (or focus, without one) at distance; the LOOK_* axes turn
+// --- around it, while ORBIT is held when hold_to_rotate is set, and ZOOM moves closer
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct OrbitController {
+    pub target: Option<components::Entity>,
+    // --- the point orbited, follows the target while it has a Transform
+    pub focus: cgmath::Vector3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // --- radians per unit of the look axes
+    pub rotate_sensitivity: f32,
+    // --- fraction of the distance per unit of the zoom axis
+    pub zoom_speed: f32,
+    pub hold_to_rotate: bool,
+    pub up: cgmath::Vector3<f32>,
+}
+
+impl OrbitController {
+    pub fn new(target: Option<components::Entity>, distance: f32) -> OrbitController {
+        OrbitController {
+            target: target,
+            focus: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            distance: distance,
+            min_distance: 0.5,
+            max_distance: 100.0,
+            rotate_sensitivity: 0.005,
+            zoom_speed: 0.1,
+            hold_to_rotate: true,
+            up: cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        }
+    }
+}
+
+fn look_delta(actions: &ActionState, hold: Option<&str>) -> cgmath::Vector2<f32> {
+    match hold {
+        Some(hold) if !actions.held(hold) => cgmath::Vector2 { x: 0.0, y: 0.0 },
+        _ => cgmath::Vector2 { x: actions.axis(LOOK_X), y: actions.axis(LOOK_Y) },
+    }
+}
+
+// --- turns direction by yaw about up and pitch about its side axis. Moving the mouse right
+// --- turns right and, with +y down on screen, moving it down looks down
+fn turn(direction: cgmath::Vector3<f32>, up: cgmath::Vector3<f32>, yaw: f32, pitch: f32) -> cgmath::Vector3<f32> {
+    let yawed = cgmath::Quaternion::from_axis_angle(up, cgmath::Rad(-yaw)).rotate_vector(direction);
+    let side = yawed.cross(up);
+    if side.magnitude2() < 1e-12 {
+        return yawed;
+    }
+    let pitched = cgmath::Quaternion::from_axis_angle(side.normalize(), cgmath::Rad(pitch)).rotate_vector(yawed);
+    if pitched.normalize().dot(up).abs() > MAX_PITCH_COS {
+        yawed
+    } else {
+        pitched
+    }
+}
+
+// --- the camera's transform after this frame's input, None when the input leaves it alone
+pub fn fly(controller: &FlyController, transform: &components::Transform, actions: &ActionState, dt: f32) -> Option<components::Transform> {
+    let up = controller.up.normalize();
+    let hold = if controller.hold_to_look { Some(LOOK) } else { None };
+    let delta = look_delta(actions, hold) * controller.look_sensitivity;
+    let forward = transform.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 });
+    let forward = turn(forward, up, delta.x, delta.y).normalize();
+    let right = forward.cross(up).normalize();
+
+    let direction = forward * actions.axis(MOVE_FORWARD) + right * actions.axis(MOVE_RIGHT) - up * actions.axis(MOVE_UP);
+    if delta.x == 0.0 && delta.y == 0.0 && direction.magnitude2() == 0.0 {
+        return None;
+    }
+
+    let mut transform = *transform;
+    if direction.magnitude2() > 0.0 {
+        let speed = if actions.held(MOVE_FAST) {
+            controller.move_speed * controller.fast_multiplier
+        } else {
+            controller.move_speed
+        };
+        // --- partly pushed axes move slower, several at once no faster than one
+        let direction = if direction.magnitude2() > 1.0 { direction.normalize() } else { direction };
+        transform.position += direction * speed * dt;
+    }
+    let target = transform.position + forward;
+    transform.look_at(target, up);
+    Some(transform)
+}
+
+// --- the camera's transform after this frame's input, None while it is on the orbit and the
+// --- input leaves it alone; a camera off the orbit, or whose focus moved, is put back on it
+pub fn orbit(controller: &mut OrbitController, transform: &components::Transform, actions: &ActionState) -> Option<components::Transform> {
+    let up = controller.up.normalize();
+    let distance = (controller.distance * (1.0 - controller.zoom_speed * actions.axis(ZOOM)))
+        .max(controller.min_distance)
+        .min(controller.max_distance);
+    let hold = if controller.hold_to_rotate { Some(ORBIT) } else { None };
+    let delta = look_delta(actions, hold) * controller.rotate_sensitivity;
+
+    let offset = transform.position - controller.focus;
+    let facing = transform.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 });
+    let on_orbit = offset.magnitude2() > 1e-12
+        && (offset.magnitude() - distance).abs() < 1e-4
+        && facing.dot(-offset.normalize()) > 0.9999;
+    if on_orbit && delta.x == 0.0 && delta.y == 0.0 && distance == controller.distance {
+        return None;
+    }
+
+    // --- turning the view direction the same way as the fly camera moves the camera the
+    // --- opposite way around the focus, which is what dragging the scene feels like
+    let forward = if offset.magnitude2() > 1e-12 { -offset.normalize() } else { facing };
+    let forward = turn(forward, up, delta.x, delta.y).normalize();
+    controller.distance = distance;
+    let mut transform = *transform;
+    transform.position = controller.focus - forward * distance;
+    transform.look_at(controller.focus, up);
+    Some(transform)
+}
+
+// --- runs once per frame before transform interpolation, with the frame's ActionState. Moved
+// --- cameras get their PreviousTransform reset, so they are drawn where the input put them
+pub struct CameraControllerSystem;
+
+impl schedule::System for CameraControllerSystem {
+    fn name(&self) -> &str {
+        "camera_controller"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<FlyController>()
+            .write::<OrbitController>()
+            .write::<components::Transform>()
+            .write::<components::PreviousTransform>()
+            .read_resource::<ActionState>()
+            .read_resource::<components::Time>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let actions = ctx.resource::<ActionState>().clone();
+        let (dt, alpha) = {
+            let time = ctx.resource::<components::Time>();
+            // --- a long hitch should not throw the camera across the scene
+            (time.frame_time.min(0.1), time.alpha())
+        };
+
+        // --- targets are followed where they are drawn this frame
+        let targets: Vec<components::Entity> = ctx
+            .query::<&OrbitController>()
+            .filter_map(|controller| controller.target)
+            .collect();
+        let focuses: HashMap<components::Entity, cgmath::Vector3<f32>> = ctx
+            .query::<(components::Entity, &components::Transform, Option<&components::PreviousTransform>)>()
+            .filter(|(entity, _, _)| targets.contains(entity))
+            .map(|(entity, transform, previous)| {
+                let position = match previous {
+                    Some(previous) => previous.transform.slerp(transform, alpha).position,
+                    None => transform.position,
+                };
+                (entity, position)
+            })
+            .collect();
+
+        ctx.query::<(&FlyController, &mut components::Transform, Option<&mut components::PreviousTransform>)>()
+            .for_each(|(controller, mut transform, previous)| {
+                if let Some(moved) = fly(controller, &*transform, &actions, dt) {
+                    *transform = moved;
+                    if let Some(mut previous) = previous {
+                        previous.transform = moved;
+                    }
+                }
+            });
+
+        ctx.query::<(&mut OrbitController, &mut components::Transform, Option<&mut components::PreviousTransform>)>()
+            .for_each(|(mut controller, mut transform, previous)| {
+                if let Some(focus) = controller.target.and_then(|target| focuses.get(&target)) {
+                    controller.focus = *focus;
+                }
+                if let Some(moved) = orbit(&mut *controller, &*transform, &actions) {
+                    *transform = moved;
+                    if let Some(mut previous) = previous {
+                        previous.transform = moved;
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::world::World;
+    use winit::ElementState;
+
+    fn actions(keys: &[VirtualKeyCode], buttons: &[MouseButton], mouse: (f32, f32), scroll: f32) -> ActionState {
+        let mut input = Input::new();
+        for key in keys.iter() {
+            input.set_key(*key, ElementState::Pressed);
+        }
+        for button in buttons.iter() {
+            input.set_button(*button, ElementState::Pressed);
+        }
+        input.mouse_delta = cgmath::Vector2 { x: mouse.0, y: mouse.1 };
+        input.scroll_delta = scroll;
+        let mut actions = ActionState::new();
+        actions.update(&bindings(), &input);
+        actions
+    }
+
+    fn keys(keys: &[VirtualKeyCode]) -> ActionState {
+        actions(keys, &[], (0.0, 0.0), 0.0)
+    }
+
+    fn at(x: f32, y: f32, z: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: y, z: z },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn forward(transform: &components::Transform) -> cgmath::Vector3<f32> {
+        transform.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 })
+    }
+
+    fn assert_vector(actual: cgmath::Vector3<f32>, x: f32, y: f32, z: f32) {
+        let expected = cgmath::Vector3 { x: x, y: y, z: z };
+        assert!((actual - expected).magnitude() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn fly_cameras_move_along_the_view() {
+        let controller = FlyController::default();
+        let camera = at(0.0, 0.0, 0.0);
+        let moved = |pressed: &[VirtualKeyCode]| fly(&controller, &camera, &keys(pressed), 0.5).unwrap().position;
+
+        assert_vector(moved(&[VirtualKeyCode::W]), 0.0, 0.0, -2.5);
+        assert_vector(moved(&[VirtualKeyCode::S]), 0.0, 0.0, 2.5);
+        assert_vector(moved(&[VirtualKeyCode::D]), 2.5, 0.0, 0.0);
+        assert_vector(moved(&[VirtualKeyCode::A]), -2.5, 0.0, 0.0);
+        // --- up is drawn downwards, so moving up on screen moves against it
+        assert_vector(moved(&[VirtualKeyCode::Space]), 0.0, -2.5, 0.0);
+        assert_vector(moved(&[VirtualKeyCode::W, VirtualKeyCode::LShift]), 0.0, 0.0, -10.0);
+
+        // --- diagonals are no faster than one axis
+        let diagonal = 2.5 / 2.0f32.sqrt();
+        assert_vector(moved(&[VirtualKeyCode::W, VirtualKeyCode::D]), diagonal, 0.0, -diagonal);
+        let all = moved(&[VirtualKeyCode::W, VirtualKeyCode::D, VirtualKeyCode::Space]);
+        assert!((all.magnitude() - 2.5).abs() < 1e-4);
+
+        let moved = fly(&controller, &camera, &keys(&[VirtualKeyCode::W]), 0.5).unwrap();
+        assert_vector(forward(&moved), 0.0, 0.0, -1.0);
+        assert!(fly(&controller, &camera, &keys(&[]), 0.5).is_none());
+    }
+
+    #[test]
+    fn fly_cameras_look_while_held_and_stop_short_of_up() {
+        let controller = FlyController::default();
+        let camera = at(0.0, 0.0, 0.0);
+        let yaw = 0.3 / controller.look_sensitivity;
+
+        assert!(fly(&controller, &camera, &actions(&[], &[], (yaw, 0.0), 0.0), 0.1).is_none());
+        let turned = fly(&controller, &camera, &actions(&[], &[MouseButton::Right], (yaw, 0.0), 0.0), 0.1).unwrap();
+        assert_vector(forward(&turned), 0.3f32.sin(), 0.0, -0.3f32.cos());
+        assert_eq!(turned.position, camera.position);
+
+        let free = FlyController { hold_to_look: false, ..controller };
+        let turned = fly(&free, &camera, &actions(&[], &[], (yaw, 0.0), 0.0), 0.1).unwrap();
+        assert_vector(forward(&turned), 0.3f32.sin(), 0.0, -0.3f32.cos());
+
+        let pitched = fly(&free, &camera, &actions(&[], &[], (0.0, yaw), 0.0), 0.1).unwrap();
+        assert!((forward(&pitched).y.abs() - 0.3f32.sin()).abs() < 1e-4);
+        // --- a pitch that would end up within acos(MAX_PITCH_COS) of up is dropped
+        let steep = 1.5 / controller.look_sensitivity;
+        let kept = fly(&free, &camera, &actions(&[], &[], (0.0, steep), 0.0), 0.1).unwrap();
+        assert_vector(forward(&kept), 0.0, 0.0, -1.0);
+        let mut camera = camera;
+        for _ in 0..20 {
+            camera = fly(&free, &camera, &actions(&[], &[], (0.0, yaw), 0.0), 0.1).unwrap();
+            assert!(forward(&camera).y.abs() <= MAX_PITCH_COS + 1e-4);
+        }
+    }
+
+    #[test]
+    fn orbit_cameras_zoom_within_limits_and_rotate_while_held() {
+        let mut controller = OrbitController::new(None, 10.0);
+        let mut camera = at(0.0, 0.0, 10.0);
+        camera.look_at(controller.focus, controller.up);
+        assert!(orbit(&mut controller, &camera, &keys(&[])).is_none());
+
+        let closer = orbit(&mut controller, &camera, &actions(&[], &[], (0.0, 0.0), 1.0)).unwrap();
+        assert_eq!(controller.distance, 9.0);
+        assert_vector(closer.position, 0.0, 0.0, 9.0);
+        orbit(&mut controller, &closer, &actions(&[], &[], (0.0, 0.0), 100.0)).unwrap();
+        assert_eq!(controller.distance, controller.min_distance);
+        let far = orbit(&mut controller, &closer, &actions(&[], &[], (0.0, 0.0), -10000.0)).unwrap();
+        assert_eq!(controller.distance, controller.max_distance);
+        assert!((far.position.magnitude() - controller.max_distance).abs() < 1e-3);
+
+        let mut controller = OrbitController::new(None, 10.0);
+        let drag = 0.3 / controller.rotate_sensitivity;
+        assert!(orbit(&mut controller, &camera, &actions(&[], &[], (drag, 0.0), 0.0)).is_none());
+        let turned = orbit(&mut controller, &camera, &actions(&[], &[MouseButton::Left], (drag, 0.0), 0.0)).unwrap();
+        assert!((turned.position.magnitude() - 10.0).abs() < 1e-4);
+        assert_vector(forward(&turned), -turned.position.x / 10.0, 0.0, -turned.position.z / 10.0);
+        assert!(turned.position.x < 0.0);
+
+        // --- a camera off the orbit is put back on it
+        let mut controller = OrbitController::new(None, 4.0);
+        let placed = orbit(&mut controller, &at(0.0, 0.0, 10.0), &keys(&[])).unwrap();
+        assert_vector(placed.position, 0.0, 0.0, 4.0);
+    }
+
+    #[test]
+    fn orbit_focus_follows_the_drawn_target() {
+        let mut world = World::new();
+        world.insert_resource(components::Time {
+            fixed_dt: 0.1,
+            frame_time: 0.016,
+            accumulator: 0.05,
+        });
+        world.insert_resource(keys(&[]));
+        let target = world
+            .create_entity()
+            .with(at(3.0, 0.0, 0.0))
+            .with(components::PreviousTransform { transform: at(1.0, 0.0, 0.0) })
+            .build();
+        let camera = world
+            .create_entity()
+            .with(OrbitController::new(Some(target), 5.0))
+            .with(at(0.0, 0.0, 5.0))
+            .with(components::PreviousTransform { transform: at(0.0, 0.0, 0.0) })
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::RENDER_PREPARE, CameraControllerSystem);
+        scheduler.run_stage(schedule::RENDER_PREPARE, &mut world);
+
+        // --- halfway between the target's last two ticks
+        let focus = world.get::<OrbitController>(camera).unwrap().focus;
+        assert_vector(focus, 2.0, 0.0, 0.0);
+        let transform = *world.get::<components::Transform>(camera).unwrap();
+        assert!(((transform.position - focus).magnitude() - 5.0).abs() < 1e-4);
+        assert!((forward(&transform) - (focus - transform.position).normalize()).magnitude() < 1e-4);
+        assert_eq!(world.get::<components::PreviousTransform>(camera).unwrap().transform.position, transform.position);
+    }
+}
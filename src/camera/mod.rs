@@ -0,0 +1,265 @@
+use cgmath::{InnerSpace, SquareMatrix};
+
+use crate::components;
+use crate::schedule;
+use crate::world::World;
+
+pub mod controller;
+
+pub use controller::{FlyController, OrbitController};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Projection {
+    // --- fov is the vertical field of view
+    Perspective { fov: cgmath::Rad<f32> },
+    // --- height of the view volume in world units; its width follows the aspect
+    Orthographic { height: f32 },
+}
+
+// --- looks along the entity's local -z with local +y up, like Transform::look_at;
+// --- the aspect ratio comes from the Surface resource
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn perspective(fov: cgmath::Rad<f32>, near: f32, far: f32) -> Camera {
+        Camera {
+            projection: Projection::Perspective { fov: fov },
+            near: near,
+            far: far,
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            projection: Projection::Orthographic { height: height },
+            near: near,
+            far: far,
+        }
+    }
+
+    // --- not flipped for Vulkan's clip space, so +y points down on screen
+    pub fn projection_matrix(&self, aspect: f32) -> cgmath::Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { fov } => cgmath::perspective(fov, aspect, self.near, self.far),
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            },
+        }
+    }
+}
+
+// --- world to view matrix for a camera placed by matrix; scale is ignored
+pub fn view_matrix(matrix: &cgmath::Matrix4<f32>) -> cgmath::Matrix4<f32> {
+    let rotation = cgmath::Matrix3::from_cols(
+        matrix.x.truncate().normalize(),
+        matrix.y.truncate().normalize(),
+        matrix.z.truncate().normalize(),
+    );
+    let inverse_rotation = cgmath::Matrix4::from(rotation).invert().unwrap_or_else(cgmath::Matrix4::identity);
+    inverse_rotation * cgmath::Matrix4::from_translation(-matrix.w.truncate())
+}
+
+// --- size of the surface rendered to, kept as a world resource
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Surface {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Surface {
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+}
+
+// --- the camera views are rendered from; None, or an entity that is no longer a camera,
+// --- falls back to the first camera in entity order
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub struct ActiveCamera {
+    pub entity: Option<components::Entity>,
+}
+
+// --- world resource written before rendering from the active camera
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct CameraView {
+    pub entity: Option<components::Entity>,
+    pub projection: cgmath::Matrix4<f32>,
+    pub view: cgmath::Matrix4<f32>,
+    pub position: cgmath::Vector3<f32>,
+}
+
+impl Default for CameraView {
+    fn default() -> CameraView {
+        CameraView {
+            entity: None,
+            projection: cgmath::Matrix4::identity(),
+            view: cgmath::Matrix4::identity(),
+            position: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+}
+
+// --- components that take part in snapshots; call once when setting up a world with cameras
+pub fn register_components(world: &mut World) {
+    world.register_component_snapshot::<Camera>();
+    world.register_component_snapshot::<FlyController>();
+    world.register_component_snapshot::<OrbitController>();
+}
+
+// --- runs before rendering, after transform interpolation, so the view follows the
+// --- camera's interpolated RenderTransform; the Transform is used until it has one
+pub struct CameraSystem;
+
+impl schedule::System for CameraSystem {
+    fn name(&self) -> &str {
+        "camera"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<Camera>()
+            .read::<components::Transform>()
+            .read::<components::RenderTransform>()
+            .read_resource::<Surface>()
+            .read_resource::<ActiveCamera>()
+            .write_resource::<CameraView>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let aspect = ctx.resource::<Surface>().aspect();
+        let active = ctx.resource::<ActiveCamera>().entity;
+        let cameras: Vec<(components::Entity, Camera, cgmath::Matrix4<f32>)> = ctx
+            .query::<(components::Entity, &Camera, Option<&components::RenderTransform>, Option<&components::Transform>)>()
+            .map(|(entity, camera, render_transform, transform)| {
+                let matrix = match (render_transform, transform) {
+                    (Some(render_transform), _) => render_transform.matrix,
+                    (None, Some(transform)) => transform.to_matrix(),
+                    (None, None) => cgmath::Matrix4::identity(),
+                };
+                (entity, *camera, matrix)
+            })
+            .collect();
+
+        let chosen = cameras
+            .iter()
+            .find(|(entity, _, _)| Some(*entity) == active)
+            .or_else(|| cameras.first());
+        if let Some((entity, camera, matrix)) = chosen {
+            *ctx.resource_mut::<CameraView>() = CameraView {
+                entity: Some(*entity),
+                projection: camera.projection_matrix(aspect),
+                view: view_matrix(matrix),
+                position: matrix.w.truncate(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Rotation;
+
+    fn transform(x: f32, y: f32, z: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: y, z: z },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.3, y: 0.5, z: -0.2 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn assert_matrix(actual: cgmath::Matrix4<f32>, expected: cgmath::Matrix4<f32>) {
+        for column in 0..4 {
+            assert!((actual[column] - expected[column]).magnitude() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn views_invert_the_camera_transform_without_its_scale() {
+        let camera = transform(1.0, 2.0, 3.0);
+        let scaled = components::Transform {
+            scale: cgmath::Vector3 { x: 2.0, y: 3.0, z: 4.0 },
+            ..camera
+        };
+        let view = view_matrix(&scaled.to_matrix());
+        assert_matrix(view * camera.to_matrix(), cgmath::Matrix4::identity());
+        assert_matrix(view, view_matrix(&camera.to_matrix()));
+
+        // --- a point ahead of the camera ends up on the view's -z axis
+        let ahead = camera.position + camera.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -2.0 });
+        let ahead = view * ahead.extend(1.0);
+        assert!((ahead.truncate() - cgmath::Vector3 { x: 0.0, y: 0.0, z: -2.0 }).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn projections_follow_the_surface_aspect() {
+        let surface = Surface { width: 1600, height: 900 };
+        let aspect = surface.aspect();
+        assert_eq!(aspect, 16.0 / 9.0);
+        assert_eq!(Surface { width: 640, height: 0 }.aspect(), 640.0);
+
+        let perspective = Camera::perspective(cgmath::Rad(1.0), 0.1, 100.0);
+        let matrix = perspective.projection_matrix(aspect);
+        assert_matrix(matrix, cgmath::perspective(cgmath::Rad(1.0), aspect, 0.1, 100.0));
+        assert!((matrix.y.y / matrix.x.x - aspect).abs() < 1e-4);
+
+        // --- the height is fixed and the width follows
+        let orthographic = Camera::orthographic(10.0, 0.1, 100.0);
+        let matrix = orthographic.projection_matrix(aspect);
+        assert!((matrix.y.y - 0.2).abs() < 1e-6);
+        assert!((matrix.x.x - 0.2 / aspect).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_active_camera_falls_back_to_the_first_one() {
+        let mut world = World::new();
+        let surface = Surface { width: 800, height: 400 };
+        world.insert_resource(surface);
+        world.insert_resource(ActiveCamera::default());
+        world.insert_resource(CameraView::default());
+
+        let orthographic = Camera::orthographic(4.0, 0.1, 10.0);
+        let not_a_camera = world.create_entity().with(transform(9.0, 9.0, 9.0)).build();
+        let first = world
+            .create_entity()
+            .with(Camera::perspective(cgmath::Rad(1.0), 0.1, 100.0))
+            .with(transform(1.0, 0.0, 0.0))
+            .build();
+        // --- the interpolated RenderTransform wins over the Transform
+        let second = world
+            .create_entity()
+            .with(orthographic)
+            .with(transform(2.0, 0.0, 0.0))
+            .with(components::RenderTransform { matrix: transform(5.0, 0.0, 0.0).to_matrix() })
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::RENDER_PREPARE, CameraSystem);
+        let mut view_from = |world: &mut World, active: Option<components::Entity>| {
+            world.insert_resource(ActiveCamera { entity: active });
+            scheduler.run_stage(schedule::RENDER_PREPARE, world);
+            *world.resource::<CameraView>().unwrap()
+        };
+
+        let view = view_from(&mut world, None);
+        assert_eq!(view.entity, Some(first));
+        assert_eq!(view.position, cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 });
+
+        let view = view_from(&mut world, Some(second));
+        assert_eq!(view.entity, Some(second));
+        assert_eq!(view.position, cgmath::Vector3 { x: 5.0, y: 0.0, z: 0.0 });
+        assert_matrix(view.projection, orthographic.projection_matrix(surface.aspect()));
+        assert_matrix(view.view, view_matrix(&transform(5.0, 0.0, 0.0).to_matrix()));
+
+        assert_eq!(view_from(&mut world, Some(not_a_camera)).entity, Some(first));
+        world.destroy_entity(second);
+        assert_eq!(view_from(&mut world, Some(second)).entity, Some(first));
+    }
+}
@@ -5,17 +5,113 @@ use cgmath::*;
 use crate::render;
 use ash::vk;
 
+#[derive(Clone, Debug, Copy)]
 pub struct Transform {
     pub position: cgmath::Vector3<f32>,
-    pub rotation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
     pub scale: cgmath::Vector3<f32>,
 }
 
+impl Transform {
+    // --- local matrix: translation * rotation * scale
+    pub fn to_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // --- rotation about x, then y, then z, the order scene files and older code use
+    pub fn euler(angles: cgmath::Vector3<f32>) -> cgmath::Quaternion<f32> {
+        cgmath::Quaternion::from_angle_x(cgmath::Rad(angles.x))
+            * cgmath::Quaternion::from_angle_y(cgmath::Rad(angles.y))
+            * cgmath::Quaternion::from_angle_z(cgmath::Rad(angles.z))
+    }
+
+    // --- turns by a rotation vector (axis * angle in radians) given in world space
+    pub fn rotate(&mut self, rotation: cgmath::Vector3<f32>) {
+        let angle = rotation.magnitude();
+        if angle > 1e-9 {
+            let turn = cgmath::Quaternion::from_axis_angle(rotation / angle, cgmath::Rad(angle));
+            self.rotation = (turn * self.rotation).normalize();
+        }
+    }
+
+    // --- points the local -z axis at target, keeping local +y as close to up as possible
+    pub fn look_at(&mut self, target: cgmath::Vector3<f32>, up: cgmath::Vector3<f32>) {
+        let forward = target - self.position;
+        if forward.magnitude2() < 1e-12 {
+            return;
+        }
+        let z_axis = -forward.normalize();
+        let mut x_axis = up.cross(z_axis);
+        if x_axis.magnitude2() < 1e-12 {
+            // --- looking straight along up, any sideways axis will do
+            x_axis = cgmath::Vector3 { x: z_axis.y, y: z_axis.z, z: z_axis.x }.cross(z_axis);
+        }
+        let x_axis = x_axis.normalize();
+        let y_axis = z_axis.cross(x_axis);
+        self.rotation = cgmath::Quaternion::from(cgmath::Matrix3::from_cols(x_axis, y_axis, z_axis)).normalize();
+    }
+
+    // --- orbits position and turns rotation by angle about an axis through point
+    pub fn rotate_around(&mut self, point: cgmath::Vector3<f32>, axis: cgmath::Vector3<f32>, angle: cgmath::Rad<f32>) {
+        let turn = cgmath::Quaternion::from_axis_angle(axis.normalize(), angle);
+        self.position = point + turn.rotate_vector(self.position - point);
+        self.rotation = (turn * self.rotation).normalize();
+    }
+
+    // --- t = 0 gives self, t = 1 gives other; rotation takes the shorter way round
+    pub fn slerp(&self, other: &Transform, t: f32) -> Transform {
+        let target = if self.rotation.dot(other.rotation) < 0.0 { -other.rotation } else { other.rotation };
+        Transform {
+            position: self.position.lerp(other.position, t),
+            rotation: self.rotation.slerp(target, t).normalize(),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+// --- Transform as of the start of the current fixed tick; render interpolation blends from it
+#[derive(Clone, Debug, Copy)]
+pub struct PreviousTransform {
+    pub transform: Transform,
+}
+
+// --- world-space matrix between the last two fixed ticks, written before rendering
+#[derive(Clone, Debug, Copy)]
+pub struct RenderTransform {
+    pub matrix: cgmath::Matrix4<f32>,
+}
+
+// --- world-space matrix, written by the transform propagation pass
+#[derive(Clone, Debug, Copy)]
+pub struct GlobalTransform {
+    pub matrix: cgmath::Matrix4<f32>,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Parent {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Children {
+    pub entities: Vec<Entity>,
+}
+
 pub struct Mesh {
     pub vertex_buffer: render::buffer::VertexBuffer,
     pub index_buffer: render::buffer::IndexBuffer,
 }
 
+// --- local space box around a mesh's vertices, used by the spatial index
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: cgmath::Vector3<f32>,
+    pub max: cgmath::Vector3<f32>,
+}
+
+#[derive(Clone, Debug, Copy)]
 pub struct Velocity {
     pub translation_speed: cgmath::Vector3<f32>,
     pub rotation_speed: cgmath::Vector3<f32>,
@@ -37,6 +133,7 @@ pub enum PBRMaterialType {
     PureEmissive, // --- just emits light with no other material properties
 }
 
+#[derive(Clone, Debug, Copy)]
 pub struct PBRMaterial {
     pub albedo: cgmath::Vector3<f32>,
     pub f0_reflectance: cgmath::Vector3<f32>,
@@ -46,6 +143,84 @@ pub struct PBRMaterial {
     pub emissive_color: cgmath::Vector3<f32>,
 }
 
+// --- where a Mesh came from, e.g. "dodecahedron"; lets scenes refer to meshes by name
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshSource {
+    pub name: String,
+}
+
+// --- shader paths a Material was built from, kept so scenes can rebuild it
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialSource {
+    pub vertex_shader: String,
+    pub fragment_shader: String,
+}
+
+// --- unique per world; World keeps an index from name to entity, see World::find_by_name.
+// --- Rename with World::set_name (or insert), writing through get_mut bypasses the index
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Name {
+    name: String,
+}
+
+impl Name {
+    pub fn new(name: &str) -> Name {
+        Name { name: String::from(name) }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+// --- interned tag string, see World::tag; comparing tags is comparing integers
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub struct Tag(pub(crate) usize);
+
+// --- set of runtime tags on an entity. Tags known at compile time can just as well be
+// --- zero sized marker components queried with With<T>
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tags {
+    tags: Signature,
+}
+
+impl Tags {
+    pub fn insert(&mut self, tag: Tag) {
+        self.tags.insert(tag.0);
+    }
+
+    pub fn remove(&mut self, tag: Tag) {
+        self.tags.remove(tag.0);
+    }
+
+    pub fn contains(&self, tag: Tag) -> bool {
+        self.tags.contains(tag.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.ids().next().is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.tags.ids().map(Tag)
+    }
+}
+
+// --- frame timing, kept as a world resource; fixed_dt is the simulation step
+#[derive(Clone, Debug, Copy)]
+pub struct Time {
+    pub fixed_dt: f32,
+    pub frame_time: f32,
+    pub accumulator: f32,
+}
+
+impl Time {
+    // --- how far the frame is between the last fixed tick and the next one, 0..1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_dt).max(0.0).min(1.0)
+    }
+}
+
 pub enum Component {
     TransformComponent(Transform),
     MeshComponent(Mesh),
@@ -54,21 +229,83 @@ pub enum Component {
     PBRMaterialComponent(PBRMaterial),
 }
 
-#[derive(Clone, Debug, Copy)]
-pub enum ComponentType {
-    TransformComponent = 0b0000_0000_0000_0001,
-    MeshComponent = 0b0000_0000_0000_0010,
-    VelocityComponent = 0b0000_0000_0000_0100,
-    MaterialComponent = 0b0000_0000_0000_1000,
-    PBRMaterialComponent = 0b0000_0000_0001_0000
+// --- index of a registered component type, also its bit in a Signature
+pub type ComponentId = usize;
+
+// --- unbounded component mask; trailing zero words are trimmed so equal sets compare equal
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Signature {
+    words: Vec<u64>,
 }
 
-pub type Entity = u32;
+impl Signature {
+    pub fn new() -> Signature {
+        Signature { words: vec![] }
+    }
+
+    pub fn insert(&mut self, id: ComponentId) {
+        let word = id / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (id % 64);
+    }
+
+    pub fn remove(&mut self, id: ComponentId) {
+        let word = id / 64;
+        if word < self.words.len() {
+            self.words[word] &= !(1 << (id % 64));
+            while self.words.last() == Some(&0) {
+                self.words.pop();
+            }
+        }
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        let word = id / 64;
+        word < self.words.len() && self.words[word] & (1 << (id % 64)) != 0
+    }
+
+    pub fn contains_all(&self, other: &Signature) -> bool {
+        other
+            .words
+            .iter()
+            .enumerate()
+            .all(|(i, word)| self.words.get(i).map_or(0, |w| *w) & word == *word)
+    }
+
+    pub fn intersects(&self, other: &Signature) -> bool {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .any(|(a, b)| a & b != 0)
+    }
 
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub index: u32,
+    pub generation: u32,
+}
+
+#[derive(Clone)]
 pub struct StorageEntry<T> {
-    pub storage_type: u32,
+    pub storage_type: Signature,
     pub entity: Entity,
     pub component: T,
+    // --- world change ticks of when the component was added and last written
+    pub added_tick: u32,
+    pub changed_tick: u32,
 }
 
 pub type TransformStorageEntry = StorageEntry<Transform>;
@@ -77,3 +314,97 @@ pub type VelocityStorageEntry = StorageEntry<Velocity>;
 pub type MaterialStorageEntry = StorageEntry<Material>;
 pub type PBRMaterialStorageEntry = StorageEntry<PBRMaterial>;
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(x: f32, y: f32, z: f32) -> Transform {
+        Transform {
+            position: cgmath::Vector3 { x: x, y: y, z: z },
+            rotation: Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn assert_vector(actual: cgmath::Vector3<f32>, x: f32, y: f32, z: f32) {
+        let expected = cgmath::Vector3 { x: x, y: y, z: z };
+        assert!((actual - expected).magnitude() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    // --- quaternions q and -q are the same rotation
+    fn assert_rotation(actual: cgmath::Quaternion<f32>, expected: cgmath::Quaternion<f32>) {
+        assert!(actual.dot(expected).abs() > 1.0 - 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn look_at_points_minus_z_at_the_target() {
+        let up = cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 };
+        let mut camera = transform(1.0, 2.0, 3.0);
+        camera.look_at(cgmath::Vector3 { x: 4.0, y: 6.0, z: 3.0 }, up);
+        assert_vector(camera.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 }), 0.6, 0.8, 0.0);
+        // --- local x stays level and local y leans towards up
+        assert!(camera.rotation.rotate_vector(cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 }).y.abs() < 1e-5);
+        assert!(camera.rotation.rotate_vector(up).y > 0.0);
+
+        // --- straight along up any sideways axis does, as long as the rotation stays valid
+        camera.look_at(cgmath::Vector3 { x: 1.0, y: 10.0, z: 3.0 }, up);
+        assert_vector(camera.rotation.rotate_vector(cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 }), 0.0, 1.0, 0.0);
+        assert!((camera.rotation.magnitude() - 1.0).abs() < 1e-5);
+
+        // --- a target on the camera leaves the rotation alone
+        let before = camera.rotation;
+        camera.look_at(camera.position, up);
+        assert_eq!(camera.rotation, before);
+    }
+
+    #[test]
+    fn slerp_takes_the_short_arc_between_both_ends() {
+        let a = Transform {
+            rotation: cgmath::Quaternion::from_angle_y(cgmath::Rad(0.0)),
+            ..transform(0.0, 0.0, 0.0)
+        };
+        // --- the same rotation as from_angle_y(0.4), with the opposite sign
+        let b = Transform {
+            position: cgmath::Vector3 { x: 2.0, y: 4.0, z: -2.0 },
+            rotation: -cgmath::Quaternion::from_angle_y(cgmath::Rad(0.4)),
+            scale: cgmath::Vector3 { x: 3.0, y: 1.0, z: 1.0 },
+        };
+
+        let start = a.slerp(&b, 0.0);
+        assert_eq!(start.position, a.position);
+        assert_rotation(start.rotation, a.rotation);
+        let end = a.slerp(&b, 1.0);
+        assert_vector(end.position, 2.0, 4.0, -2.0);
+        assert_vector(end.scale, 3.0, 1.0, 1.0);
+        assert_rotation(end.rotation, b.rotation);
+
+        let middle = a.slerp(&b, 0.5);
+        assert_vector(middle.position, 1.0, 2.0, -1.0);
+        assert_vector(middle.scale, 2.0, 1.0, 1.0);
+        assert_rotation(middle.rotation, cgmath::Quaternion::from_angle_y(cgmath::Rad(0.2)));
+    }
+
+    #[test]
+    fn euler_angles_keep_the_x_y_z_matrix_order() {
+        let angles = cgmath::Vector3 { x: 0.3, y: -1.1, z: 2.0 };
+        let expected = cgmath::Matrix4::from_angle_x(cgmath::Rad(angles.x))
+            * cgmath::Matrix4::from_angle_y(cgmath::Rad(angles.y))
+            * cgmath::Matrix4::from_angle_z(cgmath::Rad(angles.z));
+        let actual = cgmath::Matrix4::from(Transform::euler(angles));
+        for column in 0..4 {
+            assert!((actual[column] - expected[column]).magnitude() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn rotate_around_orbits_and_turns() {
+        let mut moon = transform(2.0, 0.0, 1.0);
+        let point = cgmath::Vector3 { x: 1.0, y: 0.0, z: 1.0 };
+        // --- the axis need not be unit length
+        moon.rotate_around(point, cgmath::Vector3 { x: 0.0, y: 0.0, z: 3.0 }, cgmath::Rad(std::f32::consts::FRAC_PI_2));
+        assert_vector(moon.position, 1.0, 1.0, 1.0);
+        assert_rotation(moon.rotation, cgmath::Quaternion::from_angle_z(cgmath::Rad(std::f32::consts::FRAC_PI_2)));
+        assert_vector(moon.rotation.rotate_vector(cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 }), 0.0, 1.0, 0.0);
+    }
+}
@@ -4,6 +4,7 @@ use ash::extensions::{
 };
 
 use crate::geometry;
+use crate::particles;
 
 use ash::extensions::khr::Win32Surface;
 use ash::extensions::nv::RayTracing;
@@ -259,17 +260,23 @@ pub struct DemoContext {
 pub enum PSOCreateOption {
     // --- flags
     HasVertexAttributes = 0b0000_0000_0000_0001,
+    // --- second vertex buffer stepping once per particles::ParticleInstance
+    HasInstanceAttributes = 0b0000_0000_0000_0010,
 
     // --- constants
     NoVertexAttributes = 0b0000_0000_0000_0000,
+    HasVertexAndInstanceAttributes = 0b0000_0000_0000_0011,
 }
 
 impl DemoApp {
-    pub fn run<F: FnMut()>(&self, mut f: F) {
+    // --- f gets the events that arrived since the previous frame
+    pub fn run<F: FnMut(&[winit::Event])>(&self, mut f: F) {
         use winit::*;
 
+        let mut events = vec![];
         loop {
-            f();
+            f(&events);
+            events.clear();
 
             let mut done = false;
             self.events_loop.borrow_mut().poll_events(|ev| {
@@ -280,6 +287,7 @@ impl DemoApp {
                 {
                     done = true
                 }
+                events.push(ev);
             });
             if done {
                 return;
@@ -826,6 +834,10 @@ impl DemoContext {
         *self.shader_modules.get(&String::from(shader_path)).unwrap()
     }
 
+    pub fn has_shader_module(&self, shader_path: &str) -> bool {
+        self.shader_modules.contains_key(shader_path)
+    }
+
     pub fn reload_shader_module(
         &mut self,
         shader_path: &str,
@@ -863,12 +875,12 @@ impl DemoContext {
                 },
             ];
 
-            let vertex_input_binding_descs = [vk::VertexInputBindingDescription {
+            let mut vertex_input_binding_descs = vec![vk::VertexInputBindingDescription {
                 binding: 0,
                 stride: mem::size_of::<geometry::Vertex>() as u32,
                 input_rate: vk::VertexInputRate::VERTEX,
             }];
-            let vertex_input_attribute_descs = [
+            let mut vertex_input_attribute_descs = vec![
                 vk::VertexInputAttributeDescription {
                     location: 0,
                     binding: 0,
@@ -888,6 +900,26 @@ impl DemoContext {
                     offset: offset_of!(geometry::Vertex, color) as u32,
                 },
             ];
+            if (create_flags as u32) & (PSOCreateOption::HasInstanceAttributes as u32) == (PSOCreateOption::HasInstanceAttributes as u32) {
+                vertex_input_binding_descs.push(vk::VertexInputBindingDescription {
+                    binding: 1,
+                    stride: mem::size_of::<particles::ParticleInstance>() as u32,
+                    input_rate: vk::VertexInputRate::INSTANCE,
+                });
+                // --- position and size, then color and life, as two vec4s
+                vertex_input_attribute_descs.push(vk::VertexInputAttributeDescription {
+                    location: 3,
+                    binding: 1,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(particles::ParticleInstance, position) as u32,
+                });
+                vertex_input_attribute_descs.push(vk::VertexInputAttributeDescription {
+                    location: 4,
+                    binding: 1,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(particles::ParticleInstance, color) as u32,
+                });
+            }
             let vertex_input_state_info = if (create_flags as u32) & (PSOCreateOption::HasVertexAttributes as u32) == (PSOCreateOption::HasVertexAttributes as u32) {
                 vk::PipelineVertexInputStateCreateInfo {
                     vertex_attribute_description_count: vertex_input_attribute_descs.len() as u32,
@@ -15,6 +15,7 @@ use render::buffer::Buffer;
 use render::buffer::copy_to_buffer;
 use demo::end_and_submit_command_buffer;
 
+pub mod obj;
 pub mod platonic;
 
 #[repr(C)]
@@ -22,12 +23,17 @@ pub mod platonic;
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
-    pub color: [f32; 3]
+    pub color: [f32; 3],
+    // --- not read by the current vertex layouts; generated shapes leave it at zero
+    pub texcoord: [f32; 2]
 }
 
 pub struct GeometryData {
     pub vertices: Vec<Vertex>,
-    pub indices: Vec<u32>
+    pub indices: Vec<u32>,
+    // --- object or group name and usemtl material of geometry loaded from files
+    pub name: Option<String>,
+    pub material: Option<String>
 }
 
 #[repr(C)]
@@ -151,7 +157,9 @@ pub fn mesh(
 pub fn quad() -> GeometryData {
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let vertex_data = vec![
@@ -189,24 +197,59 @@ pub fn quad() -> GeometryData {
             Vertex {
                 position: [vertex_data[face[0] as usize].x, vertex_data[face[0] as usize].y, vertex_data[face[0] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize] 
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[1] as usize].x, vertex_data[face[1] as usize].y, vertex_data[face[1] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[2] as usize].x, vertex_data[face[2] as usize].y, vertex_data[face[2] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
     }
 
     data
-}
\ No newline at end of file
+}
+
+// --- builds geometry from a generator name, as referenced by scene files
+pub fn generate(name: &str) -> Option<GeometryData> {
+    match name {
+        "quad" => Some(quad()),
+        "tetrahedron" => Some(platonic::tetrahedron()),
+        "cube" => Some(platonic::cube()),
+        "octahedron" => Some(platonic::octahedron()),
+        "dodecahedron" => Some(platonic::dodecahedron()),
+        "icosahedron" => Some(platonic::icosahedron()),
+        _ => None,
+    }
+}
+
+// --- local box around all vertices; empty geometry gives a zero sized box at the origin
+pub fn bounds(geometry: &GeometryData) -> components::Bounds {
+    let mut vertices = geometry.vertices.iter().map(|vertex| cgmath::Vector3::from(vertex.position));
+    let first = vertices.next().unwrap_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+
+    vertices.fold(components::Bounds { min: first, max: first }, |bounds, position| components::Bounds {
+        min: cgmath::Vector3 {
+            x: bounds.min.x.min(position.x),
+            y: bounds.min.y.min(position.y),
+            z: bounds.min.z.min(position.z),
+        },
+        max: cgmath::Vector3 {
+            x: bounds.max.x.max(position.x),
+            y: bounds.max.y.max(position.y),
+            z: bounds.max.z.max(position.z),
+        },
+    })
+}
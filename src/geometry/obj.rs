@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use cgmath::InnerSpace;
+
+use crate::geometry::{GeometryData, Vertex};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(error: std::io::Error) -> Self {
+        ObjError::Io(error)
+    }
+}
+
+// --- where a vertex's normal comes from; vertices without one in the file share a normal
+// --- averaged over the faces of their smoothing group, unless smoothing is off and every
+// --- face keeps its own
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    File(usize),
+    Smooth(u32),
+    Flat(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: NormalSource,
+}
+
+// --- geometry of one object or group and material while its faces are read
+struct Builder {
+    geometry: GeometryData,
+    vertices: HashMap<VertexKey, u32>,
+    keys: Vec<VertexKey>,
+    // --- area weighted face normals summed per position and smoothing group
+    smooth_normals: HashMap<(usize, u32), cgmath::Vector3<f32>>,
+}
+
+impl Builder {
+    fn new(name: Option<String>, material: Option<String>) -> Builder {
+        Builder {
+            geometry: GeometryData {
+                vertices: vec![],
+                indices: vec![],
+                name: name,
+                material: material,
+            },
+            vertices: HashMap::new(),
+            keys: vec![],
+            smooth_normals: HashMap::new(),
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey, obj: &Obj, face_normal: cgmath::Vector3<f32>) -> u32 {
+        if let Some(index) = self.vertices.get(&key) {
+            return *index;
+        }
+        let normal = match key.normal {
+            NormalSource::File(normal) => obj.normals[normal],
+            NormalSource::Flat(_) => normalize_or_zero(face_normal),
+            NormalSource::Smooth(_) => [0.0; 3],
+        };
+        let index = self.geometry.vertices.len() as u32;
+        self.geometry.vertices.push(Vertex {
+            position: obj.positions[key.position],
+            normal: normal,
+            color: obj.colors[key.position],
+            texcoord: key.texcoord.map_or([0.0, 0.0], |texcoord| obj.texcoords[texcoord]),
+        });
+        self.vertices.insert(key, index);
+        self.keys.push(key);
+        index
+    }
+
+    fn finish(mut self) -> GeometryData {
+        for (vertex, key) in self.geometry.vertices.iter_mut().zip(self.keys.iter()) {
+            if let NormalSource::Smooth(group) = key.normal {
+                vertex.normal = normalize_or_zero(self.smooth_normals[&(key.position, group)]);
+            }
+        }
+        self.geometry
+    }
+}
+
+fn normalize_or_zero(v: cgmath::Vector3<f32>) -> [f32; 3] {
+    if v.magnitude2() > 0.0 {
+        v.normalize().into()
+    } else {
+        [0.0; 3]
+    }
+}
+
+// --- vertex attributes read so far; faces may only refer back to these
+struct Obj {
+    positions: Vec<[f32; 3]>,
+    // --- the common "v x y z r g b" extension, white when absent
+    colors: Vec<[f32; 3]>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<GeometryData>, ObjError> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+// --- one GeometryData per object or group and usemtl material, in the order they first
+// --- appear; a group is named after its "g", or its "o" when it has none. Polygons are
+// --- fanned into triangles, so they should be convex, and keep the file's winding.
+// --- Materials are returned by name; mtllib files, lines and points are ignored
+pub fn from_str(text: &str) -> Result<Vec<GeometryData>, ObjError> {
+    let mut obj = Obj {
+        positions: vec![],
+        colors: vec![],
+        texcoords: vec![],
+        normals: vec![],
+    };
+    let mut builders: Vec<Builder> = vec![];
+    let mut object: Option<String> = None;
+    let mut group: Option<String> = None;
+    let mut material: Option<String> = None;
+    let mut current: Option<usize> = None;
+    // --- None when smoothing is off; faces before any "s" share group 0, which "s 0" turns off
+    let mut smoothing = Some(0);
+    let mut face_count = 0;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: String| ObjError::Parse {
+            line: line_number,
+            message: message,
+        };
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        let rest = if arguments.is_empty() { None } else { Some(arguments.join(" ")) };
+
+        match keyword {
+            "v" => {
+                let values = numbers(&arguments).map_err(&error)?;
+                match values.len() {
+                    3 | 4 => obj.colors.push([1.0; 3]),
+                    6 => obj.colors.push([values[3], values[4], values[5]]),
+                    _ => return Err(error(String::from("'v' needs 3 coordinates"))),
+                }
+                obj.positions.push([values[0], values[1], values[2]]);
+            },
+            "vt" => {
+                let values = numbers(&arguments).map_err(&error)?;
+                match values.len() {
+                    // --- OBJ puts v = 0 at the bottom of an image, Vulkan samples it at the top
+                    1 => obj.texcoords.push([values[0], 1.0]),
+                    2 | 3 => obj.texcoords.push([values[0], 1.0 - values[1]]),
+                    _ => return Err(error(String::from("'vt' needs 1 to 3 coordinates"))),
+                }
+            },
+            "vn" => {
+                let values = numbers(&arguments).map_err(&error)?;
+                if values.len() != 3 {
+                    return Err(error(String::from("'vn' needs 3 coordinates")));
+                }
+                obj.normals.push([values[0], values[1], values[2]]);
+            },
+            "o" => {
+                object = rest;
+                group = None;
+                current = None;
+            },
+            "g" => {
+                group = rest;
+                current = None;
+            },
+            "usemtl" => {
+                material = rest;
+                current = None;
+            },
+            "s" => {
+                smoothing = match arguments.first() {
+                    Some(&"off") => None,
+                    Some(value) => match value.parse::<u32>() {
+                        Ok(0) => None,
+                        Ok(group) => Some(group),
+                        Err(_) => return Err(error(format!("'{}' is not a smoothing group", value))),
+                    },
+                    None => return Err(error(String::from("'s' needs a group or off"))),
+                };
+            },
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(String::from("'f' needs at least 3 vertices")));
+                }
+                let mut keys = vec![];
+                for argument in arguments.iter() {
+                    keys.push(vertex_key(argument, &obj, smoothing, face_count).map_err(&error)?);
+                }
+
+                // --- summing edge cross products gives polygons a normal whatever their
+                // --- vertex count, twice their area long
+                let mut face_normal = cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+                for (i, key) in keys.iter().enumerate() {
+                    let a = cgmath::Vector3::from(obj.positions[key.position]);
+                    let b = cgmath::Vector3::from(obj.positions[keys[(i + 1) % keys.len()].position]);
+                    face_normal += a.cross(b);
+                }
+
+                let index = match current {
+                    Some(index) => index,
+                    None => {
+                        let name = group.clone().or_else(|| object.clone());
+                        let index = match builders
+                            .iter()
+                            .position(|builder| builder.geometry.name == name && builder.geometry.material == material)
+                        {
+                            Some(index) => index,
+                            None => {
+                                builders.push(Builder::new(name, material.clone()));
+                                builders.len() - 1
+                            },
+                        };
+                        current = Some(index);
+                        index
+                    },
+                };
+                let builder = &mut builders[index];
+
+                let indices: Vec<u32> = keys.iter().map(|key| builder.vertex(*key, &obj, face_normal)).collect();
+                for key in keys.iter() {
+                    let group = match key.normal {
+                        NormalSource::Smooth(group) => group,
+                        _ => continue,
+                    };
+                    *builder
+                        .smooth_normals
+                        .entry((key.position, group))
+                        .or_insert(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }) += face_normal;
+                }
+                for i in 1..indices.len() - 1 {
+                    builder.geometry.indices.push(indices[0]);
+                    builder.geometry.indices.push(indices[i]);
+                    builder.geometry.indices.push(indices[i + 1]);
+                }
+                face_count += 1;
+            },
+            _ => {},
+        }
+    }
+
+    Ok(builders.into_iter().map(Builder::finish).collect())
+}
+
+fn numbers(arguments: &[&str]) -> Result<Vec<f32>, String> {
+    arguments
+        .iter()
+        .map(|argument| argument.parse::<f32>().map_err(|_| format!("'{}' is not a number", argument)))
+        .collect()
+}
+
+// --- 1 based, or negative to count back from the last one read
+fn resolve(reference: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = reference
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a {} index", reference, kind))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} is out of range", kind, index));
+    }
+    Ok(resolved as usize)
+}
+
+// --- "v", "v/vt", "v//vn" or "v/vt/vn"
+fn vertex_key(argument: &str, obj: &Obj, smoothing: Option<u32>, face: usize) -> Result<VertexKey, String> {
+    let mut references = argument.split('/');
+    let position = resolve(references.next().unwrap_or(""), obj.positions.len(), "position")?;
+    let texcoord = match references.next() {
+        Some("") | None => None,
+        Some(reference) => Some(resolve(reference, obj.texcoords.len(), "texcoord")?),
+    };
+    let normal = match references.next() {
+        Some("") | None => match smoothing {
+            Some(group) => NormalSource::Smooth(group),
+            None => NormalSource::Flat(face),
+        },
+        Some(reference) => NormalSource::File(resolve(reference, obj.normals.len(), "normal")?),
+    };
+    if references.next().is_some() {
+        return Err(format!("'{}' has too many references", argument));
+    }
+    Ok(VertexKey {
+        position: position,
+        texcoord: texcoord,
+        normal: normal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE: &str = "# cube
+mtllib cube.mtl
+o cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+s off
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    fn names(geometry: &[GeometryData]) -> Vec<(Option<&str>, Option<&str>)> {
+        geometry
+            .iter()
+            .map(|geometry| (geometry.name.as_deref(), geometry.material.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn negative_and_normal_only_references_resolve() {
+        let text = "v 0 0 0 1 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+f -4//-1 -3//1 -2//-1
+f 1/-2/1 3/-1/1 4/2/-1
+";
+        let geometry = from_str(text).unwrap();
+        assert_eq!(geometry.len(), 1);
+        assert_eq!(geometry[0].indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(geometry[0].vertices[0].position, [0.0, 0.0, 0.0]);
+        assert_eq!(geometry[0].vertices[0].color, [1.0, 0.0, 0.0]);
+        assert_eq!(geometry[0].vertices[0].texcoord, [0.0, 0.0]);
+        assert_eq!(geometry[0].vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(geometry[0].vertices[5].position, [0.0, 1.0, 0.0]);
+        // --- v is flipped for Vulkan
+        assert_eq!(geometry[0].vertices[3].texcoord, [0.0, 1.0]);
+        assert_eq!(geometry[0].vertices[4].texcoord, [1.0, 0.0]);
+        for vertex in geometry[0].vertices.iter() {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn polygons_are_fanned_and_vertices_shared() {
+        let text = "v 0 0 0
+v 2 0 0
+v 3 1 0
+v 1 2 0
+v -1 1 0
+vn 0 0 1
+f 1//1 2//1 3//1 4//1 5//1
+f 1//1 3//1 4//1
+";
+        let geometry = from_str(text).unwrap();
+        assert_eq!(geometry[0].vertices.len(), 5);
+        assert_eq!(geometry[0].indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 2, 3]);
+    }
+
+    #[test]
+    fn objects_groups_and_materials_split_geometry() {
+        let text = "v 0 0 0
+v 1 0 0
+v 1 1 0
+o first
+usemtl red
+f 1 2 3
+usemtl blue
+f 1 2 3
+g named
+f 1 2 3
+o second
+f 1 2 3
+o first
+usemtl red
+f 3 2 1
+";
+        let geometry = from_str(text).unwrap();
+        assert_eq!(
+            names(&geometry),
+            vec![
+                (Some("first"), Some("red")),
+                (Some("first"), Some("blue")),
+                (Some("named"), Some("blue")),
+                (Some("second"), Some("blue")),
+            ]
+        );
+        // --- returning to an object and material appends to its geometry
+        assert_eq!(geometry[0].indices.len(), 6);
+        assert_eq!(geometry[1].indices.len(), 3);
+        assert_eq!(geometry[0].vertices.len(), 3);
+    }
+
+    #[test]
+    fn flat_normals_follow_the_winding() {
+        let geometry = from_str(CUBE).unwrap();
+        assert_eq!(names(&geometry), vec![(Some("cube"), None)]);
+        assert_eq!(geometry[0].vertices.len(), 24);
+        assert_eq!(geometry[0].indices.len(), 36);
+        for triangle in geometry[0].indices.chunks(3) {
+            let p: Vec<cgmath::Vector3<f32>> = triangle
+                .iter()
+                .map(|index| geometry[0].vertices[*index as usize].position.into())
+                .collect();
+            let normal = cgmath::Vector3::from(geometry[0].vertices[triangle[0] as usize].normal);
+            let winding = (p[1] - p[0]).cross(p[2] - p[0]).normalize();
+            assert!((winding - normal).magnitude() < 1e-5, "{:?} {:?}", winding, normal);
+            assert!(normal.dot(p[0]) > 0.0);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_are_averaged_per_position() {
+        let geometry = from_str(&CUBE.replace("s off", "s 1")).unwrap();
+        assert_eq!(geometry[0].vertices.len(), 8);
+        assert_eq!(geometry[0].indices.len(), 36);
+        for vertex in geometry[0].vertices.iter() {
+            let position = cgmath::Vector3::from(vertex.position);
+            let normal = cgmath::Vector3::from(vertex.normal);
+            assert!((normal - position.normalize()).magnitude() < 1e-5, "{:?} {:?}", position, normal);
+            assert_eq!(vertex.color, [1.0, 1.0, 1.0]);
+        }
+        // --- faces before any "s" are smooth too
+        assert_eq!(from_str(&CUBE.replace("s off", "")).unwrap()[0].vertices.len(), 8);
+    }
+
+    #[test]
+    fn smoothing_groups_keep_hard_edges() {
+        let mut group = 0;
+        let text: Vec<String> = CUBE
+            .lines()
+            .filter(|line| *line != "s off")
+            .map(|line| {
+                if line.starts_with("f ") {
+                    group += 1;
+                    format!("s {}\n{}", group, line)
+                } else {
+                    String::from(line)
+                }
+            })
+            .collect();
+        let geometry = from_str(&text.join("\n")).unwrap();
+        assert_eq!(geometry[0].vertices.len(), 24);
+        for triangle in geometry[0].indices.chunks(3) {
+            let p: Vec<cgmath::Vector3<f32>> = triangle
+                .iter()
+                .map(|index| geometry[0].vertices[*index as usize].position.into())
+                .collect();
+            let winding = (p[1] - p[0]).cross(p[2] - p[0]).normalize();
+            for index in triangle.iter() {
+                let normal = cgmath::Vector3::from(geometry[0].vertices[*index as usize].normal);
+                assert!((winding - normal).magnitude() < 1e-5, "{:?} {:?}", winding, normal);
+            }
+        }
+
+        // --- the z = -1 and y = -1 faces in group 1 share their common edge, the other
+        // --- three sides share group 3
+        let text = CUBE.replace("s off\nf 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5", "s 1\nf 1 4 3 2\ns 2\nf 5 6 7 8\ns 1\nf 1 2 6 5\ns 3");
+        let geometry = from_str(&text).unwrap();
+        assert_eq!(geometry[0].vertices.len(), 18);
+        let shared = geometry[0].vertices.iter().find(|vertex| vertex.position == [-1.0, -1.0, -1.0]).unwrap();
+        let expected = cgmath::Vector3 { x: 0.0, y: -1.0, z: -1.0 }.normalize();
+        assert!((cgmath::Vector3::from(shared.normal) - expected).magnitude() < 1e-5, "{:?}", shared.normal);
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        let line = |text: &str| match from_str(text) {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|geometry| geometry.len())),
+        };
+        assert_eq!(line("v 0 0 0\n\nf 1 2 3\n"), 3);
+        assert_eq!(line("v 0 0\n"), 1);
+        assert_eq!(line("# comment\nv a 0 0\n"), 2);
+        assert_eq!(line("v 0 0 0\nf 1 1\n"), 2);
+        assert_eq!(line("v 0 0 0\nf 0 1 1\n"), 2);
+        assert_eq!(line("v 0 0 0\nf -2 1 1\n"), 2);
+        assert_eq!(line("v 0 0 0\nf 1/1 1 1\n"), 2);
+        assert_eq!(line("v 0 0 0\nvn 0 0 1\nf 1//1/1 1 1\n"), 3);
+        assert_eq!(line("v 0 0 0\ns on\n"), 2);
+        assert!(from_str("").unwrap().is_empty());
+        match load(Path::new("/nonexistent.obj")) {
+            Err(ObjError::Io(_)) => {},
+            other => panic!("expected an io error, got {:?}", other.map(|geometry| geometry.len())),
+        }
+    }
+}
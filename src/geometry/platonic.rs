@@ -7,7 +7,9 @@ use crate::geometry::Vertex;
 pub fn tetrahedron() -> GeometryData {
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let sq2over3: f32 = 1.41421356237309504880 / 3.0;
@@ -49,21 +51,24 @@ pub fn tetrahedron() -> GeometryData {
             Vertex {
                 position: [vertex_data[face[0] as usize].x, vertex_data[face[0] as usize].y, vertex_data[face[0] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize] 
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[1] as usize].x, vertex_data[face[1] as usize].y, vertex_data[face[1] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[2] as usize].x, vertex_data[face[2] as usize].y, vertex_data[face[2] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
     }
@@ -74,7 +79,9 @@ pub fn tetrahedron() -> GeometryData {
 pub fn cube() -> GeometryData {
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let face_normals_and_tangents = vec![
@@ -138,7 +145,8 @@ pub fn cube() -> GeometryData {
                 Vertex {
                     position: [vertex_positions[j].x, vertex_positions[j].y, vertex_positions[j].z],
                     normal: [normal.x, normal.y, normal.z],
-                    color: face_colors[i as usize] 
+                    color: face_colors[i as usize],
+                    texcoord: [0.0, 0.0]
                 }
             );    
         }
@@ -150,7 +158,9 @@ pub fn cube() -> GeometryData {
 pub fn octahedron() -> GeometryData {
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let vertex_data = vec![
@@ -198,21 +208,24 @@ pub fn octahedron() -> GeometryData {
             Vertex {
                 position: [vertex_data[face[0] as usize].x, vertex_data[face[0] as usize].y, vertex_data[face[0] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize] 
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[1] as usize].x, vertex_data[face[1] as usize].y, vertex_data[face[1] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[2] as usize].x, vertex_data[face[2] as usize].y, vertex_data[face[2] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
     }
@@ -228,7 +241,9 @@ pub fn dodecahedron() -> GeometryData {
 
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let vertex_data = vec![
@@ -306,35 +321,40 @@ pub fn dodecahedron() -> GeometryData {
             Vertex {
                 position: [vertex_data[face[0] as usize].x, vertex_data[face[0] as usize].y, vertex_data[face[0] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize] 
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[1] as usize].x, vertex_data[face[1] as usize].y, vertex_data[face[1] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[2] as usize].x, vertex_data[face[2] as usize].y, vertex_data[face[2] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[3] as usize].x, vertex_data[face[3] as usize].y, vertex_data[face[3] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[4] as usize].x, vertex_data[face[4] as usize].y, vertex_data[face[4] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
     }
@@ -348,7 +368,9 @@ pub fn icosahedron() -> GeometryData {
 
     let mut data = GeometryData {
         vertices: Vec::default(),
-        indices: Vec::default()
+        indices: Vec::default(),
+        name: None,
+        material: None
     };
 
     let vertex_data = vec![
@@ -426,21 +448,24 @@ pub fn icosahedron() -> GeometryData {
             Vertex {
                 position: [vertex_data[face[0] as usize].x, vertex_data[face[0] as usize].y, vertex_data[face[0] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize] 
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[1] as usize].x, vertex_data[face[1] as usize].y, vertex_data[face[1] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
         data.vertices.push(
             Vertex {
                 position: [vertex_data[face[2] as usize].x, vertex_data[face[2] as usize].y, vertex_data[face[2] as usize].z],
                 normal: [normal.x, normal.y, normal.z],
-                color: face_colors[i as usize]
+                color: face_colors[i as usize],
+                texcoord: [0.0, 0.0]
             }
         );
     }
@@ -0,0 +1,449 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use winit::{MouseButton, VirtualKeyCode};
+
+use crate::input::Input;
+use crate::scene::json::{self, Value};
+use crate::scene::SceneError;
+
+// --- keys bindings files can name, written as winit spells them
+const KEYS: &[VirtualKeyCode] = &[
+    VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4, VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9, VirtualKeyCode::Key0,
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E,
+    VirtualKeyCode::F, VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J,
+    VirtualKeyCode::K, VirtualKeyCode::L, VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O,
+    VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R, VirtualKeyCode::S, VirtualKeyCode::T,
+    VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X, VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4, VirtualKeyCode::F5,
+    VirtualKeyCode::F6, VirtualKeyCode::F7, VirtualKeyCode::F8, VirtualKeyCode::F9, VirtualKeyCode::F10,
+    VirtualKeyCode::F11, VirtualKeyCode::F12,
+    VirtualKeyCode::Escape, VirtualKeyCode::Tab, VirtualKeyCode::Space, VirtualKeyCode::Return, VirtualKeyCode::Back,
+    VirtualKeyCode::Insert, VirtualKeyCode::Delete, VirtualKeyCode::Home, VirtualKeyCode::End,
+    VirtualKeyCode::PageUp, VirtualKeyCode::PageDown,
+    VirtualKeyCode::Left, VirtualKeyCode::Right, VirtualKeyCode::Up, VirtualKeyCode::Down,
+    VirtualKeyCode::LShift, VirtualKeyCode::RShift, VirtualKeyCode::LControl, VirtualKeyCode::RControl,
+    VirtualKeyCode::LAlt, VirtualKeyCode::RAlt,
+    VirtualKeyCode::Numpad0, VirtualKeyCode::Numpad1, VirtualKeyCode::Numpad2, VirtualKeyCode::Numpad3,
+    VirtualKeyCode::Numpad4, VirtualKeyCode::Numpad5, VirtualKeyCode::Numpad6, VirtualKeyCode::Numpad7,
+    VirtualKeyCode::Numpad8, VirtualKeyCode::Numpad9, VirtualKeyCode::NumpadEnter,
+    VirtualKeyCode::Add, VirtualKeyCode::Subtract, VirtualKeyCode::Multiply, VirtualKeyCode::Divide,
+    VirtualKeyCode::Grave, VirtualKeyCode::Minus, VirtualKeyCode::Equals, VirtualKeyCode::LBracket,
+    VirtualKeyCode::RBracket, VirtualKeyCode::Semicolon, VirtualKeyCode::Apostrophe, VirtualKeyCode::Comma,
+    VirtualKeyCode::Period, VirtualKeyCode::Slash, VirtualKeyCode::Backslash,
+];
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Button {
+    // --- "W", "Space", "LShift" for keys; "MouseLeft", "MouseRight", "MouseMiddle" or
+    // --- "Mouse4" for mouse buttons
+    pub fn from_name(name: &str) -> Option<Button> {
+        match name {
+            "MouseLeft" => return Some(Button::Mouse(MouseButton::Left)),
+            "MouseRight" => return Some(Button::Mouse(MouseButton::Right)),
+            "MouseMiddle" => return Some(Button::Mouse(MouseButton::Middle)),
+            _ => {},
+        }
+        if let Some(Ok(number)) = name.strip_prefix("Mouse").map(str::parse::<u8>) {
+            return Some(Button::Mouse(MouseButton::Other(number)));
+        }
+        KEYS.iter().find(|key| format!("{:?}", key) == name).map(|key| Button::Key(*key))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Button::Key(key) => format!("{:?}", key),
+            Button::Mouse(MouseButton::Left) => String::from("MouseLeft"),
+            Button::Mouse(MouseButton::Right) => String::from("MouseRight"),
+            Button::Mouse(MouseButton::Middle) => String::from("MouseMiddle"),
+            Button::Mouse(MouseButton::Other(number)) => format!("Mouse{}", number),
+        }
+    }
+
+    fn held(&self, input: &Input) -> bool {
+        match self {
+            Button::Key(key) => input.key_held(*key),
+            Button::Mouse(button) => input.button_held(*button),
+        }
+    }
+
+    fn pressed(&self, input: &Input) -> bool {
+        match self {
+            Button::Key(key) => input.key_pressed(*key),
+            Button::Mouse(button) => input.button_pressed(*button),
+        }
+    }
+}
+
+// --- one contribution to an axis. Buttons act like a gamepad's digital pad: their sum over
+// --- an axis is clamped to [-1, 1], so W and Up together still move at full speed once.
+// --- Mouse motion and scrolling are added on top unclamped, scaled per unit
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum AxisBinding {
+    Buttons { positive: Option<Button>, negative: Option<Button> },
+    MouseX { scale: f32 },
+    MouseY { scale: f32 },
+    Scroll { scale: f32 },
+}
+
+impl AxisBinding {
+    pub fn buttons(positive: Button, negative: Button) -> AxisBinding {
+        AxisBinding::Buttons {
+            positive: Some(positive),
+            negative: Some(negative),
+        }
+    }
+}
+
+// --- named actions (held buttons) and axes (values); any binding of an action holds it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputMap {
+    pub actions: BTreeMap<String, Vec<Button>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    pub fn new() -> InputMap {
+        InputMap::default()
+    }
+
+    pub fn action(mut self, name: &str, button: Button) -> InputMap {
+        self.actions.entry(String::from(name)).or_insert_with(Vec::new).push(button);
+        self
+    }
+
+    pub fn axis(mut self, name: &str, binding: AxisBinding) -> InputMap {
+        self.axes.entry(String::from(name)).or_insert_with(Vec::new).push(binding);
+        self
+    }
+
+    // --- bindings named in other replace this map's, everything else is kept; this is how
+    // --- a bindings file rebinds the defaults
+    pub fn rebind(&mut self, other: InputMap) {
+        self.actions.extend(other.actions);
+        self.axes.extend(other.axes);
+    }
+}
+
+pub fn load(path: &Path) -> Result<InputMap, SceneError> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+// --- { "actions": { "name": ["Tab", "MouseLeft"] },
+// ---   "axes": { "name": [{ "positive": "W", "negative": "S" }, { "mouse_x": 1.0 }, { "scroll": 1.0 }] } }
+pub fn from_str(text: &str) -> Result<InputMap, SceneError> {
+    let root = json::parse(text)?;
+    let mut map = InputMap::new();
+
+    for (name, _) in members(&root, "bindings")? {
+        if name != "actions" && name != "axes" {
+            return Err(SceneError::Invalid(format!("unknown field '{}' in bindings", name)));
+        }
+    }
+    if let Some(actions) = root.get("actions") {
+        for (name, buttons) in members(actions, "actions")? {
+            let context = format!("actions.{}", name);
+            let buttons = buttons
+                .as_array()
+                .ok_or_else(|| SceneError::Invalid(format!("{} must be an array of button names", context)))?
+                .iter()
+                .map(|button| button_from_value(button, &context))
+                .collect::<Result<Vec<Button>, SceneError>>()?;
+            map.actions.insert(name.clone(), buttons);
+        }
+    }
+    if let Some(axes) = root.get("axes") {
+        for (name, bindings) in members(axes, "axes")? {
+            let context = format!("axes.{}", name);
+            let bindings = bindings
+                .as_array()
+                .ok_or_else(|| SceneError::Invalid(format!("{} must be an array of bindings", context)))?
+                .iter()
+                .map(|binding| axis_binding_from_value(binding, &context))
+                .collect::<Result<Vec<AxisBinding>, SceneError>>()?;
+            map.axes.insert(name.clone(), bindings);
+        }
+    }
+
+    Ok(map)
+}
+
+fn members<'a>(value: &'a Value, context: &str) -> Result<&'a [(String, Value)], SceneError> {
+    match value {
+        Value::Object(members) => Ok(members.as_slice()),
+        _ => Err(SceneError::Invalid(format!("{} must be an object", context))),
+    }
+}
+
+fn button_from_value(value: &Value, context: &str) -> Result<Button, SceneError> {
+    let name = value
+        .as_str()
+        .ok_or_else(|| SceneError::Invalid(format!("{} must name buttons with strings", context)))?;
+    Button::from_name(name).ok_or_else(|| SceneError::Invalid(format!("unknown button '{}' in {}", name, context)))
+}
+
+fn axis_binding_from_value(value: &Value, context: &str) -> Result<AxisBinding, SceneError> {
+    let members = members(value, context)?;
+    let scale = |name: &str| {
+        value
+            .get(name)
+            .and_then(Value::as_f32)
+            .ok_or_else(|| SceneError::Invalid(format!("{}.{} must be a number", context, name)))
+    };
+    match members.first().map(|(name, _)| name.as_str()) {
+        Some("mouse_x") if members.len() == 1 => Ok(AxisBinding::MouseX { scale: scale("mouse_x")? }),
+        Some("mouse_y") if members.len() == 1 => Ok(AxisBinding::MouseY { scale: scale("mouse_y")? }),
+        Some("scroll") if members.len() == 1 => Ok(AxisBinding::Scroll { scale: scale("scroll")? }),
+        _ => {
+            if let Some((name, _)) = members.iter().find(|(name, _)| name != "positive" && name != "negative") {
+                return Err(SceneError::Invalid(format!("unknown field '{}' in {}", name, context)));
+            }
+            let button = |name: &str| match value.get(name) {
+                Some(button) => button_from_value(button, &format!("{}.{}", context, name)).map(Some),
+                None => Ok(None),
+            };
+            Ok(AxisBinding::Buttons {
+                positive: button("positive")?,
+                negative: button("negative")?,
+            })
+        },
+    }
+}
+
+// --- this frame's actions and axes, kept as a world resource and updated once per frame
+// --- after the Input. Tests and recordings drive it through the Input, without a window
+#[derive(Clone, Debug, Default)]
+pub struct ActionState {
+    held: HashSet<String>,
+    pressed: HashSet<String>,
+    released: HashSet<String>,
+    axes: HashMap<String, f32>,
+}
+
+impl ActionState {
+    pub fn new() -> ActionState {
+        ActionState::default()
+    }
+
+    // --- a tap that goes down and up within one frame is both pressed and released
+    pub fn update(&mut self, map: &InputMap, input: &Input) {
+        self.pressed.clear();
+        self.released.clear();
+        for (name, buttons) in map.actions.iter() {
+            let was_held = self.held.contains(name);
+            let held = buttons.iter().any(|button| button.held(input));
+            let pressed = !was_held && buttons.iter().any(|button| button.pressed(input));
+            if pressed {
+                self.pressed.insert(name.clone());
+            }
+            if (was_held || pressed) && !held {
+                self.released.insert(name.clone());
+            }
+            if held {
+                self.held.insert(name.clone());
+            } else {
+                self.held.remove(name);
+            }
+        }
+        // --- actions dropped from the map by a rebind let go too
+        let released: Vec<String> = self.held.iter().filter(|name| !map.actions.contains_key(*name)).cloned().collect();
+        for name in released {
+            self.held.remove(&name);
+            self.released.insert(name);
+        }
+
+        self.axes = map
+            .axes
+            .iter()
+            .map(|(name, bindings)| (name.clone(), axis_value(bindings, input)))
+            .collect();
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.held.contains(action)
+    }
+
+    // --- went down this frame
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+
+    // --- went up this frame
+    pub fn released(&self, action: &str) -> bool {
+        self.released.contains(action)
+    }
+
+    // --- 0 for axes that are not bound
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.get(axis).copied().unwrap_or(0.0)
+    }
+}
+
+fn axis_value(bindings: &[AxisBinding], input: &Input) -> f32 {
+    let mut digital: f32 = 0.0;
+    let mut analog = 0.0;
+    for binding in bindings.iter() {
+        match binding {
+            AxisBinding::Buttons { positive, negative } => {
+                if positive.map_or(false, |button| button.held(input)) {
+                    digital += 1.0;
+                }
+                if negative.map_or(false, |button| button.held(input)) {
+                    digital -= 1.0;
+                }
+            },
+            AxisBinding::MouseX { scale } => analog += input.mouse_delta.x * scale,
+            AxisBinding::MouseY { scale } => analog += input.mouse_delta.y * scale,
+            AxisBinding::Scroll { scale } => analog += input.scroll_delta * scale,
+        }
+    }
+    digital.max(-1.0).min(1.0) + analog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::ElementState;
+
+    fn map() -> InputMap {
+        InputMap::new()
+            .action("jump", Button::Key(VirtualKeyCode::Space))
+            .action("jump", Button::Mouse(MouseButton::Left))
+            .axis("move_forward", AxisBinding::buttons(Button::Key(VirtualKeyCode::W), Button::Key(VirtualKeyCode::S)))
+            .axis("move_forward", AxisBinding::buttons(Button::Key(VirtualKeyCode::Up), Button::Key(VirtualKeyCode::Down)))
+            .axis("move_forward", AxisBinding::Scroll { scale: 0.5 })
+    }
+
+    fn frame(actions: &mut ActionState, map: &InputMap, input: &mut Input, change: impl FnOnce(&mut Input)) {
+        input.begin_frame();
+        change(input);
+        actions.update(map, input);
+    }
+
+    #[test]
+    fn actions_go_through_pressed_held_and_released() {
+        let map = map();
+        let mut input = Input::new();
+        let mut actions = ActionState::new();
+
+        frame(&mut actions, &map, &mut input, |input| input.set_key(VirtualKeyCode::Space, ElementState::Pressed));
+        assert!(actions.pressed("jump") && actions.held("jump") && !actions.released("jump"));
+
+        // --- a second binding going down keeps the action held without pressing it again
+        frame(&mut actions, &map, &mut input, |input| input.set_button(MouseButton::Left, ElementState::Pressed));
+        assert!(!actions.pressed("jump") && actions.held("jump"));
+
+        frame(&mut actions, &map, &mut input, |input| input.set_key(VirtualKeyCode::Space, ElementState::Released));
+        assert!(actions.held("jump") && !actions.released("jump"));
+
+        frame(&mut actions, &map, &mut input, |input| input.set_button(MouseButton::Left, ElementState::Released));
+        assert!(!actions.held("jump") && actions.released("jump"));
+
+        frame(&mut actions, &map, &mut input, |_| {});
+        assert!(!actions.pressed("jump") && !actions.held("jump") && !actions.released("jump"));
+
+        // --- a tap within one frame is pressed and released at once
+        frame(&mut actions, &map, &mut input, |input| {
+            input.set_key(VirtualKeyCode::Space, ElementState::Pressed);
+            input.set_key(VirtualKeyCode::Space, ElementState::Released);
+        });
+        assert!(actions.pressed("jump") && actions.released("jump") && !actions.held("jump"));
+        assert!(!actions.pressed("unbound") && !actions.held("unbound"));
+
+        // --- rebinding a held action away lets go of it
+        frame(&mut actions, &map, &mut input, |input| input.set_key(VirtualKeyCode::Space, ElementState::Pressed));
+        frame(&mut actions, &InputMap::new(), &mut input, |_| {});
+        assert!(!actions.held("jump") && actions.released("jump"));
+    }
+
+    #[test]
+    fn button_axes_are_clamped_before_mouse_and_scroll() {
+        let map = map();
+        let mut input = Input::new();
+        let mut actions = ActionState::new();
+
+        frame(&mut actions, &map, &mut input, |input| {
+            input.set_key(VirtualKeyCode::W, ElementState::Pressed);
+            input.set_key(VirtualKeyCode::Up, ElementState::Pressed);
+        });
+        assert_eq!(actions.axis("move_forward"), 1.0);
+
+        frame(&mut actions, &map, &mut input, |input| input.set_key(VirtualKeyCode::S, ElementState::Pressed));
+        assert_eq!(actions.axis("move_forward"), 1.0);
+
+        frame(&mut actions, &map, &mut input, |input| {
+            input.set_key(VirtualKeyCode::W, ElementState::Released);
+            input.set_key(VirtualKeyCode::Up, ElementState::Released);
+            input.set_key(VirtualKeyCode::Down, ElementState::Pressed);
+        });
+        assert_eq!(actions.axis("move_forward"), -1.0);
+
+        // --- scrolling is added on top of the clamped buttons
+        frame(&mut actions, &map, &mut input, |input| input.scroll_delta = 6.0);
+        assert_eq!(actions.axis("move_forward"), 2.0);
+        frame(&mut actions, &map, &mut input, |_| {});
+        assert_eq!(actions.axis("move_forward"), -1.0);
+        assert_eq!(actions.axis("unbound"), 0.0);
+    }
+
+    #[test]
+    fn bindings_files_parse_and_rebind() {
+        let text = r#"{
+            // --- comments are allowed
+            "actions": { "jump": ["Tab", "Mouse4", "MouseRight"] },
+            "axes": { "move_forward": [{ "positive": "Up" }, { "mouse_y": -0.5 }] }
+        }"#;
+        let bindings = from_str(text).unwrap();
+        assert_eq!(
+            bindings.actions["jump"],
+            vec![Button::Key(VirtualKeyCode::Tab), Button::Mouse(MouseButton::Other(4)), Button::Mouse(MouseButton::Right)]
+        );
+        assert_eq!(
+            bindings.axes["move_forward"],
+            vec![
+                AxisBinding::Buttons { positive: Some(Button::Key(VirtualKeyCode::Up)), negative: None },
+                AxisBinding::MouseY { scale: -0.5 },
+            ]
+        );
+
+        let mut map = map().action("crouch", Button::Key(VirtualKeyCode::C));
+        map.rebind(bindings.clone());
+        assert_eq!(map.actions["jump"], bindings.actions["jump"]);
+        assert_eq!(map.axes["move_forward"], bindings.axes["move_forward"]);
+        assert_eq!(map.actions["crouch"], vec![Button::Key(VirtualKeyCode::C)]);
+
+        for button in [Button::Key(VirtualKeyCode::LShift), Button::Mouse(MouseButton::Middle), Button::Mouse(MouseButton::Other(7))].iter() {
+            assert_eq!(Button::from_name(&button.name()), Some(*button));
+        }
+    }
+
+    #[test]
+    fn unknown_names_and_fields_are_refused() {
+        let invalid = |text: &str| match from_str(text) {
+            Err(SceneError::Invalid(message)) => message,
+            other => panic!("{:?} is not an Invalid error", other.map(|_| ())),
+        };
+
+        assert_eq!(invalid(r#"{ "actions": { "jump": ["Nope"] } }"#), "unknown button 'Nope' in actions.jump");
+        assert_eq!(
+            invalid(r#"{ "axes": { "move": [{ "positive": "W", "negative": "Spacebar" }] } }"#),
+            "unknown button 'Spacebar' in axes.move.negative"
+        );
+        assert_eq!(invalid(r#"{ "keys": {} }"#), "unknown field 'keys' in bindings");
+        assert_eq!(invalid(r#"{ "axes": { "move": [{ "positive": "W", "x": 1 }] } }"#), "unknown field 'x' in axes.move");
+        assert_eq!(invalid(r#"{ "actions": { "jump": "Space" } }"#), "actions.jump must be an array of button names");
+        assert_eq!(invalid(r#"{ "axes": { "look": [{ "mouse_x": 1e39 }] } }"#), "axes.look.mouse_x must be a number");
+        match from_str("{ \"actions\": {\n  \"jump\": [\"Space\" }\n}") {
+            Err(SceneError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("{:?} is not a parse error", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+pub mod action;
+
+pub use action::{ActionState, AxisBinding, Button, InputMap};
+
+use winit::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+// --- pixel scroll deltas (touchpads) are turned into lines at this rate
+const PIXELS_PER_LINE: f32 = 20.0;
+
+// --- keyboard and mouse state for the current frame, kept as a world resource. Call
+// --- begin_frame once per frame, then handle_event for every window event of that frame
+#[derive(Clone, Debug)]
+pub struct Input {
+    keys_held: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    // --- raw mouse motion since the last frame, in device units; not clamped by the window
+    pub mouse_delta: cgmath::Vector2<f32>,
+    // --- lines scrolled since the last frame, positive away from the user
+    pub scroll_delta: f32,
+    // --- window coordinates, None while the cursor is outside the window
+    pub cursor_position: Option<cgmath::Vector2<f32>>,
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input {
+            keys_held: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
+            buttons_held: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            mouse_delta: cgmath::Vector2 { x: 0.0, y: 0.0 },
+            scroll_delta: 0.0,
+            cursor_position: None,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = cgmath::Vector2 { x: 0.0, y: 0.0 };
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(key) = input.virtual_keycode {
+                        self.set_key(key, input.state);
+                    }
+                },
+                WindowEvent::MouseInput { state, button, .. } => self.set_button(*button, *state),
+                WindowEvent::MouseWheel { delta, .. } => {
+                    self.scroll_delta += match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                    };
+                },
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.cursor_position = Some(cgmath::Vector2 { x: position.x as f32, y: position.y as f32 });
+                },
+                WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+                // --- releases are not reported while unfocused, so let go of everything
+                WindowEvent::Focused(false) => self.release_all(),
+                _ => {},
+            },
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                self.mouse_delta += cgmath::Vector2 { x: delta.0 as f32, y: delta.1 as f32 };
+            },
+            _ => {},
+        }
+    }
+
+    // --- held keys repeat Pressed events; only the first one counts as a press
+    pub fn set_key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.keys_held.insert(key) {
+                    self.keys_pressed.insert(key);
+                }
+            },
+            ElementState::Released => {
+                if self.keys_held.remove(&key) {
+                    self.keys_released.insert(key);
+                }
+            },
+        }
+    }
+
+    pub fn set_button(&mut self, button: MouseButton, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.buttons_held.insert(button) {
+                    self.buttons_pressed.insert(button);
+                }
+            },
+            ElementState::Released => {
+                if self.buttons_held.remove(&button) {
+                    self.buttons_released.insert(button);
+                }
+            },
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys_released.extend(self.keys_held.drain());
+        self.buttons_released.extend(self.buttons_held.drain());
+    }
+
+    pub fn key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys_held.contains(&key)
+    }
+
+    // --- went down this frame
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    // --- went up this frame
+    pub fn key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+}
@@ -8,4 +8,13 @@ mod render;
 mod geometry;
 mod components;
 mod world;
-mod material;
\ No newline at end of file
+mod material;
+mod schedule;
+mod scene;
+mod spatial;
+mod physics;
+mod animation;
+mod particles;
+mod lighting;
+mod camera;
+mod input;
\ No newline at end of file
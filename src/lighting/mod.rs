@@ -0,0 +1,494 @@
+use cgmath::{InnerSpace, SquareMatrix};
+
+use crate::components;
+use crate::schedule;
+use crate::world::World;
+
+// --- lights the deferred pass takes in one frame; lights past this are dropped
+pub const MAX_LIGHTS: usize = 256;
+
+pub const LIGHT_TYPE_DIRECTIONAL: f32 = 0.0;
+pub const LIGHT_TYPE_POINT: f32 = 1.0;
+pub const LIGHT_TYPE_SPOT: f32 = 2.0;
+pub const LIGHT_TYPE_AREA: f32 = 3.0;
+
+// --- intensity is luminous power in lumens; light fades to nothing at range
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct PointLight {
+    pub color: cgmath::Vector3<f32>,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+// --- shines along the entity's local -z, like Transform::look_at; intensity in lumens,
+// --- full strength inside inner_angle and none outside outer_angle (both from the axis)
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct SpotLight {
+    pub color: cgmath::Vector3<f32>,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: cgmath::Rad<f32>,
+    pub outer_angle: cgmath::Rad<f32>,
+}
+
+// --- shines along the entity's local -z from infinitely far away; illuminance in lux
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct DirectionalLight {
+    pub color: cgmath::Vector3<f32>,
+    pub illuminance: f32,
+}
+
+// --- turns an emissive mesh into a rectangular light: the rectangle spans the two longest
+// --- axes of the mesh's Bounds and faces along the shortest. Its luminance is the
+// --- PBRMaterial's emissive_color times intensity, in nits per unit of emissive color
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct AreaLight {
+    pub intensity: f32,
+    pub range: f32,
+    pub two_sided: bool,
+}
+
+// --- exposure applied to lit surfaces; emissive colors are written to the gbuffer already exposed
+#[derive(Clone, Debug, Copy)]
+pub struct LightingSettings {
+    // --- exposure value at ISO 100, higher for brighter scenes
+    pub ev100: f32,
+    pub ambient: cgmath::Vector3<f32>,
+}
+
+impl LightingSettings {
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * 2.0f32.powf(self.ev100))
+    }
+}
+
+impl Default for LightingSettings {
+    fn default() -> LightingSettings {
+        LightingSettings {
+            ev100: 6.0,
+            ambient: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+}
+
+// --- one light as the deferred pass reads it, world space; the layout matches
+// --- struct Light in deferred.frag. intensity is candela for point and spot lights,
+// --- lux for directional and nits for area lights
+#[repr(C)]
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct LightData {
+    pub position_and_type: [f32; 4],
+    pub direction_and_range: [f32; 4],
+    pub color_and_intensity: [f32; 4],
+    // --- area lights: half extents of the rectangle; spot lights: cosines of the cone angles
+    pub axis_x_and_cos_inner: [f32; 4],
+    pub axis_y_and_cos_outer: [f32; 4],
+}
+
+const NO_LIGHT: LightData = LightData {
+    position_and_type: [0.0; 4],
+    direction_and_range: [0.0; 4],
+    color_and_intensity: [0.0; 4],
+    axis_x_and_cos_inner: [0.0; 4],
+    axis_y_and_cos_outer: [0.0; 4],
+};
+
+impl LightData {
+    pub fn directional(light: &DirectionalLight, matrix: &cgmath::Matrix4<f32>) -> LightData {
+        LightData {
+            position_and_type: [0.0, 0.0, 0.0, LIGHT_TYPE_DIRECTIONAL],
+            direction_and_range: extend(forward(matrix), 0.0),
+            color_and_intensity: extend(light.color, light.illuminance),
+            ..NO_LIGHT
+        }
+    }
+
+    pub fn point(light: &PointLight, matrix: &cgmath::Matrix4<f32>) -> LightData {
+        LightData {
+            position_and_type: extend(matrix.w.truncate(), LIGHT_TYPE_POINT),
+            direction_and_range: [0.0, 0.0, 0.0, light.range],
+            // --- lumens spread evenly over the sphere
+            color_and_intensity: extend(light.color, light.intensity / (4.0 * std::f32::consts::PI)),
+            ..NO_LIGHT
+        }
+    }
+
+    pub fn spot(light: &SpotLight, matrix: &cgmath::Matrix4<f32>) -> LightData {
+        LightData {
+            position_and_type: extend(matrix.w.truncate(), LIGHT_TYPE_SPOT),
+            direction_and_range: extend(forward(matrix), light.range),
+            // --- lumens over pi rather than the cone's solid angle, so narrowing the cone
+            // --- does not brighten it
+            color_and_intensity: extend(light.color, light.intensity / std::f32::consts::PI),
+            axis_x_and_cos_inner: [0.0, 0.0, 0.0, light.inner_angle.0.cos()],
+            axis_y_and_cos_outer: [0.0, 0.0, 0.0, light.outer_angle.0.cos()],
+        }
+    }
+
+    // --- rectangle through the middle of the box, facing the box's shortest local axis;
+    // --- a negative range marks a two sided light
+    pub fn area(
+        light: &AreaLight,
+        emissive_color: cgmath::Vector3<f32>,
+        bounds: &components::Bounds,
+        matrix: &cgmath::Matrix4<f32>,
+    ) -> LightData {
+        let center = (bounds.min + bounds.max) * 0.5;
+        let half = (bounds.max - bounds.min) * 0.5;
+        let mut axes = [
+            matrix.x.truncate() * half.x,
+            matrix.y.truncate() * half.y,
+            matrix.z.truncate() * half.z,
+        ];
+        axes.sort_by(|a, b| b.magnitude2().partial_cmp(&a.magnitude2()).unwrap_or(std::cmp::Ordering::Equal));
+        // --- flat meshes have no shortest axis to face along, their plane gives the normal;
+        // --- lines and points have neither and face along local -z, emitting nothing anyway
+        let plane = axes[0].cross(axes[1]);
+        let normal = if axes[2].magnitude2() > 0.0 {
+            axes[2].normalize()
+        } else if plane.magnitude2() > 0.0 {
+            plane.normalize()
+        } else {
+            forward(matrix)
+        };
+        let position = (matrix * center.extend(1.0)).truncate();
+        LightData {
+            position_and_type: extend(position, LIGHT_TYPE_AREA),
+            direction_and_range: extend(normal, if light.two_sided { -light.range } else { light.range }),
+            color_and_intensity: extend(emissive_color, light.intensity),
+            axis_x_and_cos_inner: extend(axes[0], 0.0),
+            axis_y_and_cos_outer: extend(axes[1], 0.0),
+        }
+    }
+}
+
+fn extend(v: cgmath::Vector3<f32>, w: f32) -> [f32; 4] {
+    [v.x, v.y, v.z, w]
+}
+
+fn forward(matrix: &cgmath::Matrix4<f32>) -> cgmath::Vector3<f32> {
+    let forward = -matrix.z.truncate();
+    if forward.magnitude2() > 0.0 {
+        forward.normalize()
+    } else {
+        cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 }
+    }
+}
+
+// --- the deferred pass's light storage buffer; the layout matches LightBuffer in deferred.frag
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LightBlock {
+    pub view: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    pub ambient_and_exposure: [f32; 4],
+    pub count: [u32; 4],
+    pub lights: [LightData; MAX_LIGHTS],
+}
+
+impl LightBlock {
+    pub fn new(
+        lights: &[LightData],
+        view: cgmath::Matrix4<f32>,
+        projection: cgmath::Matrix4<f32>,
+        settings: &LightingSettings,
+    ) -> LightBlock {
+        let count = lights.len().min(MAX_LIGHTS);
+        let mut block = LightBlock {
+            view: view.into(),
+            inverse_projection: projection.invert().unwrap_or_else(cgmath::Matrix4::identity).into(),
+            ambient_and_exposure: extend(settings.ambient, settings.exposure()),
+            count: [count as u32, 0, 0, 0],
+            lights: [NO_LIGHT; MAX_LIGHTS],
+        };
+        block.lights[..count].copy_from_slice(&lights[..count]);
+        block
+    }
+}
+
+// --- world resource rebuilt before rendering from every light component
+#[derive(Clone, Debug, Default)]
+pub struct LightBuffer {
+    pub lights: Vec<LightData>,
+}
+
+// --- gives every entity with an emissive PBRMaterial and Bounds an AreaLight, unless it has one
+pub fn add_emissive_area_lights(world: &mut World, intensity: f32, range: f32) -> usize {
+    let emissive: Vec<components::Entity> = world
+        .query::<(components::Entity, &components::PBRMaterial, Option<&AreaLight>, Option<&components::Bounds>)>()
+        .filter(|(_, pbr_material, area_light, bounds)| {
+            area_light.is_none() && bounds.is_some() && pbr_material.emissive_color.magnitude2() > 0.0
+        })
+        .map(|(entity, _, _, _)| entity)
+        .collect();
+    for entity in emissive.iter() {
+        world.insert(*entity, AreaLight {
+            intensity: intensity,
+            range: range,
+            two_sided: true,
+        });
+    }
+    emissive.len()
+}
+
+// --- components that take part in snapshots; call once when setting up a world with lights
+pub fn register_components(world: &mut World) {
+    world.register_component_snapshot::<PointLight>();
+    world.register_component_snapshot::<SpotLight>();
+    world.register_component_snapshot::<DirectionalLight>();
+    world.register_component_snapshot::<AreaLight>();
+}
+
+fn light_matrix(render_transform: Option<&components::RenderTransform>, transform: Option<&components::Transform>) -> cgmath::Matrix4<f32> {
+    match (render_transform, transform) {
+        (Some(render_transform), _) => render_transform.matrix,
+        (None, Some(transform)) => transform.to_matrix(),
+        (None, None) => cgmath::Matrix4::identity(),
+    }
+}
+
+// --- runs before rendering, after transform interpolation, and fills the LightBuffer resource:
+// --- directional lights first, then point, spot and area lights, each in entity order
+pub struct LightGatherSystem;
+
+impl schedule::System for LightGatherSystem {
+    fn name(&self) -> &str {
+        "light_gather"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<DirectionalLight>()
+            .read::<PointLight>()
+            .read::<SpotLight>()
+            .read::<AreaLight>()
+            .read::<components::PBRMaterial>()
+            .read::<components::Bounds>()
+            .read::<components::Transform>()
+            .read::<components::RenderTransform>()
+            .write_resource::<LightBuffer>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let mut lights = vec![];
+        lights.extend(
+            ctx.query::<(&DirectionalLight, Option<&components::RenderTransform>, Option<&components::Transform>)>()
+                .map(|(light, render_transform, transform)| {
+                    LightData::directional(light, &light_matrix(render_transform, transform))
+                }),
+        );
+        lights.extend(
+            ctx.query::<(&PointLight, Option<&components::RenderTransform>, Option<&components::Transform>)>()
+                .map(|(light, render_transform, transform)| {
+                    LightData::point(light, &light_matrix(render_transform, transform))
+                }),
+        );
+        lights.extend(
+            ctx.query::<(&SpotLight, Option<&components::RenderTransform>, Option<&components::Transform>)>()
+                .map(|(light, render_transform, transform)| {
+                    LightData::spot(light, &light_matrix(render_transform, transform))
+                }),
+        );
+        lights.extend(
+            ctx.query::<(
+                &AreaLight,
+                &components::PBRMaterial,
+                &components::Bounds,
+                Option<&components::RenderTransform>,
+                Option<&components::Transform>,
+            )>()
+                .map(|(light, pbr_material, bounds, render_transform, transform)| {
+                    LightData::area(light, pbr_material.emissive_color, bounds, &light_matrix(render_transform, transform))
+                }),
+        );
+        ctx.resource_mut::<LightBuffer>().lights = lights;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+
+    fn assert_vector(actual: [f32; 4], x: f32, y: f32, z: f32) {
+        let expected = cgmath::Vector3 { x: x, y: y, z: z };
+        let actual = cgmath::Vector3 { x: actual[0], y: actual[1], z: actual[2] };
+        assert!((actual - expected).magnitude() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    fn translation(x: f32, y: f32, z: f32) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(cgmath::Vector3 { x: x, y: y, z: z })
+    }
+
+    fn bounds(x: f32, y: f32, z: f32) -> components::Bounds {
+        components::Bounds {
+            min: cgmath::Vector3 { x: -x, y: -y, z: -z },
+            max: cgmath::Vector3 { x: x, y: y, z: z },
+        }
+    }
+
+    fn area_light(two_sided: bool) -> AreaLight {
+        AreaLight {
+            intensity: 5.0,
+            range: 10.0,
+            two_sided: two_sided,
+        }
+    }
+
+    fn point_light(intensity: f32) -> PointLight {
+        PointLight {
+            color: cgmath::Vector3 { x: 1.0, y: 0.5, z: 0.25 },
+            intensity: intensity,
+            range: 8.0,
+        }
+    }
+
+    #[test]
+    fn point_and_spot_lights_convert_lumens_to_candela() {
+        let lumens = 4.0 * std::f32::consts::PI * 100.0;
+        let point = LightData::point(&point_light(lumens), &translation(1.0, 2.0, 3.0));
+        assert_eq!(point.position_and_type, [1.0, 2.0, 3.0, LIGHT_TYPE_POINT]);
+        assert_eq!(point.direction_and_range[3], 8.0);
+        assert_eq!(&point.color_and_intensity[..3], &[1.0, 0.5, 0.25]);
+        assert_close(point.color_and_intensity[3], 100.0);
+
+        let spot = SpotLight {
+            color: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+            intensity: std::f32::consts::PI * 50.0,
+            range: 4.0,
+            inner_angle: cgmath::Rad(std::f32::consts::PI / 6.0),
+            outer_angle: cgmath::Rad(std::f32::consts::PI / 4.0),
+        };
+        // --- turned a quarter around y, local -z points along world -x
+        let matrix = translation(0.0, 1.0, 0.0) * cgmath::Matrix4::from_angle_y(cgmath::Rad(std::f32::consts::FRAC_PI_2));
+        let data = LightData::spot(&spot, &matrix);
+        assert_eq!(data.position_and_type[3], LIGHT_TYPE_SPOT);
+        assert_vector(data.position_and_type, 0.0, 1.0, 0.0);
+        assert_vector(data.direction_and_range, -1.0, 0.0, 0.0);
+        assert_eq!(data.direction_and_range[3], 4.0);
+        assert_close(data.color_and_intensity[3], 50.0);
+        assert_close(data.axis_x_and_cos_inner[3], 3.0f32.sqrt() / 2.0);
+        assert_close(data.axis_y_and_cos_outer[3], 0.5f32.sqrt());
+        // --- narrowing the cone keeps the intensity
+        let narrow = SpotLight { outer_angle: cgmath::Rad(0.1), inner_angle: cgmath::Rad(0.05), ..spot };
+        assert_eq!(LightData::spot(&narrow, &matrix).color_and_intensity, data.color_and_intensity);
+    }
+
+    #[test]
+    fn area_lights_span_the_longest_axes() {
+        let emissive = cgmath::Vector3 { x: 2.0, y: 2.0, z: 2.0 };
+        let data = LightData::area(&area_light(false), emissive, &bounds(2.0, 0.1, 1.0), &translation(0.0, 3.0, 0.0));
+        assert_eq!(data.position_and_type, [0.0, 3.0, 0.0, LIGHT_TYPE_AREA]);
+        assert_vector(data.axis_x_and_cos_inner, 2.0, 0.0, 0.0);
+        assert_vector(data.axis_y_and_cos_outer, 0.0, 0.0, 1.0);
+        assert_vector(data.direction_and_range, 0.0, 1.0, 0.0);
+        assert_eq!(data.direction_and_range[3], 10.0);
+        assert_eq!(data.color_and_intensity, [2.0, 2.0, 2.0, 5.0]);
+
+        // --- scale decides which axis is shortest, and two sided lights get a negative range
+        let scale = cgmath::Matrix4::from_nonuniform_scale(0.01, 100.0, 1.0);
+        let data = LightData::area(&area_light(true), emissive, &bounds(2.0, 0.1, 1.0), &scale);
+        assert_vector(data.axis_x_and_cos_inner, 0.0, 10.0, 0.0);
+        assert_vector(data.axis_y_and_cos_outer, 0.0, 0.0, 1.0);
+        assert_vector(data.direction_and_range, 1.0, 0.0, 0.0);
+        assert_eq!(data.direction_and_range[3], -10.0);
+
+        // --- a flat box faces along its plane's normal
+        let data = LightData::area(&area_light(false), emissive, &bounds(2.0, 0.0, 1.0), &translation(0.0, 0.0, 0.0));
+        assert_vector(data.direction_and_range, 0.0, -1.0, 0.0);
+
+        // --- lines and points have no plane and fall back to local -z
+        for degenerate in [bounds(2.0, 0.0, 0.0), bounds(0.0, 0.0, 0.0)].iter() {
+            let data = LightData::area(&area_light(false), emissive, degenerate, &translation(1.0, 0.0, 0.0));
+            assert_vector(data.direction_and_range, 0.0, 0.0, -1.0);
+            assert!(data.axis_y_and_cos_outer.iter().all(|value| value.is_finite()));
+        }
+    }
+
+    #[test]
+    fn light_blocks_keep_at_most_max_lights() {
+        let settings = LightingSettings::default();
+        let identity = cgmath::Matrix4::identity();
+        let lights: Vec<LightData> = (0..MAX_LIGHTS + 10)
+            .map(|i| LightData::point(&point_light(i as f32), &translation(i as f32, 0.0, 0.0)))
+            .collect();
+
+        let block = LightBlock::new(&lights, identity, identity, &settings);
+        assert_eq!(block.count, [MAX_LIGHTS as u32, 0, 0, 0]);
+        assert_eq!(block.lights[MAX_LIGHTS - 1], lights[MAX_LIGHTS - 1]);
+        assert_close(block.ambient_and_exposure[3], settings.exposure());
+
+        let block = LightBlock::new(&lights[..3], identity, identity, &settings);
+        assert_eq!(block.count[0], 3);
+        assert_eq!(block.lights[2], lights[2]);
+        assert_eq!(block.lights[3], NO_LIGHT);
+    }
+
+    #[test]
+    fn gathered_lights_are_ordered_by_type_then_entity() {
+        let mut world = World::new();
+        world.insert_resource(LightBuffer::default());
+        let transform = |x: f32| components::Transform {
+            position: cgmath::Vector3 { x: x, y: 0.0, z: 0.0 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        };
+
+        world.create_entity().with(point_light(1.0)).with(transform(1.0)).build();
+        world
+            .create_entity()
+            .with(area_light(false))
+            .with(material::Materials::EmissiveWhite.get())
+            .with(bounds(1.0, 0.0, 1.0))
+            .with(transform(2.0))
+            .build();
+        // --- without Bounds an area light has no rectangle and is skipped
+        world.create_entity().with(area_light(false)).with(material::Materials::EmissiveWhite.get()).build();
+        world
+            .create_entity()
+            .with(DirectionalLight {
+                color: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+                illuminance: 1000.0,
+            })
+            .build();
+        world
+            .create_entity()
+            .with(SpotLight {
+                color: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+                intensity: 1.0,
+                range: 1.0,
+                inner_angle: cgmath::Rad(0.1),
+                outer_angle: cgmath::Rad(0.2),
+            })
+            .with(transform(5.0))
+            .build();
+        // --- the interpolated RenderTransform wins over the Transform
+        world
+            .create_entity()
+            .with(point_light(2.0))
+            .with(transform(3.0))
+            .with(components::RenderTransform { matrix: translation(4.0, 0.0, 0.0) })
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::RENDER_PREPARE, LightGatherSystem);
+        scheduler.run_stage(schedule::RENDER_PREPARE, &mut world);
+
+        let lights = &world.resource::<LightBuffer>().unwrap().lights;
+        let positions: Vec<[f32; 4]> = lights.iter().map(|light| light.position_and_type).collect();
+        assert_eq!(
+            positions,
+            vec![
+                [0.0, 0.0, 0.0, LIGHT_TYPE_DIRECTIONAL],
+                [1.0, 0.0, 0.0, LIGHT_TYPE_POINT],
+                [4.0, 0.0, 0.0, LIGHT_TYPE_POINT],
+                [5.0, 0.0, 0.0, LIGHT_TYPE_SPOT],
+                [2.0, 0.0, 0.0, LIGHT_TYPE_AREA],
+            ]
+        );
+    }
+}
@@ -12,19 +12,36 @@ mod world;
 mod render;
 mod demo;
 mod material;
+mod schedule;
+mod scene;
+mod spatial;
+mod physics;
+mod animation;
+mod particles;
+mod lighting;
+mod camera;
+mod input;
 
 use render::buffer::Buffer;
 
 use notify::{RecommendedWatcher, RecursiveMode, Result, Watcher};
 use rand::Rng;
+use std::collections::HashMap;
 use std::default::Default;
 use std::ffi::CString;
 use std::mem;
 use std::mem::align_of;
-use std::ops::Mul;
 use std::os::raw::c_void;
 use probability::prelude::*;
 
+// --- entities drawn by the gbuffer pass; uniform uploads and draws must agree on it
+type DrawableFilter = (
+    world::With<components::Transform>,
+    world::With<components::Mesh>,
+    world::With<components::Material>,
+    world::With<components::PBRMaterial>,
+);
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 struct ViewData {
@@ -99,27 +116,112 @@ fn create_dynamic_uniform_buffer(
     }
 }
 
+// --- swaps a dynamic uniform buffer for one with count slots and points the descriptor binding
+// --- at it; the contents are not carried over. The device must not be using the old buffer
+fn grow_dynamic_uniform_buffer(
+    device: &ash::Device,
+    mem_prop: &vk::PhysicalDeviceMemoryProperties,
+    buffer: &mut render::buffer::UniformBuffer,
+    mapped_memory: &mut *mut c_void,
+    alignment: u64,
+    count: u64,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+) {
+    unsafe {
+        device.unmap_memory(buffer.memory);
+        buffer.destroy(device);
+
+        let (grown, grown_mapped_memory, _) = create_dynamic_uniform_buffer(device, mem_prop, alignment, count, 0);
+        *buffer = grown;
+        *mapped_memory = grown_mapped_memory;
+
+        let write_descriptor_set = vk::WriteDescriptorSet::builder()
+            .dst_binding(binding)
+            .buffer_info(&[buffer.descriptor])
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .dst_set(descriptor_set)
+            .build();
+        device.update_descriptor_sets(&[write_descriptor_set], &[]);
+    }
+}
+
+// --- host visible per instance vertex buffer with room for count particles
+fn create_particle_instance_buffer(
+    device: &ash::Device,
+    mem_prop: &vk::PhysicalDeviceMemoryProperties,
+    count: u64,
+) -> render::buffer::VertexBuffer {
+    unsafe {
+        let buffer = render::buffer::VertexBuffer::construct(
+            device,
+            mem_prop,
+            count,
+            mem::size_of::<particles::ParticleInstance>() as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            true,
+        );
+        device
+            .bind_buffer_memory(buffer.buffer, buffer.memory, 0)
+            .unwrap();
+        buffer
+    }
+}
+
+// --- writes (slot, data) pairs into their aligned slots; slots not listed keep their contents.
+// --- Slots must be below the slot count the buffer was created with
 fn update_dynamic_uniform_buffer<T: Copy>(
     mapped_memory: *mut c_void,
     alignment: vk::DeviceSize,
-    size: vk::DeviceSize,
     memory: vk::DeviceMemory,
     device: &ash::Device,
-    instance_data: Vec<T>,
+    instance_data: Vec<(usize, T)>,
+    slot_count: u64,
 ) {
+    if instance_data.is_empty() {
+        return;
+    }
+
     unsafe {
-        let mut aligned_mapped_memory = Align::new(mapped_memory, alignment, size);
-        aligned_mapped_memory.copy_from_slice(&instance_data);
+        for (slot, data) in instance_data {
+            assert!((slot as u64) < slot_count, "Dynamic uniform buffer slot out of range!");
+            let slot_memory = (mapped_memory as *mut u8).add(slot * alignment as usize) as *mut T;
+            slot_memory.write(data);
+        }
 
         let memory_range = vk::MappedMemoryRange {
             memory: memory,
-            size: size,
+            size: vk::WHOLE_SIZE,
             ..Default::default()
         };
         device.flush_mapped_memory_ranges(&[memory_range]).unwrap();
     }
 }
 
+fn gbuffer_fragment_data(pbr_material: &components::PBRMaterial) -> GbufferFragmentData {
+    GbufferFragmentData {
+        albedo_and_roughness: cgmath::Vector4 {
+            x: pbr_material.albedo.x,
+            y: pbr_material.albedo.y,
+            z: pbr_material.albedo.z,
+            w: pbr_material.roughness,
+        },
+        reflectance_and_metalness: cgmath::Vector4 {
+            x: pbr_material.f0_reflectance.x,
+            y: pbr_material.f0_reflectance.y,
+            z: pbr_material.f0_reflectance.z,
+            w: pbr_material.metalness,
+        },
+        type_and_emissive: cgmath::Vector4 {
+            x: (pbr_material.material_type as u32) as f32,
+            y: pbr_material.emissive_color.x,
+            z: pbr_material.emissive_color.y,
+            w: pbr_material.emissive_color.z,
+        }
+    }
+}
+
 fn create_gbuffer(
     device: &ash::Device,
     device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
@@ -311,6 +413,12 @@ fn create_samplers(device: &ash::Device) -> (vk::Sampler, vk::Sampler) {
 
 fn main() {
     let mut world = world::World::new();
+    physics::register_components(&mut world);
+    animation::register_components(&mut world);
+    particles::register_components(&mut world);
+    lighting::register_components(&mut world);
+    camera::register_components(&mut world);
+    spatial::register_components(&mut world);
     unsafe {
         let demo_app = demo::DemoApp::new(1920, 1080);
         let mut demo = demo_app.build_ctx();
@@ -392,6 +500,9 @@ fn main() {
         demo.add_shader("copper/shaders/bin/gbuffer_frag.spv");
         demo.add_shader("copper/shaders/bin/deferred_vert.spv");
         demo.add_shader("copper/shaders/bin/deferred_frag.spv");
+        demo.add_shader("copper/shaders/bin/particle_mesh_vert.spv");
+        demo.add_shader("copper/shaders/bin/particle_billboard_vert.spv");
+        demo.add_shader("copper/shaders/bin/particle_frag.spv");
 
         // --- prepare for deferred
         let gbuffer = create_gbuffer(&demo.device, &demo.device_memory_properties, demo.surface_resolution.width, demo.surface_resolution.height);
@@ -483,204 +594,318 @@ fn main() {
                     binding: 4,
                     ..Default::default()
                 },
+                vk::DescriptorSetLayoutBinding {
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    binding: 5,
+                    ..Default::default()
+                },
             ]
         );
         let deferred_pipeline_layout = demo.create_pipeline_layout(deferred_descriptor_set_layout);
 
-        // --- create platonic solids, let's make an interesting scene
-        let copy_cb0 = demo.get_and_begin_command_buffer();
-        let icosahedron_mesh = geometry::mesh(
-            geometry::platonic::icosahedron(),
-            &demo.device,
-            &demo.device_memory_properties,
-            copy_cb0,
-            demo.present_queue,
-        );
-        let icosahedron = world
-            .create_entity()
-            .with_component(components::Component::TransformComponent(
-                components::Transform {
-                    position: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    rotation: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    scale: cgmath::Vector3 { x: 1.5, y: 1.5, z: 1.5, },
+        // --- scene from the file given on the command line, or the built-in one;
+        // --- `--save-scene <path>` writes the scene out as a starting point and
+        // --- `--prefabs <path>` (repeatable) registers prefabs and places their instances,
+        // --- `--replay-check <ticks>` checks that the fixed update replays bit for bit,
+        // --- `--bindings <path>` rebinds input actions and axes
+        let args: Vec<String> = std::env::args().collect();
+        let mut scene_path = None;
+        let mut bindings_path = None;
+        let mut save_scene_path = None;
+        let mut prefab_paths = vec![];
+        let mut replay_check_ticks = None;
+        let mut arg_index = 1;
+        while arg_index < args.len() {
+            match args[arg_index].as_str() {
+                "--save-scene" => {
+                    save_scene_path = args.get(arg_index + 1).cloned();
+                    arg_index += 1;
                 },
-            ))
-            .with_component(components::Component::MeshComponent(icosahedron_mesh))
-            .with_component(components::Component::VelocityComponent(
-                components::Velocity {
-                    translation_speed: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
-                    rotation_speed: cgmath::Vector3 { x: 1.5, y: 1.5, z: 1.5, },
+                "--prefabs" => {
+                    prefab_paths.extend(args.get(arg_index + 1).cloned());
+                    arg_index += 1;
                 },
-            ))
-            .with_component(components::Component::MaterialComponent(
-                components::Material {
-                    vertex_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_vert.spv"),
-                    fragment_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_frag.spv"),
-                    pso: vk::Pipeline::null(),
-                    render_pass: gbuffer.render_pass,
-                    pipeline_layout: gbuffer_pipeline_layout,
-                    color_blend_attachment_states: gbuffer_color_blend_attachment_states.clone(),
+                "--bindings" => {
+                    bindings_path = args.get(arg_index + 1).cloned();
+                    arg_index += 1;
                 },
-            ))
-            .with_component(components::Component::PBRMaterialComponent(
-                material::Materials::Gold.get()
-            ))
-            .build();
-
-        let mut source = source::default();
-        let distribution = Gaussian::new(0.0, 1.0);
-        let mut positions0: Vec<cgmath::Vector3<f32>> = vec![];
-        for i in 0..20 {
-            let mut sampler = Independent(&distribution, &mut source);
-            let samples = sampler.take(3).collect::<Vec<_>>();
-            positions0.push(cgmath::Vector3 { x: samples[0] as f32, y: samples[1] as f32, z: samples[2] as f32 });
+                "--replay-check" => {
+                    replay_check_ticks = args.get(arg_index + 1).and_then(|ticks| ticks.parse::<usize>().ok());
+                    arg_index += 1;
+                },
+                path => scene_path = Some(String::from(path)),
+            }
+            arg_index += 1;
         }
-        positions0.iter_mut().for_each(|pos| {
-            *pos = pos.normalize();
-        });
+
+        let gbuffer_material_source = components::MaterialSource {
+            vertex_shader: String::from("copper/shaders/bin/gbuffer_vert.spv"),
+            fragment_shader: String::from("copper/shaders/bin/gbuffer_frag.spv"),
+        };
+
+        if let Some(scene_path) = scene_path {
+            let scene = scene::load(std::path::Path::new(&scene_path))
+                .expect("Failed to load scene!");
+            scene::spawn(&mut world, &scene).expect("Failed to spawn scene!");
+        } else {
+            // --- create platonic solids, let's make an interesting scene
+            let prefab = |mesh: &str, scale: f32, translation_speed: f32, rotation_speed: f32, pbr_material: material::Materials| {
+                scene::prefab::Prefab::new(scene::EntityDescription {
+                    name: None,
+                    tags: vec![],
+                    transform: Some(components::Transform {
+                        position: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
+                        rotation: cgmath::Quaternion::one(),
+                        scale: cgmath::Vector3 { x: scale, y: scale, z: scale, },
+                    }),
+                    velocity: Some(components::Velocity {
+                        translation_speed: cgmath::Vector3 { x: translation_speed, y: translation_speed, z: translation_speed, },
+                        rotation_speed: cgmath::Vector3 { x: 1.5 * rotation_speed, y: 1.5 * rotation_speed, z: 1.5 * rotation_speed, },
+                    }),
+                    mesh: Some(String::from(mesh)),
+                    material: Some(gbuffer_material_source.clone()),
+                    pbr_material: Some(pbr_material.get()),
+                    parent: None,
+                })
+            };
+            world.register_prefab("icosahedron", prefab("icosahedron", 1.5, 0.0, 0.0, material::Materials::Gold));
+            world.register_prefab("dodecahedron", prefab("dodecahedron", 0.25, 0.0, 1.0, material::Materials::RoughCopper));
+            world.register_prefab("slab", prefab("cube", 1.0, 0.0, 0.0, material::Materials::RoughPlastic));
+
+            let icosahedron = world
+                .spawn_prefab("icosahedron", scene::prefab::PrefabOverrides::new().name("icosahedron"))
+                .expect("Failed to spawn prefab!")
+                .unwrap();
+
+            let mut source = source::default();
+            let distribution = Gaussian::new(0.0, 1.0);
+            let mut positions0: Vec<cgmath::Vector3<f32>> = vec![];
+            for i in 0..20 {
+                let mut sampler = Independent(&distribution, &mut source);
+                let samples = sampler.take(3).collect::<Vec<_>>();
+                positions0.push(cgmath::Vector3 { x: samples[0] as f32, y: samples[1] as f32, z: samples[2] as f32 });
+            }
+            positions0.iter_mut().for_each(|pos| {
+                *pos = pos.normalize();
+            });
         
-        for pos in positions0 {
-            let copy_cb = demo.get_and_begin_command_buffer();
-            let dodecahedron_mesh = geometry::mesh(
-                geometry::platonic::dodecahedron(),
-                &demo.device,
-                &demo.device_memory_properties,
-                copy_cb,
-                demo.present_queue,
-            );
-            let dodecahedron = world
+            for pos in positions0 {
+                world
+                    .spawn_prefab("dodecahedron", scene::prefab::PrefabOverrides::new().position(pos * 3.0))
+                    .expect("Failed to spawn prefab!");
+            }
+
+            let mut rng = rand::thread_rng();
+
+            let mut positions2: Vec<cgmath::Vector3<f32>> = vec![];
+            for i in -15..15 {
+                for j in 0..5 {
+                    let anchor_x = i as f32;
+                    let coords_x = [ anchor_x - 0.5, anchor_x - 0.25, anchor_x, anchor_x + 0.25, anchor_x + 0.5 ];
+                    let anchor_z = j as f32;
+                    let coords_z = [ anchor_z - 0.5, anchor_z - 0.25, anchor_z, anchor_z + 0.25, anchor_z + 0.5 ];
+                    positions2.push(cgmath::Vector3 { x: coords_x[0], y: 3.0, z: coords_z[0] });
+                    positions2.push(cgmath::Vector3 { x: coords_x[1], y: 3.0, z: coords_z[1] });
+                    positions2.push(cgmath::Vector3 { x: coords_x[2], y: 3.0, z: coords_z[2] });
+                    positions2.push(cgmath::Vector3 { x: coords_x[3], y: 3.0, z: coords_z[3] });
+                } 
+            }
+
+            world.spawn_prefab(
+                "slab",
+                scene::prefab::PrefabOverrides::new()
+                    .name("ground_plane")
+                    .position(cgmath::Vector3 { x: 0.0, y: 4.0, z: 0.0, })
+                    .scale(cgmath::Vector3 { x: 20.15, y: 0.15, z: 20.15, }),
+            ).expect("Failed to spawn prefab!");
+
+            world.spawn_prefab(
+                "slab",
+                scene::prefab::PrefabOverrides::new()
+                    .name("pillar_light")
+                    .position(cgmath::Vector3 { x: 8.0, y: 4.0, z: 0.0, })
+                    .scale(cgmath::Vector3 { x: 0.15, y: 10.15, z: 4.15, })
+                    .pbr_material(material::Materials::EmissiveWhite.get()),
+            ).expect("Failed to spawn prefab!");
+
+            // --- initialize rotations for objects with transforms
+            world
+                .query::<&mut components::Transform>()
+                .for_each(|mut transform| {
+                    transform.rotation = components::Transform::euler(cgmath::Vector3 {
+                        x: rng.gen_range(-1.0, 1.0),
+                        y: rng.gen_range(-1.0, 1.0),
+                        z: rng.gen_range(-1.0, 1.0),
+                    } * 2.0 * 3.14);
+                });
+
+            for name in ["ground_plane", "pillar_light"].iter() {
+                let entity = world.find_by_name(name).unwrap();
+                world
+                    .get_mut::<components::Transform>(entity)
+                    .unwrap()
+                    .rotation = cgmath::Quaternion::one();
+                world.insert(entity, physics::Collider::cuboid(cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }));
+            }
+
+            // --- the icosahedron keeps spinning about its diagonal, the light pillar slowly pulses
+            let start = world.get::<components::Transform>(icosahedron).unwrap().rotation;
+            let axis = cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }.normalize();
+            let spin = (0..4).fold(animation::Track::new(animation::Interpolation::Linear), |track, key| {
+                let turn = cgmath::Quaternion::from_axis_angle(axis, cgmath::Rad(key as f32 * 2.0 * 3.14159 / 3.0));
+                track.key(key as f32 * 0.8, turn * start)
+            });
+            world.insert(icosahedron, animation::Animation::new(animation::WrapMode::Loop).rotation(spin));
+
+            let pulse = animation::Track::new(animation::Interpolation::CubicHermite)
+                .key(0.0, cgmath::Vector3 { x: 1.5, y: 1.5, z: 1.5 })
+                .key(2.0, cgmath::Vector3 { x: 2.5, y: 2.5, z: 2.5 });
+            let pillar_light = world.find_by_name("pillar_light").unwrap();
+            world.insert(pillar_light, animation::Animation::new(animation::WrapMode::PingPong).emissive_color(pulse));
+
+            // --- sparks rising off the ground at the foot of the light pillar; -y is up on screen
+            let sparks = particles::ParticleEmitter::new(40.0, 0x5eed)
+                .lifetime(particles::Distribution::Uniform { min: 0.8, max: 1.6 })
+                .velocity(particles::VelocityDistribution::Cone {
+                    axis: cgmath::Vector3 { x: 0.0, y: -1.0, z: 0.0 },
+                    angle: 0.4,
+                    speed: particles::Distribution::Gaussian { mean: 4.0, deviation: 0.75 },
+                })
+                .color(
+                    animation::Track::new(animation::Interpolation::Linear)
+                        .key(0.0, cgmath::Vector3 { x: 4.0, y: 2.5, z: 1.0 })
+                        .key(1.0, cgmath::Vector3 { x: 0.6, y: 0.1, z: 0.0 }),
+                )
+                .size(animation::Track::new(animation::Interpolation::Linear).key(0.0, 0.08).key(1.0, 0.0))
+                .gravity(cgmath::Vector3 { x: 0.0, y: 4.0, z: 0.0 })
+                .shape(particles::ParticleShape::Mesh(String::from("tetrahedron")));
+            world
                 .create_entity()
-                .with_component(components::Component::TransformComponent(
-                    components::Transform {
-                        position: cgmath::Vector3 { x: pos.x * 3.0, y: pos.y * 3.0, z: pos.z * 3.0, },
-                        rotation: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                        scale: cgmath::Vector3 { x: 0.25, y: 0.25, z: 0.25, },
-                    },
-                ))
-                .with_component(components::Component::MeshComponent(dodecahedron_mesh))
-                .with_component(components::Component::VelocityComponent(
-                    components::Velocity {
-                        translation_speed: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
-                        rotation_speed: cgmath::Vector3 { x: 1.5, y: 1.5, z: 1.5, },
-                    },
-                ))
-                .with_component(components::Component::MaterialComponent(
-                    components::Material {
-                        vertex_shader: demo
-                            .get_shader_module("copper/shaders/bin/gbuffer_vert.spv"),
-                        fragment_shader: demo
-                            .get_shader_module("copper/shaders/bin/gbuffer_frag.spv"),
-                        pso: vk::Pipeline::null(),
-                        render_pass: gbuffer.render_pass,
-                        pipeline_layout: gbuffer_pipeline_layout,
-                        color_blend_attachment_states: gbuffer_color_blend_attachment_states.clone(),
-                    },
-                ))
-                .with_component(components::Component::PBRMaterialComponent(
-                    material::Materials::RoughCopper.get()
-                ))
+                .with(components::Name::new("sparks"))
+                .with(components::Transform {
+                    position: cgmath::Vector3 { x: 7.5, y: 3.8, z: 0.0, },
+                    rotation: cgmath::Quaternion::one(),
+                    scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
+                })
+                .with(sparks)
                 .build();
+
+            // --- a low sun from the side, a warm point light under the icosahedron and a spot
+            // --- looking down onto the ground plane from above the camera
+            let mut sun = components::Transform {
+                position: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
+                rotation: cgmath::Quaternion::one(),
+                scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
+            };
+            sun.look_at(cgmath::Vector3 { x: 1.0, y: 0.6, z: 0.5 }, cgmath::Vector3 { x: 0.0, y: -1.0, z: 0.0 });
+            world
+                .create_entity()
+                .with(components::Name::new("sun"))
+                .with(sun)
+                .with(lighting::DirectionalLight {
+                    color: cgmath::Vector3 { x: 1.0, y: 0.95, z: 0.85 },
+                    illuminance: 40.0,
+                })
+                .build();
+            world
+                .create_entity()
+                .with(components::Name::new("warm_light"))
+                .with(components::Transform {
+                    position: cgmath::Vector3 { x: 0.0, y: 2.5, z: 0.0, },
+                    rotation: cgmath::Quaternion::one(),
+                    scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
+                })
+                .with(lighting::PointLight {
+                    color: cgmath::Vector3 { x: 1.0, y: 0.6, z: 0.3 },
+                    intensity: 8000.0,
+                    range: 12.0,
+                })
+                .build();
+            let mut spot = components::Transform {
+                position: cgmath::Vector3 { x: -6.0, y: -4.0, z: 4.0, },
+                rotation: cgmath::Quaternion::one(),
+                scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
+            };
+            spot.look_at(cgmath::Vector3 { x: -6.0, y: 4.0, z: 2.0 }, cgmath::Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+            world
+                .create_entity()
+                .with(components::Name::new("spot_light"))
+                .with(spot)
+                .with(lighting::SpotLight {
+                    color: cgmath::Vector3 { x: 0.7, y: 0.8, z: 1.0 },
+                    intensity: 12000.0,
+                    range: 20.0,
+                    inner_angle: cgmath::Rad(0.3),
+                    outer_angle: cgmath::Rad(0.5),
+                })
+                .build();
+
+            // --- the dodecahedrons drop onto the ground plane and bounce off the spinning icosahedron
+            let solids: Vec<(components::Entity, String, cgmath::Vector3<f32>)> = world
+                .query::<(components::Entity, &components::MeshSource, &components::Transform)>()
+                .filter(|(_, mesh, _)| mesh.name != "cube")
+                .map(|(entity, mesh, transform)| (entity, mesh.name.clone(), transform.scale))
+                .collect();
+            for (entity, mesh, scale) in solids {
+                let collider = physics::Collider::from_mesh_name(&mesh).expect("Failed to build collider!");
+                if mesh == "dodecahedron" {
+                    world.insert(entity, physics::RigidBody::new(1.0, &collider, scale));
+                }
+                world.insert(entity, collider);
+            }
         }
 
-        let mut rng = rand::thread_rng();
-
-        let mut positions2: Vec<cgmath::Vector3<f32>> = vec![];
-        for i in -15..15 {
-            for j in 0..5 {
-                let anchor_x = i as f32;
-                let coords_x = [ anchor_x - 0.5, anchor_x - 0.25, anchor_x, anchor_x + 0.25, anchor_x + 0.5 ];
-                let anchor_z = j as f32;
-                let coords_z = [ anchor_z - 0.5, anchor_z - 0.25, anchor_z, anchor_z + 0.25, anchor_z + 0.5 ];
-                positions2.push(cgmath::Vector3 { x: coords_x[0], y: 3.0, z: coords_z[0] });
-                positions2.push(cgmath::Vector3 { x: coords_x[1], y: 3.0, z: coords_z[1] });
-                positions2.push(cgmath::Vector3 { x: coords_x[2], y: 3.0, z: coords_z[2] });
-                positions2.push(cgmath::Vector3 { x: coords_x[3], y: 3.0, z: coords_z[3] });
-            } 
+        for prefab_path in prefab_paths {
+            let prefabs = scene::prefab::load(std::path::Path::new(&prefab_path))
+                .expect("Failed to load prefabs!");
+            scene::prefab::spawn(&mut world, &prefabs).expect("Failed to spawn prefabs!");
         }
 
-        let copy_cb1 = demo.get_and_begin_command_buffer();
-        let cube_mesh = geometry::mesh(
-            geometry::platonic::cube(),
-            &demo.device,
-            &demo.device_memory_properties,
-            copy_cb1,
-            demo.present_queue,
-        );
-        let ground_plane = world
-            .create_entity()
-            .with_component(components::Component::TransformComponent(
-                components::Transform {
-                    position: cgmath::Vector3 { x: 0.0, y: 4.0, z: 0.0, },
-                    rotation: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    scale: cgmath::Vector3 { x: 20.15, y: 0.15, z: 20.15, },
-                },
-            ))
-            .with_component(components::Component::MeshComponent(cube_mesh))
-            .with_component(components::Component::VelocityComponent(
-                components::Velocity {
-                    translation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                },
-            ))
-            .with_component(components::Component::MaterialComponent(
-                components::Material {
-                    vertex_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_vert.spv"),
-                    fragment_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_frag.spv"),
-                    pso: vk::Pipeline::null(),
-                    render_pass: gbuffer.render_pass,
-                    pipeline_layout: gbuffer_pipeline_layout,
-                    color_blend_attachment_states: gbuffer_color_blend_attachment_states.clone(),
-                },
-            ))
-            .with_component(components::Component::PBRMaterialComponent(
-                material::Materials::RoughPlastic.get()
-            ))
-            .build();
+        // --- emissive meshes such as the light pillar light their surroundings
+        lighting::add_emissive_area_lights(&mut world, 60.0, 30.0);
 
-        let copy_cb2 = demo.get_and_begin_command_buffer();
-        let cube_mesh2 = geometry::mesh(
-            geometry::platonic::cube(),
-            &demo.device,
-            &demo.device_memory_properties,
-            copy_cb2,
-            demo.present_queue,
-        );
-        let pillar_light = world
-            .create_entity()
-            .with_component(components::Component::TransformComponent(
-                components::Transform {
-                    position: cgmath::Vector3 { x: 8.0, y: 4.0, z: 0.0, },
-                    rotation: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    scale: cgmath::Vector3 { x: 0.15, y: 10.15, z: 4.15, },
-                },
-            ))
-            .with_component(components::Component::MeshComponent(cube_mesh2))
-            .with_component(components::Component::VelocityComponent(
-                components::Velocity {
-                    translation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                    rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0, },
-                },
-            ))
-            .with_component(components::Component::MaterialComponent(
-                components::Material {
-                    vertex_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_vert.spv"),
-                    fragment_shader: demo
-                        .get_shader_module("copper/shaders/bin/gbuffer_frag.spv"),
-                    pso: vk::Pipeline::null(),
-                    render_pass: gbuffer.render_pass,
-                    pipeline_layout: gbuffer_pipeline_layout,
-                    color_blend_attachment_states: gbuffer_color_blend_attachment_states.clone(),
-                },
-            ))
-            .with_component(components::Component::PBRMaterialComponent(
-                material::Materials::EmissiveWhite.get()
-            ))
-            .build();
+        // --- GPU side of everything spawned above: shaders first, then meshes and materials
+        let shader_paths: Vec<String> = world
+            .query::<&components::MaterialSource>()
+            .flat_map(|source| vec![source.vertex_shader.clone(), source.fragment_shader.clone()])
+            .collect();
+        for shader_path in shader_paths {
+            if !demo.has_shader_module(&shader_path) {
+                demo.add_shader(&shader_path);
+            }
+        }
+
+        scene::build_gpu_resources(
+            &mut world,
+            |name| {
+                geometry::generate(name).map(|geometry| {
+                    geometry::mesh(
+                        geometry,
+                        &demo.device,
+                        &demo.device_memory_properties,
+                        demo.get_and_begin_command_buffer(),
+                        demo.present_queue,
+                    )
+                })
+            },
+            |source| components::Material {
+                vertex_shader: demo.get_shader_module(&source.vertex_shader),
+                fragment_shader: demo.get_shader_module(&source.fragment_shader),
+                pso: vk::Pipeline::null(),
+                render_pass: gbuffer.render_pass,
+                pipeline_layout: gbuffer_pipeline_layout,
+                color_blend_attachment_states: gbuffer_color_blend_attachment_states.clone(),
+            },
+        )
+        .expect("Failed to build scene resources!");
+
+        if let Some(save_scene_path) = save_scene_path {
+            scene::save(&mut world, std::path::Path::new(&save_scene_path))
+                .expect("Failed to save scene!");
+        }
+        let object_count = world.query::<&components::Transform>().count() as u64;
 
         let deferred_light = world
             .create_entity()
@@ -698,7 +923,6 @@ fn main() {
             ))
             .build();
 
-        let mesh_filter = components::ComponentType::MeshComponent as u32;
         // --- initialize ray-tracing geometry for our scene and build acceleration structures
         
         // let raytracing_geometry: Vec<vk::GeometryNV> = world
@@ -777,38 +1001,6 @@ fn main() {
 
         // let bl_acceleration_struct_handle = demo.raytracing.get_acceleration_structure_handle(bl_acceleration_struct).unwrap();
 
-        // --- initialize rotations for objects with transforms
-        let mut object_count = 0;
-        let transform_filter = components::ComponentType::TransformComponent as u32;
-        let pbr_filter = components::ComponentType::PBRMaterialComponent as u32;
-        world
-            .transform_storage
-            .iter_mut()
-            .filter(|entry| entry.storage_type & transform_filter == transform_filter)
-            .for_each(|entry| {
-                entry.component.rotation = cgmath::Vector3 {
-                    x: rng.gen_range(-1.0, 1.0),
-                    y: rng.gen_range(-1.0, 1.0),
-                    z: rng.gen_range(-1.0, 1.0),
-                } * 2.0 * 3.14;
-                object_count += 1;
-            });
-
-        world
-            .transform_storage
-            .iter_mut()
-            .find(|entry| entry.entity == ground_plane)
-            .unwrap()
-            .component
-            .rotation = cgmath::Vector3 { x:0.0, y:0.0, z:0.0 };
-
-        world
-            .transform_storage
-            .iter_mut()
-            .find(|entry| entry.entity == pillar_light)
-            .unwrap()
-            .component
-            .rotation = cgmath::Vector3 { x:0.0, y:0.0, z:0.0 };
 
         // --- now that transforms are in place, start creating the top-level acceleration structure
         // let rt_geo_instances: Vec<geometry::RayTracingInstance> = world
@@ -1065,19 +1257,21 @@ fn main() {
             .limits
             .min_uniform_buffer_offset_alignment;
 
-        let (ub_gbuffer_vs, mem_ub_gbuffer_vs, stride_ub_gbuffer_vs) = create_dynamic_uniform_buffer(
+        // --- one slot per drawable; both buffers grow together when more drawables appear
+        let mut ub_gbuffer_capacity = object_count.max(1);
+        let (mut ub_gbuffer_vs, mut mem_ub_gbuffer_vs, stride_ub_gbuffer_vs) = create_dynamic_uniform_buffer(
             &demo.device, 
             &demo.device_memory_properties,
             std::mem::size_of::<cgmath::Matrix4<f32>>() as u64,
-            object_count,
+            ub_gbuffer_capacity,
             min_ub_alignment
         );
 
-        let (ub_gbuffer_fs, mem_ub_gbuffer_fs, stride_ub_gbuffer_fs) = create_dynamic_uniform_buffer(
+        let (mut ub_gbuffer_fs, mut mem_ub_gbuffer_fs, stride_ub_gbuffer_fs) = create_dynamic_uniform_buffer(
             &demo.device, 
             &demo.device_memory_properties,
             std::mem::size_of::<GbufferFragmentData>() as u64,
-            object_count,
+            ub_gbuffer_capacity,
             min_ub_alignment
         );
 
@@ -1095,6 +1289,33 @@ fn main() {
             .bind_buffer_memory(ub_view_data.descriptor.buffer, ub_view_data.memory, 0)
             .unwrap();
 
+        // --- lights for the deferred pass, rewritten every frame
+        let sb_lights = render::buffer::UniformBuffer::construct(
+            &demo.device,
+            &demo.device_memory_properties,
+            1,
+            mem::size_of::<lighting::LightBlock>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            false,
+        );
+        demo.device
+            .bind_buffer_memory(sb_lights.descriptor.buffer, sb_lights.memory, 0)
+            .unwrap();
+
+        // --- per instance vertex buffer for particles, sized for every emitter at its limit;
+        // --- grows when emitters are added or raise their limit
+        let mut particle_capacity = world
+            .query::<&particles::ParticleEmitter>()
+            .map(|emitter| emitter.max_particles as u64)
+            .sum::<u64>()
+            .max(1);
+        let mut vb_particle_instances = create_particle_instance_buffer(
+            &demo.device,
+            &demo.device_memory_properties,
+            particle_capacity,
+        );
+
         let ub_view_data_ptr = demo
             .device
             .map_memory(
@@ -1104,22 +1325,40 @@ fn main() {
                 vk::MemoryMapFlags::empty(),
             )
             .unwrap();
-        update_viewdata_uniform_buffer(
-            ub_view_data_ptr,
-            mem::size_of::<ViewData>() as u64,
-            ub_view_data.descriptor.range,
-            cgmath::perspective(
-                cgmath::Rad::from(cgmath::Deg(90.0)),
-                demo.surface_resolution.width as f32 / demo.surface_resolution.height as f32,
-                0.1,
-                256.0,
-            ),
-            cgmath::Matrix4::look_at(
-                cgmath::Point3::new(0.0, 0.0, -10.0),
-                cgmath::Point3::new(0.0, 0.0, 5.0),
-                cgmath::Vector3::new(0.0, 1.0, 0.0),
-            )
-        );
+        // --- scenes without a camera are looked at from in front of the origin
+        let main_camera = match world.query::<(components::Entity, &camera::Camera)>().map(|(entity, _)| entity).next() {
+            Some(entity) => entity,
+            None => {
+                let mut transform = components::Transform {
+                    position: cgmath::Vector3 { x: 0.0, y: 0.0, z: -10.0, },
+                    rotation: cgmath::Quaternion::one(),
+                    scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0, },
+                };
+                transform.look_at(cgmath::Vector3 { x: 0.0, y: 0.0, z: 5.0 }, cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 });
+                world
+                    .create_entity()
+                    .with(components::Name::new("main_camera"))
+                    .with(transform)
+                    .with(camera::Camera::perspective(cgmath::Rad::from(cgmath::Deg(90.0)), 0.1, 256.0))
+                    .with(camera::FlyController::default())
+                    .build()
+            },
+        };
+        world.insert_resource(camera::ActiveCamera { entity: Some(main_camera) });
+        world.insert_resource(input::Input::new());
+        // --- Tab switches the active camera between flying and orbiting the icosahedron
+        let mut input_map = camera::controller::bindings()
+            .action("switch_camera_controller", input::Button::Key(winit::VirtualKeyCode::Tab));
+        if let Some(bindings_path) = bindings_path {
+            input_map.rebind(input::action::load(std::path::Path::new(&bindings_path)).expect("Failed to load bindings!"));
+        }
+        world.insert_resource(input_map);
+        world.insert_resource(input::ActionState::new());
+        world.insert_resource(camera::CameraView::default());
+        world.insert_resource(camera::Surface {
+            width: demo.surface_resolution.width,
+            height: demo.surface_resolution.height,
+        });
 
         let viewports = [vk::Viewport {
             x: 0.0,
@@ -1144,21 +1383,18 @@ fn main() {
         );
 
         // --- build PSOs for objects
-        let material_filter = components::ComponentType::MaterialComponent as u32;
         world
-            .material_storage
-            .iter_mut()
-            .filter(|entry| entry.storage_type & material_filter == material_filter)
-            .for_each(|entry| {
-                entry.component.pso = demo.create_pso(
-                    entry.component.vertex_shader,
-                    entry.component.fragment_shader,
-                    entry.component.render_pass,
-                    entry.component.pipeline_layout,
+            .query::<(&mut components::Material, Option<&components::Mesh>)>()
+            .for_each(|(mut material, mesh)| {
+                material.pso = demo.create_pso(
+                    material.vertex_shader,
+                    material.fragment_shader,
+                    material.render_pass,
+                    material.pipeline_layout,
                     viewports,
                     scissors,
-                    &entry.component.color_blend_attachment_states,
-                    if entry.storage_type & mesh_filter == mesh_filter {
+                    &material.color_blend_attachment_states,
+                    if mesh.is_some() {
                         demo::PSOCreateOption::HasVertexAttributes
                     } else {
                         demo::PSOCreateOption::NoVertexAttributes
@@ -1166,6 +1402,27 @@ fn main() {
                 );
             });
 
+        // --- particles go into the gbuffer as instanced meshes; the meshes are built the
+        // --- first time an emitter with their shape has live particles
+        let mut particle_mesh_vs = demo.get_shader_module("copper/shaders/bin/particle_mesh_vert.spv");
+        let mut particle_billboard_vs = demo.get_shader_module("copper/shaders/bin/particle_billboard_vert.spv");
+        let mut particle_fs = demo.get_shader_module("copper/shaders/bin/particle_frag.spv");
+        let create_particle_pso = |demo: &demo::DemoContext, vertex_shader: vk::ShaderModule, fragment_shader: vk::ShaderModule| {
+            demo.create_pso(
+                vertex_shader,
+                fragment_shader,
+                gbuffer.render_pass,
+                gbuffer_pipeline_layout,
+                viewports,
+                scissors,
+                &gbuffer_color_blend_attachment_states,
+                demo::PSOCreateOption::HasVertexAndInstanceAttributes,
+            )
+        };
+        let mut particle_mesh_pso = create_particle_pso(&demo, particle_mesh_vs, particle_fs);
+        let mut particle_billboard_pso = create_particle_pso(&demo, particle_billboard_vs, particle_fs);
+        let mut particle_meshes: HashMap<String, components::Mesh> = HashMap::new();
+
         // --- setup descriptor pool
         let descriptor_pool_sizes = vec![
             vk::DescriptorPoolSize {
@@ -1180,6 +1437,10 @@ fn main() {
                 ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
                 descriptor_count: 5,
             },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            },
         ];
         demo.create_descriptor_pool(descriptor_pool_sizes);
 
@@ -1247,6 +1508,12 @@ fn main() {
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                 .dst_set(deferred_descriptor_sets[0])
                 .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_binding(5)
+                .buffer_info(&[sb_lights.descriptor])
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .dst_set(deferred_descriptor_sets[0])
+                .build(),
         ];
 
         demo.device.update_descriptor_sets(&deferred_write_descriptor_sets, &[]);
@@ -1286,16 +1553,103 @@ fn main() {
         let semaphore_create_info = vk::SemaphoreCreateInfo::default();
         let gbuffer_semaphore = demo.device.create_semaphore(&semaphore_create_info, None).unwrap();      
 
-        let dt: f32 = 1.0 / 60.0;
+        world.insert_resource(components::Time {
+            fixed_dt: 1.0 / 60.0,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
         let mut current_time = std::time::SystemTime::now();
-        let mut accumulator: f32 = 0.0;
+
+        let mut uploaded_drawables: Vec<components::Entity> = vec![];
+        let mut drawable_slots: HashMap<components::Entity, usize> = HashMap::new();
+
+        let mut retired_components: Vec<Box<dyn std::any::Any>> = vec![];
+
+        world.insert_resource(spatial::SpatialIndex::default());
+        world.insert_resource(particles::ParticleInstances::default());
+        world.insert_resource(lighting::LightBuffer::default());
+        world.insert_resource(lighting::LightingSettings {
+            ambient: cgmath::Vector3 { x: 0.03, y: 0.03, z: 0.04 },
+            ..lighting::LightingSettings::default()
+        });
+        // --- the projection is not flipped for Vulkan's clip space, so +y points down on screen
+        world.insert_resource(physics::PhysicsSettings {
+            gravity: cgmath::Vector3 { x: 0.0, y: 9.81, z: 0.0 },
+            ..physics::PhysicsSettings::default()
+        });
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, world::hierarchy::TransformHistorySystem);
+        scheduler.add_system(schedule::FIXED_UPDATE, physics::MovementSystem);
+        scheduler.add_system(schedule::FIXED_UPDATE, animation::AnimationSystem);
+        scheduler.add_system(schedule::FIXED_UPDATE, physics::PhysicsSystem::new());
+        scheduler.add_system(schedule::FIXED_UPDATE, particles::ParticleSystem);
+        scheduler.add_system(schedule::POST_UPDATE, world::hierarchy::TransformPropagationSystem);
+        scheduler.add_system(schedule::POST_UPDATE, spatial::SpatialIndexSystem);
+        scheduler.add_system(schedule::RENDER_PREPARE, camera::controller::CameraControllerSystem);
+        scheduler.add_system(schedule::RENDER_PREPARE, world::hierarchy::TransformInterpolationSystem);
+        scheduler.add_system(schedule::RENDER_PREPARE, particles::ParticleInstanceSystem);
+        scheduler.add_system(schedule::RENDER_PREPARE, lighting::LightGatherSystem);
+        scheduler.add_system(schedule::RENDER_PREPARE, camera::CameraSystem);
+
+        // --- simulate, rewind and simulate again before the first frame; the scene then
+        // --- starts from where it was before the check
+        if let Some(ticks) = replay_check_ticks {
+            let step = |scheduler: &mut schedule::Schedule, world: &mut world::World| {
+                scheduler.run_stage(schedule::FIXED_UPDATE, world);
+                scheduler.run_stage(schedule::POST_UPDATE, world);
+            };
+            let scheduler_start = scheduler.snapshot();
+            let recording = world.record(ticks, |world, _tick| step(&mut scheduler, world));
+            scheduler.restore(&scheduler_start);
+            retired_components.extend(
+                world
+                    .replay(&recording, |world, _tick| step(&mut scheduler, world))
+                    .expect("Fixed update is not deterministic!"),
+            );
+            scheduler.restore(&scheduler_start);
+            retired_components.extend(world.restore(recording.start()));
+            retired_components.extend(scheduler.take_removed());
+        }
 
         let shader_asset_bin_path: String = String::from("copper/shaders/bin");
         demo.watcher
             .watch(shader_asset_bin_path.clone(), RecursiveMode::Recursive)
             .unwrap();
 
-        demo_app.run(|| {
+        demo_app.run(|events| {
+            {
+                let input = world.resource_mut::<input::Input>().unwrap();
+                input.begin_frame();
+                for event in events {
+                    input.handle_event(event);
+                }
+            }
+            {
+                let mut actions = world.remove_resource::<input::ActionState>().unwrap();
+                actions.update(world.resource::<input::InputMap>().unwrap(), world.resource::<input::Input>().unwrap());
+                world.insert_resource(actions);
+            }
+
+            let switch_controller = world.resource::<input::ActionState>().unwrap().pressed("switch_camera_controller");
+            if let (true, Some(active)) = (switch_controller, world.resource::<camera::ActiveCamera>().unwrap().entity) {
+                if world.remove_component::<camera::FlyController>(active).is_some() {
+                    let target = world.find_by_name("icosahedron");
+                    let target_position = target
+                        .and_then(|target| world.get::<components::Transform>(target))
+                        .map(|transform| transform.position);
+                    let position = world.get::<components::Transform>(active).map(|transform| transform.position);
+                    let distance = match (target_position, position) {
+                        (Some(target_position), Some(position)) => (position - target_position).magnitude(),
+                        _ => 10.0,
+                    };
+                    world.insert(active, camera::OrbitController::new(target, distance));
+                } else {
+                    world.remove_component::<camera::OrbitController>(active);
+                    world.insert(active, camera::FlyController::default());
+                }
+            }
+
             let asset_key = demo.process_asset_event();
             demo.receive_asset_event();
 
@@ -1304,30 +1658,28 @@ fn main() {
                 let (old_shader_module, new_shader_module) = demo.reload_shader_module(&key);
 
                 world
-                    .material_storage
-                    .iter_mut()
-                    .filter(|entry| {
-                        (entry.storage_type & material_filter == material_filter)
-                            && (entry.component.vertex_shader == old_shader_module
-                                || entry.component.fragment_shader == old_shader_module)
+                    .query::<(&mut components::Material, Option<&components::Mesh>)>()
+                    .filter(|(material, _)| {
+                        material.vertex_shader == old_shader_module
+                            || material.fragment_shader == old_shader_module
                     })
-                    .for_each(|entry| {
-                        if entry.component.vertex_shader == old_shader_module {
-                            entry.component.vertex_shader = new_shader_module;
-                        } else if entry.component.fragment_shader == old_shader_module {
-                            entry.component.fragment_shader = new_shader_module;
+                    .for_each(|(mut material, mesh)| {
+                        if material.vertex_shader == old_shader_module {
+                            material.vertex_shader = new_shader_module;
+                        } else if material.fragment_shader == old_shader_module {
+                            material.fragment_shader = new_shader_module;
                         }
 
-                        demo.device.destroy_pipeline(entry.component.pso, None);
-                        entry.component.pso = demo.create_pso(
-                            entry.component.vertex_shader,
-                            entry.component.fragment_shader,
-                            entry.component.render_pass,
-                            entry.component.pipeline_layout,
+                        demo.device.destroy_pipeline(material.pso, None);
+                        material.pso = demo.create_pso(
+                            material.vertex_shader,
+                            material.fragment_shader,
+                            material.render_pass,
+                            material.pipeline_layout,
                             viewports,
                             scissors,
-                            &entry.component.color_blend_attachment_states,
-                            if entry.storage_type & mesh_filter == mesh_filter {
+                            &material.color_blend_attachment_states,
+                            if mesh.is_some() {
                                 demo::PSOCreateOption::HasVertexAttributes
                             } else {
                                 demo::PSOCreateOption::NoVertexAttributes
@@ -1335,14 +1687,27 @@ fn main() {
                         );
                     });
 
+                if [particle_mesh_vs, particle_billboard_vs, particle_fs].contains(&old_shader_module) {
+                    if particle_mesh_vs == old_shader_module {
+                        particle_mesh_vs = new_shader_module;
+                    } else if particle_billboard_vs == old_shader_module {
+                        particle_billboard_vs = new_shader_module;
+                    } else {
+                        particle_fs = new_shader_module;
+                    }
+
+                    demo.device.destroy_pipeline(particle_mesh_pso, None);
+                    demo.device.destroy_pipeline(particle_billboard_pso, None);
+                    particle_mesh_pso = create_particle_pso(&demo, particle_mesh_vs, particle_fs);
+                    particle_billboard_pso = create_particle_pso(&demo, particle_billboard_vs, particle_fs);
+                }
+
                 demo.device.destroy_shader_module(old_shader_module, None);
             }
 
-            let deferred_pso = world.material_storage
-                .iter_mut()
-                .find(|entry| entry.entity == deferred_light)
+            let deferred_pso = world
+                .get::<components::Material>(deferred_light)
                 .unwrap()
-                .component
                 .pso;
 
             let new_time = std::time::SystemTime::now();
@@ -1350,114 +1715,168 @@ fn main() {
                 new_time.duration_since(current_time).unwrap().as_millis() as f32 / 1000.0;
             current_time = new_time;
 
-            accumulator += frame_time;
-
-            while accumulator >= dt {
-                let transform_velocity_filter = (components::ComponentType::TransformComponent as u32) | (components::ComponentType::VelocityComponent as u32);
-
-                world
-                    .velocity_storage
-                    .iter()
-                    .filter(|entry| {
-                        entry.storage_type & transform_velocity_filter == transform_velocity_filter
-                    })
-                    .zip(world.transform_storage.iter_mut().filter(|entry| {
-                        entry.storage_type & transform_velocity_filter == transform_velocity_filter
-                    }))
-                    .for_each(|(velocity, transform)| {
-                        transform.component.rotation += cgmath::Vector3 {
-                            x: velocity.component.rotation_speed.x * dt,
-                            y: velocity.component.rotation_speed.y * dt,
-                            z: velocity.component.rotation_speed.z * dt,
-                        };
-                    });
+            let dt = {
+                let time = world.resource_mut::<components::Time>().unwrap();
+                time.frame_time = frame_time;
+                time.accumulator += frame_time;
+                time.fixed_dt
+            };
+
+            while world.resource::<components::Time>().unwrap().accumulator >= dt {
+                scheduler.run_stage(schedule::FIXED_UPDATE, &mut world);
+                scheduler.run_stage(schedule::POST_UPDATE, &mut world);
+                world.resource_mut::<components::Time>().unwrap().accumulator -= dt;
+            }
 
-                let transform_instance_data: Vec<cgmath::Matrix4<f32>> = world
-                    .transform_storage
-                    .iter()
-                    .filter(|entry| entry.storage_type & transform_filter == transform_filter)
-                    .map(|entry| {
-                        cgmath::Matrix4::from_translation(entry.component.position)
-                            .mul(cgmath::Matrix4::from_axis_angle(
-                                cgmath::Vector3 {
-                                    x: 1.0,
-                                    y: 0.0,
-                                    z: 0.0,
-                                },
-                                cgmath::Rad(entry.component.rotation.x),
-                            ))
-                            .mul(cgmath::Matrix4::from_axis_angle(
-                                cgmath::Vector3 {
-                                    x: 0.0,
-                                    y: 1.0,
-                                    z: 0.0,
-                                },
-                                cgmath::Rad(entry.component.rotation.y),
-                            ))
-                            .mul(cgmath::Matrix4::from_axis_angle(
-                                cgmath::Vector3 {
-                                    x: 0.0,
-                                    y: 0.0,
-                                    z: 1.0,
-                                },
-                                cgmath::Rad(entry.component.rotation.z),
-                            ))
-                            .mul(cgmath::Matrix4::from_nonuniform_scale(
-                                entry.component.scale.x, 
-                                entry.component.scale.y, 
-                                entry.component.scale.z
-                            ))
-                    })
-                    .collect();
+            // --- blends the last two fixed ticks by how far the frame is into the next one
+            scheduler.run_stage(schedule::RENDER_PREPARE, &mut world);
+
+            // --- batches index into this buffer through their first_instance; the previous frame
+            // --- has finished on the device (see queue_wait_idle below), so it can be replaced
+            let particle_instances = world.resource::<particles::ParticleInstances>().unwrap();
+            let particle_instance_data = particle_instances.instance_data();
+            let particle_draws: Vec<(particles::ParticleShape, u32, u32)> = particle_instances
+                .batches
+                .iter()
+                .map(|batch| (batch.shape.clone(), batch.first_instance, batch.instances.len() as u32))
+                .collect();
+            if particle_instance_data.len() as u64 > particle_capacity {
+                particle_capacity = (particle_instance_data.len() as u64).next_power_of_two();
+                vb_particle_instances.destroy(&demo.device);
+                vb_particle_instances = create_particle_instance_buffer(
+                    &demo.device,
+                    &demo.device_memory_properties,
+                    particle_capacity,
+                );
+            }
+            if !particle_instance_data.is_empty() {
+                render::buffer::copy_to_buffer(&demo.device, vb_particle_instances.memory, &particle_instance_data);
+            }
+            // --- shapes without a generated mesh are not drawn
+            for (shape, _, _) in particle_draws.iter() {
+                let name = shape.mesh_name();
+                if particle_meshes.contains_key(name) {
+                    continue;
+                }
+                if let Some(geometry) = geometry::generate(name) {
+                    let mesh = geometry::mesh(
+                        geometry,
+                        &demo.device,
+                        &demo.device_memory_properties,
+                        demo.get_and_begin_command_buffer(),
+                        demo.present_queue,
+                    );
+                    particle_meshes.insert(String::from(name), mesh);
+                }
+            }
 
-                update_dynamic_uniform_buffer(
-                    mem_ub_gbuffer_vs,
+            // --- components removed by system commands may still be used by frames in flight
+            retired_components.extend(scheduler.take_removed());
+
+            // --- instance data is laid out in the same order the gbuffer pass draws in; while
+            // --- that order holds only slots of changed entities are rewritten
+            let drawables: Vec<components::Entity> = world
+                .query_filtered::<components::Entity, DrawableFilter>()
+                .collect();
+            // --- the previous frame has finished on the device (see queue_wait_idle below), so
+            // --- the buffers can be replaced before this frame's command buffer is recorded
+            if drawables.len() as u64 > ub_gbuffer_capacity {
+                ub_gbuffer_capacity = (drawables.len() as u64).next_power_of_two();
+                grow_dynamic_uniform_buffer(
+                    &demo.device,
+                    &demo.device_memory_properties,
+                    &mut ub_gbuffer_vs,
+                    &mut mem_ub_gbuffer_vs,
                     stride_ub_gbuffer_vs,
-                    ub_gbuffer_vs.descriptor.range * transform_instance_data.len() as u64,
-                    ub_gbuffer_vs.memory,
+                    ub_gbuffer_capacity,
+                    gbuffer_descriptor_sets[0],
+                    1,
+                );
+                grow_dynamic_uniform_buffer(
                     &demo.device,
-                    transform_instance_data,
+                    &demo.device_memory_properties,
+                    &mut ub_gbuffer_fs,
+                    &mut mem_ub_gbuffer_fs,
+                    stride_ub_gbuffer_fs,
+                    ub_gbuffer_capacity,
+                    gbuffer_descriptor_sets[0],
+                    2,
                 );
+                uploaded_drawables.clear();
+            }
+            let full_upload = drawables != uploaded_drawables;
+            if full_upload {
+                drawable_slots = drawables.iter().enumerate().map(|(slot, entity)| (*entity, slot)).collect();
+                uploaded_drawables = drawables;
+            }
 
-                let pbr_instance_data: Vec<GbufferFragmentData> = world
-                    .pbr_material_storage
-                    .iter()
-                    .filter(|entry| entry.storage_type & pbr_filter == pbr_filter)
-                    .map(|entry| {
-                        GbufferFragmentData {
-                            albedo_and_roughness: cgmath::Vector4 {
-                                x: entry.component.albedo.x,
-                                y: entry.component.albedo.y,
-                                z: entry.component.albedo.z,
-                                w: entry.component.roughness,
-                            },
-                            reflectance_and_metalness: cgmath::Vector4 {
-                                x: entry.component.f0_reflectance.x,
-                                y: entry.component.f0_reflectance.y,
-                                z: entry.component.f0_reflectance.z,
-                                w: entry.component.metalness,
-                            },
-                            type_and_emissive: cgmath::Vector4 {
-                                x: (entry.component.material_type as u32) as f32,
-                                y: entry.component.emissive_color.x,
-                                z: entry.component.emissive_color.y,
-                                w: entry.component.emissive_color.z,
-                            }
-                        }
-                    })
-                    .collect();
+            let transform_instance_data: Vec<(usize, cgmath::Matrix4<f32>)> = if full_upload {
+                world
+                    .query_filtered::<&components::RenderTransform, DrawableFilter>()
+                    .map(|render_transform| render_transform.matrix)
+                    .enumerate()
+                    .collect()
+            } else {
+                world
+                    .query_filtered::<(components::Entity, &components::RenderTransform), (
+                        DrawableFilter,
+                        world::Changed<components::RenderTransform>,
+                    )>()
+                    .map(|(entity, render_transform)| (drawable_slots[&entity], render_transform.matrix))
+                    .collect()
+            };
+
+            update_dynamic_uniform_buffer(
+                mem_ub_gbuffer_vs,
+                stride_ub_gbuffer_vs,
+                ub_gbuffer_vs.memory,
+                &demo.device,
+                transform_instance_data,
+                ub_gbuffer_capacity,
+            );
 
-                update_dynamic_uniform_buffer(
-                        mem_ub_gbuffer_fs,
-                        stride_ub_gbuffer_fs,
-                        ub_gbuffer_fs.descriptor.range * pbr_instance_data.len() as u64,
-                        ub_gbuffer_fs.memory,
-                        &demo.device,
-                        pbr_instance_data,
-                );
+            let pbr_instance_data: Vec<(usize, GbufferFragmentData)> = if full_upload {
+                world
+                    .query_filtered::<&components::PBRMaterial, DrawableFilter>()
+                    .map(gbuffer_fragment_data)
+                    .enumerate()
+                    .collect()
+            } else {
+                world
+                    .query_filtered::<(components::Entity, &components::PBRMaterial), (
+                        DrawableFilter,
+                        world::Changed<components::PBRMaterial>,
+                    )>()
+                    .map(|(entity, pbr_material)| (drawable_slots[&entity], gbuffer_fragment_data(pbr_material)))
+                    .collect()
+            };
+
+            update_dynamic_uniform_buffer(
+                mem_ub_gbuffer_fs,
+                stride_ub_gbuffer_fs,
+                ub_gbuffer_fs.memory,
+                &demo.device,
+                pbr_instance_data,
+                ub_gbuffer_capacity,
+            );
+            world.clear_trackers();
 
-                accumulator -= dt;
-            }
+            let camera_view = *world.resource::<camera::CameraView>().unwrap();
+            update_viewdata_uniform_buffer(
+                ub_view_data_ptr,
+                mem::size_of::<ViewData>() as u64,
+                ub_view_data.descriptor.range,
+                camera_view.projection,
+                camera_view.view,
+            );
+
+            let light_block = lighting::LightBlock::new(
+                &world.resource::<lighting::LightBuffer>().unwrap().lights,
+                camera_view.view,
+                camera_view.projection,
+                world.resource::<lighting::LightingSettings>().unwrap(),
+            );
+            render::buffer::copy_to_buffer(&demo.device, sb_lights.memory, &[light_block]);
 
             // --- we have done updates, record gbuffer command buffer
             demo::record_command_buffer(
@@ -1509,19 +1928,10 @@ fn main() {
                     device.cmd_set_scissor(draw_command_buffer, 0, &scissors);
                     let mut dynamic_offset = 0;
     
-                    let mesh_material_filter = (components::ComponentType::MeshComponent as u32) | (components::ComponentType::MaterialComponent as u32);
-
                     world
-                        .mesh_storage
-                        .iter()
-                        .filter(|entry| {
-                            entry.storage_type & mesh_material_filter == mesh_material_filter
-                        })
-                        .zip(world.material_storage.iter().filter(|entry| {
-                            entry.storage_type & mesh_material_filter == mesh_material_filter
-                        }))
+                        .query_filtered::<(&components::Mesh, &components::Material), DrawableFilter>()
                         .for_each(|(mesh, material)| {
-                            device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, material.component.pso);
+                            device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, material.pso);
     
                             device.cmd_bind_descriptor_sets(
                                 draw_command_buffer,
@@ -1532,11 +1942,41 @@ fn main() {
                                 &[dynamic_offset * stride_ub_gbuffer_vs as u32, dynamic_offset * stride_ub_gbuffer_fs as u32],
                             );
     
-                            device.cmd_bind_vertex_buffers(draw_command_buffer, 0, &[mesh.component.vertex_buffer.buffer], &[0]);
-                            device.cmd_bind_index_buffer(draw_command_buffer, mesh.component.index_buffer.buffer, 0, vk::IndexType::UINT32);
-                            device.cmd_draw_indexed(draw_command_buffer, mesh.component.index_buffer.count as u32, 1, 0, 0, 1);
+                            device.cmd_bind_vertex_buffers(draw_command_buffer, 0, &[mesh.vertex_buffer.buffer], &[0]);
+                            device.cmd_bind_index_buffer(draw_command_buffer, mesh.index_buffer.buffer, 0, vk::IndexType::UINT32);
+                            device.cmd_draw_indexed(draw_command_buffer, mesh.index_buffer.count as u32, 1, 0, 0, 1);
                             dynamic_offset += 1;
                         });
+
+                    // --- one instanced draw per emitter, first_instance selects its particles
+                    for (shape, first_instance, instance_count) in particle_draws.iter() {
+                        let mesh = match particle_meshes.get(shape.mesh_name()) {
+                            Some(mesh) => mesh,
+                            None => continue,
+                        };
+                        let pso = match shape {
+                            particles::ParticleShape::Billboard => particle_billboard_pso,
+                            particles::ParticleShape::Mesh(_) => particle_mesh_pso,
+                        };
+
+                        device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, pso);
+                        device.cmd_bind_descriptor_sets(
+                            draw_command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            gbuffer_pipeline_layout,
+                            0,
+                            &gbuffer_descriptor_sets,
+                            &[0, 0],
+                        );
+                        device.cmd_bind_vertex_buffers(
+                            draw_command_buffer,
+                            0,
+                            &[mesh.vertex_buffer.buffer, vb_particle_instances.buffer],
+                            &[0, 0],
+                        );
+                        device.cmd_bind_index_buffer(draw_command_buffer, mesh.index_buffer.buffer, 0, vk::IndexType::UINT32);
+                        device.cmd_draw_indexed(draw_command_buffer, mesh.index_buffer.count as u32, *instance_count, 0, 0, *first_instance);
+                    }
     
                     device.cmd_end_render_pass(draw_command_buffer);
                 },
@@ -1639,32 +2079,45 @@ fn main() {
         // demo.device.free_memory(bl_acceleration_struct_mem, None);
 
         world
-            .material_storage
-            .iter()
-            .filter(|entry| entry.storage_type & material_filter == material_filter)
-            .for_each(|entry| {
-                demo.device.destroy_pipeline(entry.component.pso, None);
+            .query::<&components::Material>()
+            .for_each(|material| {
+                demo.device.destroy_pipeline(material.pso, None);
             });
 
+        demo.device.destroy_pipeline(particle_mesh_pso, None);
+        demo.device.destroy_pipeline(particle_billboard_pso, None);
+        for mesh in particle_meshes.values() {
+            mesh.index_buffer.destroy(&demo.device);
+            mesh.vertex_buffer.destroy(&demo.device);
+        }
+
         demo.device.destroy_pipeline_layout(gbuffer_pipeline_layout, None);
         demo.device.destroy_pipeline_layout(deferred_pipeline_layout, None);
 
         demo.device.destroy_descriptor_set_layout(gbuffer_descriptor_set_layout, None);
         demo.device.destroy_descriptor_set_layout(deferred_descriptor_set_layout, None);
 
-        let mesh_filter = components::ComponentType::MeshComponent as u32;
         world
-            .mesh_storage
-            .iter()
-            .filter(|entry| entry.storage_type & mesh_filter == mesh_filter)
-            .for_each(|entry| {
-                entry.component.index_buffer.destroy(&demo.device);
-                entry.component.vertex_buffer.destroy(&demo.device);
+            .query::<&components::Mesh>()
+            .for_each(|mesh| {
+                mesh.index_buffer.destroy(&demo.device);
+                mesh.vertex_buffer.destroy(&demo.device);
             });
 
+        for component in retired_components {
+            if let Some(mesh) = component.downcast_ref::<components::Mesh>() {
+                mesh.index_buffer.destroy(&demo.device);
+                mesh.vertex_buffer.destroy(&demo.device);
+            } else if let Some(material) = component.downcast_ref::<components::Material>() {
+                demo.device.destroy_pipeline(material.pso, None);
+            }
+        }
+
         ub_gbuffer_fs.destroy(&demo.device);
         ub_gbuffer_vs.destroy(&demo.device);
         ub_view_data.destroy(&demo.device);
+        vb_particle_instances.destroy(&demo.device);
+        sb_lights.destroy(&demo.device);
         for framebuffer in framebuffers {
             demo.device.destroy_framebuffer(framebuffer, None);
         }
@@ -110,4 +110,22 @@ impl Materials {
             },
         }
     }
+
+    // --- preset by its variant name, as written in scene and prefab files
+    pub fn from_name(name: &str) -> Option<Materials> {
+        match name {
+            "Gold" => Some(Materials::Gold),
+            "RoughGold" => Some(Materials::RoughGold),
+            "Iron" => Some(Materials::Iron),
+            "RoughIron" => Some(Materials::RoughIron),
+            "Copper" => Some(Materials::Copper),
+            "RoughCopper" => Some(Materials::RoughCopper),
+            "Silver" => Some(Materials::Silver),
+            "RoughSilver" => Some(Materials::RoughSilver),
+            "Plastic" => Some(Materials::Plastic),
+            "RoughPlastic" => Some(Materials::RoughPlastic),
+            "EmissiveWhite" => Some(Materials::EmissiveWhite),
+            _ => None,
+        }
+    }
 }
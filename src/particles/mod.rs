@@ -0,0 +1,574 @@
+use cgmath::{InnerSpace, SquareMatrix, Transform as _, Zero};
+use probability::prelude::{Exponential, Gaussian, Sample, Uniform};
+use probability::source::Source;
+
+use crate::animation::{Interpolation, Track};
+use crate::components;
+use crate::schedule;
+use crate::world::World;
+
+// --- per particle random value; parameters that would not make a valid probability
+// --- distribution (empty range, zero deviation) fall back to the constant they describe
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Distribution {
+    Constant(f32),
+    Uniform { min: f32, max: f32 },
+    Gaussian { mean: f32, deviation: f32 },
+    // --- mean is 1 / rate
+    Exponential { rate: f32 },
+}
+
+impl Distribution {
+    pub fn sample<S: Source>(&self, source: &mut S) -> f32 {
+        match *self {
+            Distribution::Constant(value) => value,
+            Distribution::Uniform { min, max } if min < max => Uniform::new(min as f64, max as f64).sample(source) as f32,
+            Distribution::Uniform { min, .. } => min,
+            Distribution::Gaussian { mean, deviation } if deviation > 0.0 => {
+                Gaussian::new(mean as f64, deviation as f64).sample(source) as f32
+            },
+            Distribution::Gaussian { mean, .. } => mean,
+            Distribution::Exponential { rate } if rate > 0.0 => Exponential::new(rate as f64).sample(source) as f32,
+            Distribution::Exponential { .. } => 0.0,
+        }
+    }
+}
+
+// --- initial velocity in the emitter's space
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum VelocityDistribution {
+    Axes { x: Distribution, y: Distribution, z: Distribution },
+    // --- direction uniform over the sphere (normalized Gaussian samples, like the dodecahedron ring)
+    Sphere { speed: Distribution },
+    // --- direction uniform within angle (radians) of the emitter's local axis
+    Cone { axis: cgmath::Vector3<f32>, angle: f32, speed: Distribution },
+}
+
+impl VelocityDistribution {
+    pub fn sample<S: Source>(&self, source: &mut S) -> cgmath::Vector3<f32> {
+        match *self {
+            VelocityDistribution::Axes { x, y, z } => cgmath::Vector3 {
+                x: x.sample(source),
+                y: y.sample(source),
+                z: z.sample(source),
+            },
+            VelocityDistribution::Sphere { speed } => random_direction(source) * speed.sample(source),
+            VelocityDistribution::Cone { axis, angle, speed } => {
+                let axis = axis.normalize();
+                // --- uniform in solid angle: cos(theta) uniform in [cos(angle), 1]
+                let cos_theta = Distribution::Uniform { min: angle.cos(), max: 1.0 }.sample(source);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = Distribution::Uniform { min: 0.0, max: 2.0 * std::f32::consts::PI }.sample(source);
+                let helper = if axis.x.abs() < 0.9 {
+                    cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 }
+                } else {
+                    cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 }
+                };
+                let tangent = axis.cross(helper).normalize();
+                let bitangent = axis.cross(tangent);
+                let direction = axis * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta;
+                direction * speed.sample(source)
+            },
+        }
+    }
+}
+
+fn random_direction<S: Source>(source: &mut S) -> cgmath::Vector3<f32> {
+    let normal = Distribution::Gaussian { mean: 0.0, deviation: 1.0 };
+    loop {
+        let direction = cgmath::Vector3 {
+            x: normal.sample(source),
+            y: normal.sample(source),
+            z: normal.sample(source),
+        };
+        if direction.magnitude2() > 1e-12 {
+            return direction.normalize();
+        }
+    }
+}
+
+// --- xorshift128+, like probability's source::Xorshift128Plus, with its state visible to
+// --- Debug so snapshots and replay checks compare it
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct ParticleSource {
+    state: [u64; 2],
+}
+
+impl ParticleSource {
+    pub fn new(seed: u64) -> ParticleSource {
+        // --- the second word is never zero, so neither is the state
+        ParticleSource {
+            state: [seed, (seed ^ 0x9e37_79b9_7f4a_7c15) | 1],
+        }
+    }
+}
+
+impl Source for ParticleSource {
+    fn read_u64(&mut self) -> u64 {
+        let (mut x, y) = (self.state[0], self.state[1]);
+        self.state[0] = y;
+        x ^= x << 23;
+        x ^= x >> 17;
+        x ^= y ^ (y >> 26);
+        self.state[1] = x;
+        x.wrapping_add(y)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParticleShape {
+    // --- camera facing quad, expanded in the vertex shader
+    Billboard,
+    // --- one of the geometry::platonic meshes, by scene mesh name ("cube", "icosahedron", ...)
+    Mesh(String),
+}
+
+impl ParticleShape {
+    // --- geometry::generate name of the mesh the particles are drawn with
+    pub fn mesh_name(&self) -> &str {
+        match self {
+            ParticleShape::Billboard => "quad",
+            ParticleShape::Mesh(name) => name,
+        }
+    }
+}
+
+// --- simulated in world space, so particles stay behind when the emitter moves
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Particle {
+    pub position: cgmath::Vector3<f32>,
+    pub previous_position: cgmath::Vector3<f32>,
+    pub velocity: cgmath::Vector3<f32>,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    // --- 0 when spawned, 1 when it dies
+    pub fn life(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            (self.age / self.lifetime).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+// --- spawns particles at the entity's world position; color and size tracks are sampled at
+// --- the particle's normalized age, so their keys lie in 0..1
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleEmitter {
+    // --- particles per second
+    pub rate: f32,
+    pub lifetime: Distribution,
+    pub velocity: VelocityDistribution,
+    pub color: Track<cgmath::Vector3<f32>>,
+    pub size: Track<f32>,
+    pub gravity: cgmath::Vector3<f32>,
+    pub max_particles: usize,
+    pub shape: ParticleShape,
+    pub emitting: bool,
+    particles: Vec<Particle>,
+    // --- fraction of a particle carried over to the next tick
+    spawn_accumulator: f32,
+    source: ParticleSource,
+}
+
+impl ParticleEmitter {
+    // --- emitters with the same seed and settings spawn the same particles
+    pub fn new(rate: f32, seed: u64) -> ParticleEmitter {
+        ParticleEmitter {
+            rate: rate,
+            lifetime: Distribution::Constant(1.0),
+            velocity: VelocityDistribution::Sphere { speed: Distribution::Constant(1.0) },
+            color: Track::new(Interpolation::Linear).key(0.0, cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }),
+            size: Track::new(Interpolation::Linear).key(0.0, 1.0),
+            gravity: cgmath::Vector3::zero(),
+            max_particles: 1024,
+            shape: ParticleShape::Billboard,
+            emitting: true,
+            particles: vec![],
+            spawn_accumulator: 0.0,
+            source: ParticleSource::new(seed),
+        }
+    }
+
+    pub fn lifetime(mut self, lifetime: Distribution) -> ParticleEmitter {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn velocity(mut self, velocity: VelocityDistribution) -> ParticleEmitter {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn color(mut self, track: Track<cgmath::Vector3<f32>>) -> ParticleEmitter {
+        self.color = track;
+        self
+    }
+
+    pub fn size(mut self, track: Track<f32>) -> ParticleEmitter {
+        self.size = track;
+        self
+    }
+
+    pub fn gravity(mut self, gravity: cgmath::Vector3<f32>) -> ParticleEmitter {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn max_particles(mut self, max_particles: usize) -> ParticleEmitter {
+        self.max_particles = max_particles;
+        self
+    }
+
+    pub fn shape(mut self, shape: ParticleShape) -> ParticleEmitter {
+        self.shape = shape;
+        self
+    }
+
+    // --- live particles, oldest first
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    // --- spawns count particles at once, regardless of rate and emitting
+    pub fn burst(&mut self, count: usize, emitter_matrix: &cgmath::Matrix4<f32>) {
+        for _ in 0..count.min(self.max_particles.saturating_sub(self.particles.len())) {
+            self.spawn(emitter_matrix);
+        }
+    }
+
+    // --- one fixed tick: ages, moves and retires the live particles, then spawns new ones
+    // --- at the emitter; emitter_matrix is the emitter's world matrix
+    pub fn step(&mut self, dt: f32, emitter_matrix: &cgmath::Matrix4<f32>) {
+        let gravity = self.gravity;
+        for particle in self.particles.iter_mut() {
+            particle.velocity += gravity * dt;
+            particle.previous_position = particle.position;
+            particle.position += particle.velocity * dt;
+            particle.age += dt;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        if !self.emitting {
+            self.spawn_accumulator = 0.0;
+            return;
+        }
+        self.spawn_accumulator += self.rate.max(0.0) * dt;
+        let count = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= count;
+        self.burst(count as usize, emitter_matrix);
+    }
+
+    fn spawn(&mut self, emitter_matrix: &cgmath::Matrix4<f32>) {
+        let origin = emitter_matrix.w.truncate();
+        let local_velocity = self.velocity.sample(&mut self.source);
+        // --- rotate into world space but keep the sampled speed, whatever the emitter's scale
+        let velocity = emitter_matrix.transform_vector(local_velocity);
+        let velocity = if velocity.magnitude2() > 1e-12 {
+            velocity.normalize_to(local_velocity.magnitude())
+        } else {
+            velocity
+        };
+        let lifetime = self.lifetime.sample(&mut self.source).max(0.0);
+        self.particles.push(Particle {
+            position: origin,
+            previous_position: origin,
+            velocity: velocity,
+            age: 0.0,
+            lifetime: lifetime,
+        });
+    }
+
+    // --- render data for the live particles, blended alpha of the way into the next tick
+    pub fn instances(&self, alpha: f32) -> impl Iterator<Item = ParticleInstance> + '_ {
+        self.particles.iter().map(move |particle| {
+            let position = particle.previous_position + (particle.position - particle.previous_position) * alpha;
+            let life = particle.life();
+            let color = self.color.sample(life).unwrap_or(cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 });
+            ParticleInstance {
+                position: position.into(),
+                size: self.size.sample(life).unwrap_or(1.0),
+                color: color.into(),
+                life: life,
+            }
+        })
+    }
+}
+
+// --- per instance vertex data, two vec4s: xyz position and size, rgb color and normalized age
+#[repr(C)]
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct ParticleInstance {
+    pub position: [f32; 3],
+    pub size: f32,
+    pub color: [f32; 3],
+    pub life: f32,
+}
+
+// --- instances of one emitter, drawn with a single instanced draw of its shape; first_instance
+// --- is the batch's offset into instance_data
+#[derive(Clone, Debug)]
+pub struct ParticleBatch {
+    pub emitter: components::Entity,
+    pub shape: ParticleShape,
+    pub first_instance: u32,
+    pub instances: Vec<ParticleInstance>,
+}
+
+// --- world resource rebuilt before rendering, one batch per emitter with live particles
+#[derive(Clone, Debug, Default)]
+pub struct ParticleInstances {
+    pub batches: Vec<ParticleBatch>,
+}
+
+impl ParticleInstances {
+    pub fn instance_count(&self) -> usize {
+        self.batches.iter().map(|batch| batch.instances.len()).sum()
+    }
+
+    // --- every batch back to back, ready to be copied into a per instance vertex buffer
+    pub fn instance_data(&self) -> Vec<ParticleInstance> {
+        self.batches.iter().flat_map(|batch| batch.instances.iter().copied()).collect()
+    }
+}
+
+// --- components that take part in snapshots; call once when setting up a world with particles
+pub fn register_components(world: &mut World) {
+    world.register_component_snapshot::<ParticleEmitter>();
+}
+
+// --- steps every emitter on the fixed tick; the emitter's GlobalTransform is from the
+// --- previous tick's propagation, the Transform is used for entities that have none yet
+pub struct ParticleSystem;
+
+impl schedule::System for ParticleSystem {
+    fn name(&self) -> &str {
+        "particles"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .write::<ParticleEmitter>()
+            .read::<components::Transform>()
+            .read::<components::GlobalTransform>()
+            .read_resource::<components::Time>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let dt = ctx.resource::<components::Time>().fixed_dt;
+        ctx.query::<(&mut ParticleEmitter, Option<&components::Transform>, Option<&components::GlobalTransform>)>()
+            .for_each(|(mut emitter, transform, global_transform)| {
+                let matrix = match (global_transform, transform) {
+                    (Some(global_transform), _) => global_transform.matrix,
+                    (None, Some(transform)) => transform.to_matrix(),
+                    (None, None) => cgmath::Matrix4::identity(),
+                };
+                emitter.step(dt, &matrix);
+            });
+    }
+}
+
+// --- runs before rendering and fills the ParticleInstances resource
+pub struct ParticleInstanceSystem;
+
+impl schedule::System for ParticleInstanceSystem {
+    fn name(&self) -> &str {
+        "particle_instances"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<ParticleEmitter>()
+            .read_resource::<components::Time>()
+            .write_resource::<ParticleInstances>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let alpha = ctx.resource::<components::Time>().alpha();
+        let mut first_instance = 0;
+        let batches: Vec<ParticleBatch> = ctx
+            .query::<(components::Entity, &ParticleEmitter)>()
+            .filter(|(_, emitter)| !emitter.particles().is_empty())
+            .map(|(entity, emitter)| {
+                let batch = ParticleBatch {
+                    emitter: entity,
+                    shape: emitter.shape.clone(),
+                    first_instance: first_instance,
+                    instances: emitter.instances(alpha).collect(),
+                };
+                first_instance += batch.instances.len() as u32;
+                batch
+            })
+            .collect();
+        ctx.resource_mut::<ParticleInstances>().batches = batches;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32, y: f32, z: f32) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(cgmath::Vector3 { x: x, y: y, z: z })
+    }
+
+    fn axes(x: f32, y: f32, z: f32) -> VelocityDistribution {
+        VelocityDistribution::Axes {
+            x: Distribution::Constant(x),
+            y: Distribution::Constant(y),
+            z: Distribution::Constant(z),
+        }
+    }
+
+    fn assert_vector(actual: cgmath::Vector3<f32>, x: f32, y: f32, z: f32) {
+        let expected = cgmath::Vector3 { x: x, y: y, z: z };
+        assert!((actual - expected).magnitude() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn spawn_rate_carries_fractions_across_ticks() {
+        let identity = cgmath::Matrix4::identity();
+        let mut emitter = ParticleEmitter::new(2.0, 1).lifetime(Distribution::Constant(10.0));
+        let mut counts = vec![];
+        for _ in 0..5 {
+            emitter.step(0.25, &identity);
+            counts.push(emitter.particles().len());
+        }
+        assert_eq!(counts, vec![0, 1, 1, 2, 2]);
+
+        // --- a stopped emitter drops the carried fraction
+        emitter.emitting = false;
+        emitter.step(0.25, &identity);
+        emitter.emitting = true;
+        emitter.step(0.25, &identity);
+        assert_eq!(emitter.particles().len(), 2);
+        emitter.step(0.25, &identity);
+        assert_eq!(emitter.particles().len(), 3);
+    }
+
+    #[test]
+    fn emitters_keep_at_most_max_particles() {
+        let identity = cgmath::Matrix4::identity();
+        let mut emitter = ParticleEmitter::new(100.0, 1).lifetime(Distribution::Constant(10.0)).max_particles(5);
+        emitter.step(1.0, &identity);
+        assert_eq!(emitter.particles().len(), 5);
+        emitter.burst(3, &identity);
+        assert_eq!(emitter.particles().len(), 5);
+    }
+
+    #[test]
+    fn particles_retire_at_their_lifetime() {
+        let identity = cgmath::Matrix4::identity();
+        let mut emitter = ParticleEmitter::new(0.0, 1).lifetime(Distribution::Constant(0.5));
+        emitter.burst(3, &identity);
+        emitter.step(0.25, &identity);
+        assert_eq!(emitter.particles().len(), 3);
+        assert_eq!(emitter.particles()[0].life(), 0.5);
+        emitter.step(0.25, &identity);
+        assert!(emitter.particles().is_empty());
+
+        // --- particles sampled without a lifetime are done at once
+        let mut emitter = ParticleEmitter::new(0.0, 1).lifetime(Distribution::Constant(-1.0));
+        emitter.burst(1, &identity);
+        assert_eq!(emitter.particles()[0].life(), 1.0);
+        emitter.step(0.25, &identity);
+        assert!(emitter.particles().is_empty());
+    }
+
+    #[test]
+    fn gravity_is_integrated_per_tick() {
+        let mut emitter = ParticleEmitter::new(0.0, 1)
+            .lifetime(Distribution::Constant(10.0))
+            .velocity(axes(1.0, 0.0, 0.0))
+            .gravity(cgmath::Vector3 { x: 0.0, y: -10.0, z: 0.0 });
+        // --- the emitter's scale does not change the sampled speed
+        let matrix = translation(5.0, 0.0, 0.0) * cgmath::Matrix4::from_scale(3.0);
+        emitter.burst(1, &matrix);
+        emitter.step(0.1, &matrix);
+        emitter.step(0.1, &matrix);
+
+        let particle = emitter.particles()[0];
+        assert_vector(particle.velocity, 1.0, -2.0, 0.0);
+        assert_vector(particle.position, 5.2, -0.3, 0.0);
+        assert_vector(particle.previous_position, 5.1, -0.1, 0.0);
+        let instance = emitter.instances(0.5).next().unwrap();
+        assert_vector(instance.position.into(), 5.15, -0.2, 0.0);
+    }
+
+    #[test]
+    fn emitters_with_the_same_seed_spawn_the_same_particles() {
+        let run = |seed: u64| {
+            let mut emitter = ParticleEmitter::new(30.0, seed)
+                .lifetime(Distribution::Uniform { min: 0.5, max: 2.0 })
+                .velocity(VelocityDistribution::Sphere { speed: Distribution::Gaussian { mean: 2.0, deviation: 0.5 } });
+            for _ in 0..20 {
+                emitter.step(1.0 / 60.0, &cgmath::Matrix4::identity());
+            }
+            emitter
+        };
+        assert_eq!(run(7), run(7));
+        assert_eq!(run(7).particles().len(), 10);
+        assert_ne!(run(7).particles(), run(8).particles());
+    }
+
+    #[test]
+    fn invalid_distributions_fall_back_to_constants() {
+        let mut source = ParticleSource::new(3);
+        assert_eq!(Distribution::Uniform { min: 2.0, max: 2.0 }.sample(&mut source), 2.0);
+        assert_eq!(Distribution::Uniform { min: 3.0, max: 1.0 }.sample(&mut source), 3.0);
+        assert_eq!(Distribution::Gaussian { mean: 4.0, deviation: 0.0 }.sample(&mut source), 4.0);
+        assert_eq!(Distribution::Exponential { rate: 0.0 }.sample(&mut source), 0.0);
+        for _ in 0..100 {
+            let value = Distribution::Uniform { min: -1.0, max: 1.0 }.sample(&mut source);
+            assert!(value >= -1.0 && value <= 1.0);
+            assert!(Distribution::Exponential { rate: 2.0 }.sample(&mut source) >= 0.0);
+        }
+
+        // --- a cone without an angle shoots along its axis
+        let cone = VelocityDistribution::Cone {
+            axis: cgmath::Vector3 { x: 0.0, y: 2.0, z: 0.0 },
+            angle: 0.0,
+            speed: Distribution::Constant(3.0),
+        };
+        assert_vector(cone.sample(&mut source), 0.0, 3.0, 0.0);
+        let sphere = VelocityDistribution::Sphere { speed: Distribution::Constant(2.0) };
+        assert!((sphere.sample(&mut source).magnitude() - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn batches_start_where_the_previous_one_ends() {
+        let mut world = World::new();
+        world.insert_resource(components::Time {
+            fixed_dt: 0.1,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
+        world.insert_resource(ParticleInstances::default());
+        let emitter = |count: usize, shape: ParticleShape| {
+            let mut emitter = ParticleEmitter::new(0.0, 1).shape(shape);
+            emitter.burst(count, &cgmath::Matrix4::identity());
+            emitter
+        };
+        let sparks = world.create_entity().with(emitter(3, ParticleShape::Billboard)).build();
+        // --- emitters without live particles get no batch
+        world.create_entity().with(emitter(0, ParticleShape::Billboard)).build();
+        let debris = world.create_entity().with(emitter(2, ParticleShape::Mesh(String::from("cube")))).build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::RENDER_PREPARE, ParticleInstanceSystem);
+        scheduler.run_stage(schedule::RENDER_PREPARE, &mut world);
+
+        let instances = world.resource::<ParticleInstances>().unwrap();
+        let batches: Vec<(components::Entity, &str, u32, usize)> = instances
+            .batches
+            .iter()
+            .map(|batch| (batch.emitter, batch.shape.mesh_name(), batch.first_instance, batch.instances.len()))
+            .collect();
+        assert_eq!(batches, vec![(sparks, "quad", 0, 3), (debris, "cube", 3, 2)]);
+        assert_eq!(instances.instance_count(), 5);
+        assert_eq!(instances.instance_data().len(), 5);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::spatial::Aabb;
+
+// --- sort and sweep along the axis where the boxes are most spread out. The order of the
+// --- previous call is kept, so between fixed steps the insertion sort only moves the few
+// --- boxes that overtook a neighbour
+#[derive(Clone, Debug, Default)]
+pub struct SweepAndPrune {
+    order: Vec<usize>,
+    axis: usize,
+}
+
+impl SweepAndPrune {
+    pub fn new() -> SweepAndPrune {
+        SweepAndPrune {
+            order: vec![],
+            axis: 0,
+        }
+    }
+
+    // --- index pairs (i, j) with i < j of overlapping boxes, sorted; boxes are expected in
+    // --- the same order from call to call, a different count starts over
+    pub fn pairs(&mut self, boxes: &[Aabb]) -> Vec<(usize, usize)> {
+        if self.order.len() != boxes.len() {
+            self.order = (0..boxes.len()).collect();
+        }
+
+        let axis = self.axis;
+        for sorted in 1..self.order.len() {
+            let mut position = sorted;
+            while position > 0 && boxes[self.order[position - 1]].min[axis] > boxes[self.order[position]].min[axis] {
+                self.order.swap(position - 1, position);
+                position -= 1;
+            }
+        }
+
+        let mut pairs = vec![];
+        for (position, &first) in self.order.iter().enumerate() {
+            let end = boxes[first].max[axis];
+            for &second in self.order[(position + 1)..].iter() {
+                if boxes[second].min[axis] > end {
+                    break;
+                }
+                if boxes[first].intersects(&boxes[second]) {
+                    pairs.push((first.min(second), first.max(second)));
+                }
+            }
+        }
+        pairs.sort();
+
+        self.axis = widest_axis(boxes);
+        pairs
+    }
+}
+
+// --- axis with the largest variance of the box centers
+fn widest_axis(boxes: &[Aabb]) -> usize {
+    if boxes.is_empty() {
+        return 0;
+    }
+
+    let count = boxes.len() as f32;
+    let mean = boxes.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, aabb| sum + aabb.center()) / count;
+    let variance = boxes.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, aabb| {
+        let offset = aabb.center() - mean;
+        sum + cgmath::Vector3 { x: offset.x * offset.x, y: offset.y * offset.y, z: offset.z * offset.z }
+    });
+
+    if variance.x >= variance.y && variance.x >= variance.z {
+        0
+    } else if variance.y >= variance.z {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // --- xorshift, so the scenes are the same on every run without pulling in rand
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 % 10_000) as f32 / 10_000.0
+        }
+    }
+
+    fn brute_force(boxes: &[Aabb]) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+        for i in 0..boxes.len() {
+            for j in (i + 1)..boxes.len() {
+                if boxes[i].intersects(&boxes[j]) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    // --- boxes spread out along spread, so the sweep axis follows it
+    fn scatter(random: &mut Random, count: usize, spread: cgmath::Vector3<f32>) -> Vec<Aabb> {
+        (0..count)
+            .map(|_| {
+                let center = cgmath::Vector3 {
+                    x: (random.next() - 0.5) * spread.x,
+                    y: (random.next() - 0.5) * spread.y,
+                    z: (random.next() - 0.5) * spread.z,
+                };
+                let extent = cgmath::Vector3 { x: random.next() + 0.1, y: random.next() + 0.1, z: random.next() + 0.1 };
+                Aabb::new(center - extent, center + extent)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pairs_match_a_brute_force_scan_while_the_axis_changes() {
+        let mut random = Random(0x2545_f491);
+        let mut broad_phase = SweepAndPrune::new();
+        let spreads = [
+            cgmath::Vector3 { x: 40.0, y: 4.0, z: 4.0 },
+            cgmath::Vector3 { x: 4.0, y: 40.0, z: 4.0 },
+            cgmath::Vector3 { x: 4.0, y: 4.0, z: 40.0 },
+            cgmath::Vector3 { x: 20.0, y: 20.0, z: 2.0 },
+        ];
+
+        let mut axes = vec![];
+        for spread in spreads.iter() {
+            let mut boxes = scatter(&mut random, 150, *spread);
+            for _ in 0..3 {
+                assert_eq!(broad_phase.pairs(&boxes), brute_force(&boxes));
+                axes.push(broad_phase.axis);
+                // --- small moves between calls keep the order nearly sorted
+                for aabb in boxes.iter_mut() {
+                    let offset = cgmath::Vector3 { x: random.next() - 0.5, y: random.next() - 0.5, z: random.next() - 0.5 };
+                    *aabb = Aabb::new(aabb.min + offset, aabb.max + offset);
+                }
+            }
+        }
+        // --- the first call after each change still sweeps along the previous axis
+        assert_eq!(&axes[..9], &[0, 0, 0, 1, 1, 1, 2, 2, 2]);
+        assert!(axes[9..].iter().all(|axis| *axis != 2));
+
+        // --- a different count starts over
+        let boxes = scatter(&mut random, 20, spreads[0]);
+        assert_eq!(broad_phase.pairs(&boxes), brute_force(&boxes));
+        assert!(broad_phase.pairs(&[]).is_empty());
+    }
+}
@@ -0,0 +1,653 @@
+use cgmath::InnerSpace;
+
+use crate::physics::collision::{ConvexHull, WorldShape};
+
+const MAX_ITERATIONS: usize = 64;
+// --- shapes closer than this count as touching, which GJK hands over to EPA
+const TOUCHING_DISTANCE: f32 = 1e-4;
+// --- relative progress below which GJK stops, and absolute progress below which EPA stops
+const GJK_TOLERANCE: f32 = 1e-5;
+const EPA_TOLERANCE: f32 = 1e-4;
+
+// --- closest points of two shapes that are apart
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Separation {
+    pub distance: f32,
+    pub point_a: cgmath::Vector3<f32>,
+    pub point_b: cgmath::Vector3<f32>,
+}
+
+// --- smallest translation that separates two overlapping shapes: moving b by normal * depth
+// --- (or a by the opposite) leaves them touching. point lies halfway between the surfaces
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Penetration {
+    pub normal: cgmath::Vector3<f32>,
+    pub depth: f32,
+    pub point: cgmath::Vector3<f32>,
+}
+
+pub fn intersects(a: &WorldShape, b: &WorldShape) -> bool {
+    closest_points(a, b).is_none()
+}
+
+// --- 0 when the shapes touch or overlap
+pub fn distance(a: &WorldShape, b: &WorldShape) -> f32 {
+    closest_points(a, b).map_or(0.0, |separation| separation.distance)
+}
+
+// --- None when the shapes touch or overlap. A hull without points is infinitely far from
+// --- everything; its closest point is taken to be the other shape's center
+pub fn closest_points(a: &WorldShape, b: &WorldShape) -> Option<Separation> {
+    let ((core_a, radius_a), (core_b, radius_b)) = match (core(a), core(b)) {
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => {
+            let center_a = a.map(|(core, _)| core.center());
+            let center_b = b.map(|(core, _)| core.center());
+            let point_a = center_a.or(center_b).unwrap_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+            return Some(Separation {
+                distance: std::f32::INFINITY,
+                point_a: point_a,
+                point_b: center_b.unwrap_or(point_a),
+            });
+        },
+    };
+
+    match gjk(&core_a, &core_b) {
+        Gjk::Separated { distance, point_a, point_b } if distance > radius_a + radius_b => {
+            let normal = (point_b - point_a) / distance;
+            Some(Separation {
+                distance: distance - radius_a - radius_b,
+                point_a: point_a + normal * radius_a,
+                point_b: point_b - normal * radius_b,
+            })
+        },
+        _ => None,
+    }
+}
+
+// --- None when the shapes are apart or one of them is a hull without points
+pub fn penetration(a: &WorldShape, b: &WorldShape) -> Option<Penetration> {
+    let (core_a, radius_a) = core(a)?;
+    let (core_b, radius_b) = core(b)?;
+    let radius = radius_a + radius_b;
+
+    // --- spheres are points with a margin: when only the margins overlap, the closest points
+    // --- of the cores already give the answer; otherwise EPA measures how far the cores overlap
+    let (normal, depth, point_a) = match gjk(&core_a, &core_b) {
+        Gjk::Separated { distance, point_a, point_b } => {
+            if distance >= radius {
+                return None;
+            }
+            let normal = (point_b - point_a) / distance;
+            (normal, radius - distance, point_a + normal * radius_a)
+        },
+        Gjk::Overlapping(simplex) => match epa(&core_a, &core_b, simplex) {
+            Some((normal, depth, point_a)) => (normal, depth + radius, point_a + normal * radius_a),
+            // --- the cores only touch in a point, edge or face; any direction separates them
+            None => {
+                let offset = core_b.center() - core_a.center();
+                let normal = if offset.magnitude2() > 1e-12 {
+                    offset.normalize()
+                } else {
+                    cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 }
+                };
+                (normal, radius, core_a.support(normal) + normal * radius_a)
+            },
+        },
+    };
+
+    Some(Penetration {
+        normal: normal,
+        depth: depth,
+        point: point_a - normal * (depth * 0.5),
+    })
+}
+
+// --- shapes as GJK sees them: spheres shrink to their center and keep the radius as a margin.
+// --- Hulls always have points, see core
+enum Core<'a> {
+    Point(cgmath::Vector3<f32>),
+    Hull(&'a ConvexHull),
+}
+
+impl<'a> Core<'a> {
+    fn support(&self, direction: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+        match self {
+            Core::Point(point) => *point,
+            Core::Hull(hull) => hull
+                .points
+                .iter()
+                .copied()
+                .reduce(|best, point| if point.dot(direction) > best.dot(direction) { point } else { best })
+                .unwrap_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+        }
+    }
+
+    fn center(&self) -> cgmath::Vector3<f32> {
+        match self {
+            Core::Point(point) => *point,
+            Core::Hull(hull) => hull.points.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, point| sum + point)
+                / hull.points.len() as f32,
+        }
+    }
+}
+
+// --- None for a hull without points, which has nothing to collide with
+fn core(shape: &WorldShape) -> Option<(Core<'_>, f32)> {
+    match shape {
+        WorldShape::Sphere { center, radius } => Some((Core::Point(*center), *radius)),
+        WorldShape::Convex(hull) if hull.points.is_empty() => None,
+        WorldShape::Convex(hull) => Some((Core::Hull(hull), 0.0)),
+    }
+}
+
+// --- point of the Minkowski difference a - b, with the points of a and b it came from
+#[derive(Clone, Debug, Copy)]
+struct Vertex {
+    point: cgmath::Vector3<f32>,
+    a: cgmath::Vector3<f32>,
+    b: cgmath::Vector3<f32>,
+}
+
+fn support(a: &Core, b: &Core, direction: cgmath::Vector3<f32>) -> Vertex {
+    let on_a = a.support(direction);
+    let on_b = b.support(-direction);
+    Vertex {
+        point: on_a - on_b,
+        a: on_a,
+        b: on_b,
+    }
+}
+
+enum Gjk {
+    Separated {
+        distance: f32,
+        point_a: cgmath::Vector3<f32>,
+        point_b: cgmath::Vector3<f32>,
+    },
+    // --- the last simplex, which EPA grows from
+    Overlapping(Vec<Vertex>),
+}
+
+// --- distance GJK: walks the simplex towards the point of a - b closest to the origin
+fn gjk(a: &Core, b: &Core) -> Gjk {
+    let mut direction = a.center() - b.center();
+    if direction.magnitude2() < 1e-12 {
+        direction = cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 };
+    }
+
+    let first = support(a, b, direction);
+    let mut simplex = vec![(first, 1.0)];
+    let mut closest = first.point;
+    for _ in 0..MAX_ITERATIONS {
+        let distance2 = closest.magnitude2();
+        if distance2 < TOUCHING_DISTANCE * TOUCHING_DISTANCE {
+            return Gjk::Overlapping(simplex.into_iter().map(|(vertex, _)| vertex).collect());
+        }
+
+        // --- stop once no point of a - b is noticeably closer than the current one
+        let vertex = support(a, b, -closest);
+        let duplicate = simplex.iter().any(|(existing, _)| (existing.point - vertex.point).magnitude2() < 1e-12);
+        if duplicate || distance2 - closest.dot(vertex.point) <= GJK_TOLERANCE * distance2 {
+            break;
+        }
+
+        let mut vertices: Vec<Vertex> = simplex.iter().map(|(vertex, _)| *vertex).collect();
+        vertices.push(vertex);
+        simplex = match closest_on_simplex(&vertices) {
+            Some(reduced) => reduced,
+            None => return Gjk::Overlapping(vertices),
+        };
+        closest = weighted_point(&simplex);
+    }
+
+    Gjk::Separated {
+        distance: closest.magnitude(),
+        point_a: simplex.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, (vertex, weight)| sum + vertex.a * *weight),
+        point_b: simplex.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, (vertex, weight)| sum + vertex.b * *weight),
+    }
+}
+
+// --- smallest sub-simplex holding the point closest to the origin, with the barycentric
+// --- weight of each of its vertices; None when a tetrahedron contains the origin
+fn closest_on_simplex(vertices: &[Vertex]) -> Option<Vec<(Vertex, f32)>> {
+    match vertices.len() {
+        1 => Some(vec![(vertices[0], 1.0)]),
+        2 => Some(closest_on_segment(vertices[0], vertices[1])),
+        3 => Some(closest_on_triangle(vertices[0], vertices[1], vertices[2])),
+        _ => closest_on_tetrahedron(vertices[0], vertices[1], vertices[2], vertices[3]),
+    }
+}
+
+fn closest_on_segment(a: Vertex, b: Vertex) -> Vec<(Vertex, f32)> {
+    let ab = b.point - a.point;
+    let length2 = ab.magnitude2();
+    let t = if length2 > 1e-12 { -a.point.dot(ab) / length2 } else { 0.0 };
+    if t <= 0.0 {
+        vec![(a, 1.0)]
+    } else if t >= 1.0 {
+        vec![(b, 1.0)]
+    } else {
+        vec![(a, 1.0 - t), (b, t)]
+    }
+}
+
+// --- Voronoi region tests from Ericson, Real-Time Collision Detection 5.1.5
+fn closest_on_triangle(a: Vertex, b: Vertex, c: Vertex) -> Vec<(Vertex, f32)> {
+    let ab = b.point - a.point;
+    let ac = c.point - a.point;
+
+    let d1 = ab.dot(-a.point);
+    let d2 = ac.dot(-a.point);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return vec![(a, 1.0)];
+    }
+
+    let d3 = ab.dot(-b.point);
+    let d4 = ac.dot(-b.point);
+    if d3 >= 0.0 && d4 <= d3 {
+        return vec![(b, 1.0)];
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return vec![(a, 1.0 - v), (b, v)];
+    }
+
+    let d5 = ab.dot(-c.point);
+    let d6 = ac.dot(-c.point);
+    if d6 >= 0.0 && d5 <= d6 {
+        return vec![(c, 1.0)];
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return vec![(a, 1.0 - w), (c, w)];
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return vec![(b, 1.0 - w), (c, w)];
+    }
+
+    let total = va + vb + vc;
+    if total.abs() < 1e-12 {
+        // --- degenerate triangle, the nearest of its edges decides
+        return nearest(vec![closest_on_segment(a, b), closest_on_segment(a, c), closest_on_segment(b, c)]).unwrap();
+    }
+    let v = vb / total;
+    let w = vc / total;
+    vec![(a, 1.0 - v - w), (b, v), (c, w)]
+}
+
+fn closest_on_tetrahedron(a: Vertex, b: Vertex, c: Vertex, d: Vertex) -> Option<Vec<(Vertex, f32)>> {
+    let mut candidates = vec![];
+    for &(p, q, r, opposite) in [(a, b, c, d), (a, c, d, b), (a, d, b, c), (b, d, c, a)].iter() {
+        let normal = (q.point - p.point).cross(r.point - p.point);
+        let origin_side = normal.dot(-p.point);
+        let opposite_side = normal.dot(opposite.point - p.point);
+        // --- only faces with the origin in front count; a flat tetrahedron has no inside
+        if opposite_side.abs() < 1e-12 || origin_side * opposite_side < 0.0 {
+            candidates.push(closest_on_triangle(p, q, r));
+        }
+    }
+    nearest(candidates)
+}
+
+fn nearest(candidates: Vec<Vec<(Vertex, f32)>>) -> Option<Vec<(Vertex, f32)>> {
+    candidates.into_iter().min_by(|a, b| {
+        weighted_point(a)
+            .magnitude2()
+            .partial_cmp(&weighted_point(b).magnitude2())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+fn weighted_point(simplex: &[(Vertex, f32)]) -> cgmath::Vector3<f32> {
+    simplex.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, (vertex, weight)| sum + vertex.point * *weight)
+}
+
+struct Face {
+    indices: [usize; 3],
+    normal: cgmath::Vector3<f32>,
+    distance: f32,
+}
+
+impl Face {
+    // --- indices wind counter-clockwise seen from outside the polytope
+    fn new(vertices: &[Vertex], indices: [usize; 3]) -> Face {
+        let a = vertices[indices[0]].point;
+        let cross = (vertices[indices[1]].point - a).cross(vertices[indices[2]].point - a);
+        if cross.magnitude2() < 1e-16 {
+            // --- sliver faces are kept for the topology but never picked as the closest, see
+            // --- closest_face
+            return Face {
+                indices: indices,
+                normal: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+                distance: std::f32::MAX,
+            };
+        }
+        let normal = cross.normalize();
+        Face {
+            indices: indices,
+            normal: normal,
+            distance: normal.dot(a),
+        }
+    }
+}
+
+// --- face nearest to the origin, leaving out slivers; None when only slivers are left
+fn closest_face(faces: &[Face]) -> Option<usize> {
+    faces
+        .iter()
+        .enumerate()
+        .filter(|(_, face)| face.distance < std::f32::MAX)
+        .fold(None, |best: Option<usize>, (index, face)| match best {
+            Some(best) if faces[best].distance <= face.distance => Some(best),
+            _ => Some(index),
+        })
+}
+
+// --- expanding polytope: grows the simplex around the origin until the face of a - b
+// --- closest to the origin is found. Returns its normal, distance and the matching point
+// --- on a; None when a - b is flat around the origin, i.e. the shapes merely touch
+fn epa(a: &Core, b: &Core, mut vertices: Vec<Vertex>) -> Option<(cgmath::Vector3<f32>, f32, cgmath::Vector3<f32>)> {
+    if !grow_to_tetrahedron(a, b, &mut vertices) {
+        return None;
+    }
+
+    // --- wind the first face so the fourth vertex is behind it
+    let first = Face::new(&vertices, [0, 1, 2]);
+    if first.normal.dot(vertices[3].point - vertices[0].point) > 0.0 {
+        vertices.swap(1, 2);
+    }
+    let mut faces: Vec<Face> = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+        .iter()
+        .map(|indices| Face::new(&vertices, *indices))
+        .collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        let closest = closest_face(&faces)?;
+        let normal = faces[closest].normal;
+        let distance = faces[closest].distance;
+
+        let vertex = support(a, b, normal);
+        if vertex.point.dot(normal) - distance < EPA_TOLERANCE {
+            break;
+        }
+
+        // --- remove every face the new vertex sees and stitch the hole to it
+        let new_index = vertices.len();
+        vertices.push(vertex);
+        let mut horizon: Vec<(usize, usize)> = vec![];
+        let mut kept = vec![];
+        for face in faces.into_iter() {
+            if face.normal.dot(vertex.point - vertices[face.indices[0]].point) > 0.0 {
+                for &(from, to) in [(0, 1), (1, 2), (2, 0)].iter() {
+                    let edge = (face.indices[from], face.indices[to]);
+                    match horizon.iter().position(|existing| *existing == (edge.1, edge.0)) {
+                        Some(position) => {
+                            horizon.swap_remove(position);
+                        },
+                        None => horizon.push(edge),
+                    }
+                }
+            } else {
+                kept.push(face);
+            }
+        }
+        faces = kept;
+        for (from, to) in horizon {
+            faces.push(Face::new(&vertices, [from, to, new_index]));
+        }
+    }
+
+    let closest = &faces[closest_face(&faces)?];
+    let [i, j, k] = closest.indices;
+    let (u, v, w) = barycentric(
+        closest.normal * closest.distance,
+        vertices[i].point,
+        vertices[j].point,
+        vertices[k].point,
+    );
+    let point_a = vertices[i].a * u + vertices[j].a * v + vertices[k].a * w;
+    Some((closest.normal, closest.distance.max(0.0), point_a))
+}
+
+// --- adds support points until the simplex spans a volume; false when a - b is too flat
+fn grow_to_tetrahedron(a: &Core, b: &Core, vertices: &mut Vec<Vertex>) -> bool {
+    let axes = [
+        cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 },
+        cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        cgmath::Vector3 { x: 0.0, y: 0.0, z: 1.0 },
+    ];
+
+    while vertices.len() < 4 {
+        let directions: Vec<cgmath::Vector3<f32>> = match vertices.len() {
+            1 => axes.iter().flat_map(|axis| vec![*axis, -*axis]).collect(),
+            2 => {
+                let line = vertices[1].point - vertices[0].point;
+                axes.iter()
+                    .map(|axis| line.cross(*axis))
+                    .filter(|direction| direction.magnitude2() > 1e-12)
+                    .flat_map(|direction| vec![direction, -direction])
+                    .collect()
+            },
+            _ => {
+                let normal = (vertices[1].point - vertices[0].point).cross(vertices[2].point - vertices[0].point);
+                vec![normal, -normal]
+            },
+        };
+
+        let grown = directions.into_iter().map(|direction| support(a, b, direction)).find(|vertex| {
+            let base = vertices[0].point;
+            let offset = vertex.point - base;
+            match vertices.len() {
+                1 => offset.magnitude2() > 1e-10,
+                2 => offset.cross((vertices[1].point - base).normalize()).magnitude2() > 1e-10,
+                _ => {
+                    let normal = (vertices[1].point - base).cross(vertices[2].point - base);
+                    normal.magnitude2() > 1e-16 && offset.dot(normal.normalize()).abs() > 1e-5
+                },
+            }
+        });
+        match grown {
+            Some(vertex) => vertices.push(vertex),
+            None => return false,
+        }
+    }
+
+    true
+}
+
+// --- weights of p in triangle abc, Ericson 3.4
+fn barycentric(
+    p: cgmath::Vector3<f32>,
+    a: cgmath::Vector3<f32>,
+    b: cgmath::Vector3<f32>,
+    c: cgmath::Vector3<f32>,
+) -> (f32, f32, f32) {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = p - a;
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator.abs() < 1e-12 {
+        return (1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0);
+    }
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    (1.0 - v - w, v, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components;
+    use crate::geometry::platonic;
+    use crate::physics::collision;
+    use crate::spatial::Aabb;
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> WorldShape {
+        WorldShape::Sphere {
+            center: cgmath::Vector3 { x: x, y: y, z: z },
+            radius: radius,
+        }
+    }
+
+    // --- cube with half extent 1, rotated about z
+    fn cube(x: f32, y: f32, z: f32, angle: f32) -> WorldShape {
+        let transform = components::Transform {
+            position: cgmath::Vector3 { x: x, y: y, z: z },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: angle }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        };
+        WorldShape::Convex(ConvexHull::cuboid(cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }).transformed(&transform.to_matrix()))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    fn assert_normal(penetration: &Penetration, x: f32, y: f32, z: f32) {
+        let expected = cgmath::Vector3 { x: x, y: y, z: z };
+        assert!((penetration.normal - expected).magnitude() < 1e-3, "{:?} != {:?}", penetration.normal, expected);
+    }
+
+    #[test]
+    fn sphere_sphere() {
+        let a = sphere(0.0, 0.0, 0.0, 1.0);
+        let b = sphere(3.0, 4.0, 0.0, 2.0);
+        assert_close(distance(&a, &b), 2.0);
+        let separation = closest_points(&a, &b).unwrap();
+        assert!((separation.point_a - cgmath::Vector3 { x: 0.6, y: 0.8, z: 0.0 }).magnitude() < 1e-4);
+        assert!((separation.point_b - cgmath::Vector3 { x: 1.8, y: 2.4, z: 0.0 }).magnitude() < 1e-4);
+        assert!(penetration(&a, &b).is_none());
+
+        let b = sphere(0.0, 1.5, 0.0, 1.0);
+        assert!(intersects(&a, &b));
+        let overlap = penetration(&a, &b).unwrap();
+        assert_close(overlap.depth, 0.5);
+        assert_normal(&overlap, 0.0, 1.0, 0.0);
+    }
+
+    #[test]
+    fn box_box() {
+        let a = cube(0.0, 0.0, 0.0, 0.0);
+        assert_close(distance(&a, &cube(3.0, 0.5, 0.0, 0.0)), 1.0);
+        assert_close(distance(&a, &cube(3.0, 3.0, 0.0, 0.0)), 2.0f32.sqrt());
+        assert_close(distance(&a, &cube(2.0, 0.0, 0.0, 0.0)), 0.0);
+
+        let overlap = penetration(&a, &cube(1.7, 0.3, 0.2, 0.0)).unwrap();
+        assert_close(overlap.depth, 0.3);
+        assert_normal(&overlap, 1.0, 0.0, 0.0);
+
+        // --- turned by 45 degrees the corner reaches out to sqrt(2)
+        let quarter = std::f32::consts::FRAC_PI_4;
+        let overlap = penetration(&a, &cube(1.0 + 2.0f32.sqrt() - 0.1, 0.0, 0.0, quarter)).unwrap();
+        assert_close(overlap.depth, 0.1);
+        assert_normal(&overlap, 1.0, 0.0, 0.0);
+        assert_close(distance(&a, &cube(1.0 + 2.0f32.sqrt() + 0.1, 0.0, 0.0, quarter)), 0.1);
+    }
+
+    #[test]
+    fn sphere_box() {
+        let a = cube(0.0, 0.0, 0.0, 0.0);
+        assert_close(distance(&a, &sphere(0.0, 3.0, 0.0, 1.0)), 1.0);
+        assert_close(distance(&a, &sphere(2.0, 2.0, 0.0, 1.0)), 2.0f32.sqrt() - 1.0);
+
+        let overlap = penetration(&a, &sphere(0.0, 1.8, 0.0, 1.0)).unwrap();
+        assert_close(overlap.depth, 0.2);
+        assert_normal(&overlap, 0.0, 1.0, 0.0);
+
+        // --- the sphere's center inside the box takes EPA; the normal flips with the order
+        let inside = sphere(0.0, 0.7, 0.0, 0.5);
+        let overlap = penetration(&a, &inside).unwrap();
+        assert_close(overlap.depth, 0.8);
+        assert_normal(&overlap, 0.0, 1.0, 0.0);
+        let overlap = penetration(&inside, &a).unwrap();
+        assert_close(overlap.depth, 0.8);
+        assert_normal(&overlap, 0.0, -1.0, 0.0);
+    }
+
+    #[test]
+    fn empty_and_flat_hulls_do_not_panic() {
+        let empty = WorldShape::Convex(ConvexHull { points: vec![], planes: vec![] });
+        let a = cube(0.0, 0.0, 0.0, 0.0);
+        assert!(penetration(&a, &empty).is_none());
+        assert!(penetration(&empty, &empty).is_none());
+        assert!(!intersects(&empty, &a));
+        assert_eq!(distance(&a, &empty), std::f32::INFINITY);
+        let separation = closest_points(&empty, &sphere(1.0, 2.0, 3.0, 1.0)).unwrap();
+        assert_eq!(separation.point_a, separation.point_b);
+        assert_eq!(empty.aabb(), Aabb::from_point(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }));
+        assert!(collision::contacts(&a, &empty).is_empty());
+
+        // --- a square without thickness lying in the y = 0.5 plane
+        let square = |y: f32| {
+            WorldShape::Convex(ConvexHull {
+                points: vec![
+                    cgmath::Vector3 { x: -0.5, y: y, z: -0.5 },
+                    cgmath::Vector3 { x: 0.5, y: y, z: -0.5 },
+                    cgmath::Vector3 { x: 0.5, y: y, z: 0.5 },
+                    cgmath::Vector3 { x: -0.5, y: y, z: 0.5 },
+                ],
+                planes: vec![],
+            })
+        };
+        let overlap = penetration(&a, &square(0.5)).unwrap();
+        assert_close(overlap.depth, 0.5);
+        assert_normal(&overlap, 0.0, 1.0, 0.0);
+        assert_close(distance(&a, &square(3.0)), 2.0);
+
+        // --- two squares in the same plane merely touch
+        let overlap = penetration(&square(0.5), &square(0.5)).unwrap();
+        assert_close(overlap.depth, 0.0);
+        assert_close(distance(&square(0.5), &square(1.5)), 1.0);
+    }
+
+    #[test]
+    fn platonic_solids_against_spheres_and_themselves() {
+        let solids = [
+            ("tetrahedron", platonic::tetrahedron()),
+            ("cube", platonic::cube()),
+            ("octahedron", platonic::octahedron()),
+            ("dodecahedron", platonic::dodecahedron()),
+            ("icosahedron", platonic::icosahedron()),
+        ];
+        for (name, geometry) in solids.iter() {
+            let hull = ConvexHull::from_geometry(geometry);
+            let shape = WorldShape::Convex(hull.clone());
+            for (normal, distance) in hull.planes.iter() {
+                // --- a sphere over the middle of a face is as far from the solid as from its plane
+                let center = normal * (distance + 0.5 + 0.25);
+                let apart = sphere(center.x, center.y, center.z, 0.5);
+                assert!((super::distance(&shape, &apart) - 0.25).abs() < 1e-3, "{} {:?}", name, normal);
+                assert!(penetration(&shape, &apart).is_none(), "{}", name);
+
+                let center = normal * (distance + 0.5 - 0.1);
+                let overlap = penetration(&shape, &sphere(center.x, center.y, center.z, 0.5)).unwrap();
+                assert!((overlap.depth - 0.1).abs() < 1e-3, "{} {:?}", name, overlap);
+                assert!((overlap.normal - normal).magnitude() < 1e-3, "{} {:?}", name, overlap);
+
+                // --- the copy mirrored through its center shows a face parallel to this one
+                let mirrored = |gap: f32| {
+                    let offset = normal * (2.0 * distance + gap);
+                    let matrix = cgmath::Matrix4::from_translation(offset) * cgmath::Matrix4::from_scale(-1.0);
+                    WorldShape::Convex(hull.transformed(&matrix))
+                };
+                assert!((super::distance(&shape, &mirrored(0.3)) - 0.3).abs() < 1e-3, "{} {:?}", name, normal);
+                let overlap = penetration(&shape, &mirrored(-0.05)).unwrap();
+                assert!((overlap.depth - 0.05).abs() < 1e-3, "{} {:?}", name, overlap);
+                assert!((overlap.normal - normal).magnitude() < 1e-3, "{} {:?}", name, overlap);
+            }
+        }
+    }
+}
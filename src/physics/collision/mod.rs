@@ -0,0 +1,199 @@
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+
+use crate::components;
+use crate::geometry;
+use crate::spatial::Aabb;
+
+mod broad_phase;
+pub mod gjk;
+
+pub use broad_phase::SweepAndPrune;
+
+// --- convex polyhedron in collider space; planes are outward normals with their distance
+// --- from the origin, so a point p is inside when dot(n, p) <= d for every plane
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConvexHull {
+    pub points: Vec<cgmath::Vector3<f32>>,
+    pub planes: Vec<(cgmath::Vector3<f32>, f32)>,
+}
+
+impl ConvexHull {
+    // --- the geometry is expected to be convex and closed, like the platonic solids;
+    // --- vertices repeated per face are merged and coplanar triangles share one plane
+    pub fn from_geometry(geometry: &geometry::GeometryData) -> ConvexHull {
+        let mut points: Vec<cgmath::Vector3<f32>> = vec![];
+        for vertex in geometry.vertices.iter() {
+            let point = cgmath::Vector3::from(vertex.position);
+            if !points.iter().any(|existing| (existing - point).magnitude2() < 1e-10) {
+                points.push(point);
+            }
+        }
+
+        let centroid = points.iter().fold(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |sum, point| sum + point)
+            / points.len().max(1) as f32;
+
+        let mut planes: Vec<(cgmath::Vector3<f32>, f32)> = vec![];
+        for triangle in geometry.indices.chunks(3) {
+            if triangle.len() < 3 {
+                continue;
+            }
+            let p0 = cgmath::Vector3::from(geometry.vertices[triangle[0] as usize].position);
+            let p1 = cgmath::Vector3::from(geometry.vertices[triangle[1] as usize].position);
+            let p2 = cgmath::Vector3::from(geometry.vertices[triangle[2] as usize].position);
+            let cross = (p1 - p0).cross(p2 - p0);
+            if cross.magnitude2() < 1e-12 {
+                continue;
+            }
+
+            let mut normal = cross.normalize();
+            if normal.dot(p0 - centroid) < 0.0 {
+                normal = -normal;
+            }
+            if !planes.iter().any(|(existing, _)| existing.dot(normal) > 0.9999) {
+                planes.push((normal, normal.dot(p0)));
+            }
+        }
+
+        ConvexHull {
+            points: points,
+            planes: planes,
+        }
+    }
+
+    pub fn cuboid(half_extents: cgmath::Vector3<f32>) -> ConvexHull {
+        let h = half_extents;
+        let mut points = vec![];
+        for &x in [-h.x, h.x].iter() {
+            for &y in [-h.y, h.y].iter() {
+                for &z in [-h.z, h.z].iter() {
+                    points.push(cgmath::Vector3 { x: x, y: y, z: z });
+                }
+            }
+        }
+
+        ConvexHull {
+            points: points,
+            planes: vec![
+                (cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 }, h.x),
+                (cgmath::Vector3 { x: -1.0, y: 0.0, z: 0.0 }, h.x),
+                (cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 }, h.y),
+                (cgmath::Vector3 { x: 0.0, y: -1.0, z: 0.0 }, h.y),
+                (cgmath::Vector3 { x: 0.0, y: 0.0, z: 1.0 }, h.z),
+                (cgmath::Vector3 { x: 0.0, y: 0.0, z: -1.0 }, h.z),
+            ],
+        }
+    }
+
+    // --- hull of the geometry's vertices placed by transform, scale included
+    pub fn from_geometry_transformed(geometry: &geometry::GeometryData, transform: &components::Transform) -> ConvexHull {
+        ConvexHull::from_geometry(geometry).transformed(&transform.to_matrix())
+    }
+
+    // --- points and planes under an affine matrix; normals go through the inverse transpose
+    // --- so non-uniform scale keeps them perpendicular to their faces
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> ConvexHull {
+        let linear = cgmath::Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+        let normal_matrix = linear.invert().map_or(linear, |inverse| inverse.transpose());
+        let translation = matrix.w.truncate();
+
+        ConvexHull {
+            points: self.points.iter().map(|point| linear * point + translation).collect(),
+            planes: self
+                .planes
+                .iter()
+                .map(|(normal, distance)| {
+                    let on_plane = linear * (normal * *distance) + translation;
+                    let normal = (normal_matrix * normal).normalize();
+                    (normal, normal.dot(on_plane))
+                })
+                .collect(),
+        }
+    }
+
+    // --- the largest signed plane distance, negative inside; also the plane it belongs to
+    fn deepest_plane(&self, point: cgmath::Vector3<f32>) -> (f32, cgmath::Vector3<f32>) {
+        self.planes
+            .iter()
+            .map(|(normal, distance)| (normal.dot(point) - distance, *normal))
+            .fold((std::f32::MIN, cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 }), |best, candidate| {
+                if candidate.0 > best.0 { candidate } else { best }
+            })
+    }
+}
+
+// --- a collider placed in the world, ready for contact tests
+#[derive(Clone, Debug)]
+pub enum WorldShape {
+    Sphere { center: cgmath::Vector3<f32>, radius: f32 },
+    Convex(ConvexHull),
+}
+
+impl WorldShape {
+    pub fn aabb(&self) -> Aabb {
+        match self {
+            WorldShape::Sphere { center, radius } => Aabb::from_point(*center).expanded(*radius),
+            // --- an empty hull becomes a point at the origin; it has no support to touch with
+            WorldShape::Convex(hull) => {
+                let first = hull.points.first().cloned().unwrap_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+                hull.points
+                    .iter()
+                    .fold(Aabb::from_point(first), |aabb, point| aabb.union(&Aabb::from_point(*point)))
+            },
+        }
+    }
+}
+
+// --- normal points from the first shape into the second; depth is the overlap along it
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct ContactPoint {
+    pub point: cgmath::Vector3<f32>,
+    pub normal: cgmath::Vector3<f32>,
+    pub depth: f32,
+}
+
+// --- vertices of one hull closer than this to the inside of the other still count as contacts
+const CONTACT_TOLERANCE: f32 = 1e-3;
+
+// --- contact points between two overlapping shapes, empty when they are apart. EPA gives the
+// --- normal; for two hulls every vertex of one inside the other becomes a contact along it,
+// --- so resting faces get several points, and edge-edge crossings fall back to the EPA point
+pub fn contacts(a: &WorldShape, b: &WorldShape) -> Vec<ContactPoint> {
+    let penetration = match gjk::penetration(a, b) {
+        Some(penetration) => penetration,
+        None => return vec![],
+    };
+    let deepest = ContactPoint {
+        point: penetration.point,
+        normal: penetration.normal,
+        depth: penetration.depth,
+    };
+
+    match (a, b) {
+        (WorldShape::Convex(hull_a), WorldShape::Convex(hull_b)) => {
+            let normal = penetration.normal;
+            let reach_a = hull_a.points.iter().map(|point| point.dot(normal)).fold(std::f32::MIN, f32::max);
+            let reach_b = hull_b.points.iter().map(|point| point.dot(normal)).fold(std::f32::MAX, f32::min);
+
+            let mut points = vec![];
+            for point in hull_a.points.iter() {
+                let depth = point.dot(normal) - reach_b;
+                if depth > 0.0 && hull_b.deepest_plane(*point).0 < CONTACT_TOLERANCE {
+                    points.push(ContactPoint { point: *point, normal: normal, depth: depth.min(penetration.depth) });
+                }
+            }
+            for point in hull_b.points.iter() {
+                let depth = reach_a - point.dot(normal);
+                if depth > 0.0 && hull_a.deepest_plane(*point).0 < CONTACT_TOLERANCE {
+                    points.push(ContactPoint { point: *point, normal: normal, depth: depth.min(penetration.depth) });
+                }
+            }
+
+            if points.is_empty() {
+                vec![deepest]
+            } else {
+                points
+            }
+        },
+        _ => vec![deepest],
+    }
+}
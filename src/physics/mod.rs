@@ -0,0 +1,680 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+
+use crate::components;
+use crate::geometry;
+use crate::schedule;
+use crate::spatial::Aabb;
+use crate::world::{Without, World};
+
+pub mod collision;
+
+use collision::{ConvexHull, WorldShape};
+
+// --- mass <= 0 makes the body immovable; inertia holds the principal moments in body space.
+// --- Dynamic bodies move through their Velocity, which the physics step adds if missing
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct RigidBody {
+    pub mass: f32,
+    pub inertia: cgmath::Vector3<f32>,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl RigidBody {
+    // --- inertia of a solid body filling the collider at the given scale
+    pub fn new(mass: f32, collider: &Collider, scale: cgmath::Vector3<f32>) -> RigidBody {
+        RigidBody {
+            mass: mass,
+            inertia: collider.inertia(mass, scale),
+            restitution: 0.2,
+            friction: 0.5,
+        }
+    }
+
+    pub fn fixed() -> RigidBody {
+        RigidBody {
+            mass: 0.0,
+            inertia: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            restitution: 0.2,
+            friction: 0.5,
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.mass > 0.0
+    }
+}
+
+// --- shapes are in mesh space and follow the entity's Transform, scale included
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Sphere { radius: f32 },
+    Box { half_extents: cgmath::Vector3<f32> },
+    ConvexHull(ConvexHull),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColliderError {
+    // --- the geometry has no vertices to build a hull from
+    EmptyHull,
+    UnknownMesh(String),
+}
+
+// --- an entity with a Collider but no RigidBody is static scenery
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider {
+    pub shape: Shape,
+}
+
+impl Collider {
+    pub fn sphere(radius: f32) -> Collider {
+        Collider { shape: Shape::Sphere { radius: radius } }
+    }
+
+    pub fn cuboid(half_extents: cgmath::Vector3<f32>) -> Collider {
+        Collider { shape: Shape::Box { half_extents: half_extents } }
+    }
+
+    pub fn convex_hull(geometry: &geometry::GeometryData) -> Result<Collider, ColliderError> {
+        let hull = ConvexHull::from_geometry(geometry);
+        if hull.points.is_empty() {
+            return Err(ColliderError::EmptyHull);
+        }
+
+        Ok(Collider { shape: Shape::ConvexHull(hull) })
+    }
+
+    // --- collider matching a mesh from geometry::generate, e.g. "dodecahedron"
+    pub fn from_mesh_name(name: &str) -> Result<Collider, ColliderError> {
+        match name {
+            "cube" => Ok(Collider::cuboid(cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 })),
+            _ => match geometry::generate(name) {
+                Some(geometry) => Collider::convex_hull(&geometry),
+                None => Err(ColliderError::UnknownMesh(String::from(name))),
+            },
+        }
+    }
+
+    // --- principal moments; hulls are treated like their bounding box
+    pub fn inertia(&self, mass: f32, scale: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+        let box_inertia = |size: cgmath::Vector3<f32>| {
+            cgmath::Vector3 {
+                x: mass / 12.0 * (size.y * size.y + size.z * size.z),
+                y: mass / 12.0 * (size.x * size.x + size.z * size.z),
+                z: mass / 12.0 * (size.x * size.x + size.y * size.y),
+            }
+        };
+        let scaled = |v: cgmath::Vector3<f32>| cgmath::Vector3 { x: v.x * scale.x, y: v.y * scale.y, z: v.z * scale.z };
+
+        match &self.shape {
+            Shape::Sphere { radius } => {
+                let radius = radius * scale.x.max(scale.y).max(scale.z);
+                let moment = 0.4 * mass * radius * radius;
+                cgmath::Vector3 { x: moment, y: moment, z: moment }
+            },
+            Shape::Box { half_extents } => box_inertia(scaled(*half_extents * 2.0)),
+            Shape::ConvexHull(hull) => {
+                // --- convex_hull refuses empty hulls, one built by hand weighs like a point
+                let first = hull.points.first().copied().unwrap_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+                let bounds = hull.points.iter().fold(
+                    (first, first),
+                    |(min, max), point| {
+                        (
+                            cgmath::Vector3 { x: min.x.min(point.x), y: min.y.min(point.y), z: min.z.min(point.z) },
+                            cgmath::Vector3 { x: max.x.max(point.x), y: max.y.max(point.y), z: max.z.max(point.z) },
+                        )
+                    },
+                );
+                box_inertia(scaled(bounds.1 - bounds.0))
+            },
+        }
+    }
+
+    // --- spheres take the largest scale axis, so they stay spheres under non-uniform scale
+    pub fn world_shape(&self, transform: &components::Transform) -> WorldShape {
+        match &self.shape {
+            Shape::Sphere { radius } => WorldShape::Sphere {
+                center: transform.position,
+                radius: radius * transform.scale.x.abs().max(transform.scale.y.abs()).max(transform.scale.z.abs()),
+            },
+            Shape::Box { half_extents } => WorldShape::Convex(ConvexHull::cuboid(*half_extents).transformed(&transform.to_matrix())),
+            Shape::ConvexHull(hull) => WorldShape::Convex(hull.transformed(&transform.to_matrix())),
+        }
+    }
+}
+
+// --- world resource read by the physics step; gravity is an acceleration in world units per
+// --- second squared and defaults to -y
+#[derive(Clone, Debug, Copy)]
+pub struct PhysicsSettings {
+    pub gravity: cgmath::Vector3<f32>,
+    pub iterations: usize,
+    // --- fraction of linear / angular velocity lost per second
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> PhysicsSettings {
+        PhysicsSettings {
+            gravity: cgmath::Vector3 { x: 0.0, y: -9.81, z: 0.0 },
+            iterations: 8,
+            linear_damping: 0.01,
+            angular_damping: 0.1,
+        }
+    }
+}
+
+// --- components that take part in snapshots; call once when setting up a world with physics
+pub fn register_components(world: &mut World) {
+    world.register_component_snapshot::<RigidBody>();
+    world.register_component_snapshot::<Collider>();
+    world.register_resource_snapshot::<PhysicsSettings>();
+}
+
+// --- overlap allowed before positions are corrected, and how much of the rest is corrected per step
+const PENETRATION_SLOP: f32 = 0.005;
+const CORRECTION_PERCENT: f32 = 0.8;
+// --- slower impacts do not bounce, so resting contacts settle
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+
+struct Body {
+    entity: components::Entity,
+    transform: components::Transform,
+    orientation: cgmath::Matrix3<f32>,
+    linear: cgmath::Vector3<f32>,
+    angular: cgmath::Vector3<f32>,
+    inverse_mass: f32,
+    inverse_inertia: cgmath::Vector3<f32>,
+    restitution: f32,
+    friction: f32,
+    shape: WorldShape,
+}
+
+impl Body {
+    fn inverse_inertia_world(&self) -> cgmath::Matrix3<f32> {
+        let local = cgmath::Matrix3::from_diagonal(self.inverse_inertia);
+        self.orientation * local * self.orientation.transpose()
+    }
+
+    fn velocity_at(&self, point: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+        self.linear + self.angular.cross(point - self.transform.position)
+    }
+
+    fn apply_impulse(&mut self, impulse: cgmath::Vector3<f32>, point: cgmath::Vector3<f32>) {
+        self.linear += impulse * self.inverse_mass;
+        self.angular += self.inverse_inertia_world() * (point - self.transform.position).cross(impulse);
+    }
+}
+
+struct Contact {
+    a: usize,
+    b: usize,
+    point: cgmath::Vector3<f32>,
+    normal: cgmath::Vector3<f32>,
+    depth: f32,
+    // --- normal speed before solving, used for restitution
+    approach: f32,
+    normal_impulse: f32,
+    friction_impulse: f32,
+}
+
+// --- semi-implicit Euler on the fixed step: gravity goes into the velocity, contacts are
+// --- resolved with sequential impulses, then positions move with the new velocity and
+// --- remaining overlap is pushed apart. The broad phase keeps its sort order between steps
+pub struct PhysicsSystem {
+    broad_phase: collision::SweepAndPrune,
+}
+
+impl PhysicsSystem {
+    pub fn new() -> PhysicsSystem {
+        PhysicsSystem {
+            broad_phase: collision::SweepAndPrune::new(),
+        }
+    }
+}
+
+impl schedule::System for PhysicsSystem {
+    fn name(&self) -> &str {
+        "physics"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .write::<components::Transform>()
+            .write::<components::Velocity>()
+            .read::<RigidBody>()
+            .read::<Collider>()
+            .read_resource::<components::Time>()
+            .read_resource::<PhysicsSettings>()
+    }
+
+    fn snapshot(&self) -> Option<Box<dyn Any + Send>> {
+        Some(Box::new(self.broad_phase.clone()))
+    }
+
+    fn restore(&mut self, state: &dyn Any) {
+        self.broad_phase = state.downcast_ref::<collision::SweepAndPrune>().unwrap().clone();
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let dt = ctx.resource::<components::Time>().fixed_dt;
+        let settings = *ctx.resource::<PhysicsSettings>();
+
+        let mut missing_velocity = vec![];
+        let mut bodies: Vec<Body> = ctx
+            .query::<(
+                components::Entity,
+                &components::Transform,
+                &Collider,
+                Option<&RigidBody>,
+                Option<&components::Velocity>,
+            )>()
+            .map(|(entity, transform, collider, body, velocity)| {
+                let dynamic = body.filter(|body| body.is_dynamic());
+                if dynamic.is_some() && velocity.is_none() {
+                    missing_velocity.push(entity);
+                }
+                let dynamic = dynamic.filter(|_| velocity.is_some());
+
+                let inverse = |value: f32| if value > 0.0 { 1.0 / value } else { 0.0 };
+                Body {
+                    entity: entity,
+                    transform: *transform,
+                    orientation: cgmath::Matrix3::from(transform.rotation),
+                    linear: velocity.filter(|_| dynamic.is_some()).map_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |velocity| velocity.translation_speed),
+                    angular: velocity.filter(|_| dynamic.is_some()).map_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |velocity| velocity.rotation_speed),
+                    inverse_mass: dynamic.map_or(0.0, |body| 1.0 / body.mass),
+                    inverse_inertia: dynamic.map_or(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }, |body| cgmath::Vector3 {
+                        x: inverse(body.inertia.x),
+                        y: inverse(body.inertia.y),
+                        z: inverse(body.inertia.z),
+                    }),
+                    restitution: body.map_or(0.2, |body| body.restitution),
+                    friction: body.map_or(0.5, |body| body.friction),
+                    shape: collider.world_shape(transform),
+                }
+            })
+            .collect();
+        for entity in missing_velocity {
+            ctx.commands().insert(entity, components::Velocity {
+                translation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+                rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            });
+        }
+
+        step(&mut bodies, &mut self.broad_phase, &settings, dt);
+
+        // --- only dynamic bodies move; they are looked up by entity, so the write-back does
+        // --- not depend on both queries visiting entities in the same order
+        let moved: HashMap<components::Entity, &Body> = bodies
+            .iter()
+            .filter(|body| body.inverse_mass > 0.0)
+            .map(|body| (body.entity, body))
+            .collect();
+        ctx.query::<(components::Entity, &mut components::Transform, Option<&mut components::Velocity>)>()
+            .for_each(|(entity, mut transform, velocity)| {
+                let body = match moved.get(&entity) {
+                    Some(body) => body,
+                    None => return,
+                };
+
+                *transform = body.transform;
+                if let Some(mut velocity) = velocity {
+                    velocity.translation_speed = body.linear;
+                    velocity.rotation_speed = body.angular;
+                }
+            });
+    }
+}
+
+fn step(bodies: &mut Vec<Body>, broad_phase: &mut collision::SweepAndPrune, settings: &PhysicsSettings, dt: f32) {
+    let linear_keep = (1.0 - settings.linear_damping * dt).max(0.0);
+    let angular_keep = (1.0 - settings.angular_damping * dt).max(0.0);
+    for body in bodies.iter_mut().filter(|body| body.inverse_mass > 0.0) {
+        body.linear = (body.linear + settings.gravity * dt) * linear_keep;
+        body.angular *= angular_keep;
+    }
+
+    let mut contacts = find_contacts(bodies, broad_phase);
+    solve_contacts(bodies, &mut contacts, settings.iterations);
+
+    for body in bodies.iter_mut().filter(|body| body.inverse_mass > 0.0) {
+        body.transform.position += body.linear * dt;
+        body.transform.rotate(body.angular * dt);
+        body.orientation = cgmath::Matrix3::from(body.transform.rotation);
+    }
+
+    correct_positions(bodies, &contacts);
+}
+
+// --- pairs from the broad phase with at least one dynamic body, in index order
+fn find_contacts(bodies: &[Body], broad_phase: &mut collision::SweepAndPrune) -> Vec<Contact> {
+    let boxes: Vec<Aabb> = bodies.iter().map(|body| body.shape.aabb()).collect();
+    let mut contacts = vec![];
+    for (a, b) in broad_phase.pairs(&boxes) {
+        if bodies[a].inverse_mass == 0.0 && bodies[b].inverse_mass == 0.0 {
+            continue;
+        }
+
+        for point in collision::contacts(&bodies[a].shape, &bodies[b].shape) {
+            let relative = bodies[b].velocity_at(point.point) - bodies[a].velocity_at(point.point);
+            contacts.push(Contact {
+                a: a,
+                b: b,
+                point: point.point,
+                normal: point.normal,
+                depth: point.depth,
+                approach: relative.dot(point.normal),
+                normal_impulse: 0.0,
+                friction_impulse: 0.0,
+            });
+        }
+    }
+    contacts
+}
+
+// --- accumulated impulses are clamped (normal >= 0, friction inside the Coulomb cone) so
+// --- later iterations can take back what earlier ones overshot
+fn solve_contacts(bodies: &mut [Body], contacts: &mut [Contact], iterations: usize) {
+    for _ in 0..iterations {
+        for contact in contacts.iter_mut() {
+            let (a, b) = pair_mut(bodies, contact.a, contact.b);
+            let restitution = a.restitution.max(b.restitution);
+            let friction = (a.friction * b.friction).sqrt();
+
+            let relative = b.velocity_at(contact.point) - a.velocity_at(contact.point);
+            let normal_speed = relative.dot(contact.normal);
+            let target = if contact.approach < -RESTITUTION_THRESHOLD {
+                -restitution * contact.approach
+            } else {
+                0.0
+            };
+            let mass = effective_mass(a, b, contact.point, contact.normal);
+            if mass <= 0.0 {
+                continue;
+            }
+            let accumulated = (contact.normal_impulse + (target - normal_speed) / mass).max(0.0);
+            let impulse = contact.normal * (accumulated - contact.normal_impulse);
+            contact.normal_impulse = accumulated;
+            a.apply_impulse(-impulse, contact.point);
+            b.apply_impulse(impulse, contact.point);
+
+            let relative = b.velocity_at(contact.point) - a.velocity_at(contact.point);
+            let sliding = relative - contact.normal * relative.dot(contact.normal);
+            if sliding.magnitude2() < 1e-12 {
+                continue;
+            }
+            let tangent = sliding.normalize();
+            let mass = effective_mass(a, b, contact.point, tangent);
+            if mass <= 0.0 {
+                continue;
+            }
+            let limit = friction * contact.normal_impulse;
+            let accumulated = (contact.friction_impulse - sliding.magnitude() / mass).max(-limit).min(limit);
+            let impulse = tangent * (accumulated - contact.friction_impulse);
+            contact.friction_impulse = accumulated;
+            a.apply_impulse(-impulse, contact.point);
+            b.apply_impulse(impulse, contact.point);
+        }
+    }
+}
+
+// --- pushes each overlapping pair apart along its deepest contact, split by inverse mass
+fn correct_positions(bodies: &mut [Body], contacts: &[Contact]) {
+    let mut start = 0;
+    while start < contacts.len() {
+        let (a, b) = (contacts[start].a, contacts[start].b);
+        let end = start + contacts[start..].iter().take_while(|contact| contact.a == a && contact.b == b).count();
+        let deepest = contacts[start..end]
+            .iter()
+            .fold(&contacts[start], |deepest, contact| if contact.depth > deepest.depth { contact } else { deepest });
+        start = end;
+
+        let (body_a, body_b) = pair_mut(bodies, a, b);
+        let total = body_a.inverse_mass + body_b.inverse_mass;
+        let correction = (deepest.depth - PENETRATION_SLOP).max(0.0) * CORRECTION_PERCENT / total;
+        if correction > 0.0 {
+            body_a.transform.position -= deepest.normal * correction * body_a.inverse_mass;
+            body_b.transform.position += deepest.normal * correction * body_b.inverse_mass;
+        }
+    }
+}
+
+fn effective_mass(a: &Body, b: &Body, point: cgmath::Vector3<f32>, direction: cgmath::Vector3<f32>) -> f32 {
+    let ra = point - a.transform.position;
+    let rb = point - b.transform.position;
+    let angular_a = (a.inverse_inertia_world() * ra.cross(direction)).cross(ra);
+    let angular_b = (b.inverse_inertia_world() * rb.cross(direction)).cross(rb);
+    a.inverse_mass + b.inverse_mass + direction.dot(angular_a + angular_b)
+}
+
+fn pair_mut(bodies: &mut [Body], a: usize, b: usize) -> (&mut Body, &mut Body) {
+    debug_assert!(a < b);
+    let (head, tail) = bodies.split_at_mut(b);
+    (&mut head[a], &mut tail[0])
+}
+
+// --- moves entities that have a Velocity but are not simulated by the physics step
+pub struct MovementSystem;
+
+impl schedule::System for MovementSystem {
+    fn name(&self) -> &str {
+        "movement"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<components::Velocity>()
+            .write::<components::Transform>()
+            .read::<RigidBody>()
+            .read_resource::<components::Time>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let dt = ctx.resource::<components::Time>().fixed_dt;
+        ctx.query_filtered::<(&mut components::Transform, &components::Velocity), Without<RigidBody>>()
+            .for_each(|(mut transform, velocity)| {
+                transform.position += velocity.translation_speed * dt;
+                transform.rotate(velocity.rotation_speed * dt);
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Rotation3;
+
+    #[test]
+    fn empty_hulls_and_unknown_meshes_are_refused() {
+        let empty = geometry::GeometryData {
+            vertices: vec![],
+            indices: vec![],
+            name: None,
+            material: None,
+        };
+        assert_eq!(Collider::convex_hull(&empty), Err(ColliderError::EmptyHull));
+        assert_eq!(Collider::from_mesh_name("teapot"), Err(ColliderError::UnknownMesh(String::from("teapot"))));
+        assert!(Collider::from_mesh_name("dodecahedron").is_ok());
+
+        // --- a hull built by hand without points has no extent instead of panicking
+        let hull = Collider {
+            shape: Shape::ConvexHull(ConvexHull { points: vec![], planes: vec![] }),
+        };
+        let scale = cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 };
+        assert_eq!(hull.inertia(1.0, scale), cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+    }
+
+    #[test]
+    fn bodies_are_written_back_to_their_own_entity() {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.insert_resource(components::Time {
+            fixed_dt: 1.0 / 60.0,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
+        world.insert_resource(PhysicsSettings::default());
+
+        let transform = |y: f32| components::Transform {
+            position: cgmath::Vector3 { x: 0.0, y: y, z: 0.0 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        };
+        let velocity = components::Velocity {
+            translation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+        };
+        let sphere = Collider::sphere(0.5);
+        let scale = cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 };
+
+        // --- the falling body gets its Velocity before the static one gets its Transform, so
+        // --- the storages list the entities in different orders
+        let falling = world.create_entity().with(velocity).build();
+        let fixed = world.create_entity().with(velocity).with(transform(-5.0)).with(sphere.clone()).build();
+        world.insert(falling, transform(5.0));
+        world.insert(falling, RigidBody::new(1.0, &sphere, scale));
+        world.insert(falling, sphere.clone());
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, PhysicsSystem::new());
+        for _ in 0..10 {
+            scheduler.run_stage(schedule::FIXED_UPDATE, &mut world);
+        }
+
+        assert!(world.get::<components::Transform>(falling).unwrap().position.y < 5.0);
+        assert!(world.get::<components::Velocity>(falling).unwrap().translation_speed.y < 0.0);
+        assert_eq!(world.get::<components::Transform>(fixed).unwrap().position.y, -5.0);
+        assert_eq!(world.get::<components::Velocity>(fixed).unwrap().translation_speed.y, 0.0);
+    }
+
+    fn physics_world(gravity: f32) -> World {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.insert_resource(components::Time {
+            fixed_dt: 1.0 / 60.0,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
+        world.insert_resource(PhysicsSettings {
+            gravity: cgmath::Vector3 { x: 0.0, y: gravity, z: 0.0 },
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            ..PhysicsSettings::default()
+        });
+        world
+    }
+
+    fn at(y: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: 0.0, y: y, z: 0.0 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn moving(y: f32) -> components::Velocity {
+        components::Velocity {
+            translation_speed: cgmath::Vector3 { x: 0.0, y: y, z: 0.0 },
+            rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+
+    // --- a static slab whose top is at y = 0
+    fn ground(world: &mut World) -> components::Entity {
+        world
+            .create_entity()
+            .with(at(-0.5))
+            .with(Collider::cuboid(cgmath::Vector3 { x: 10.0, y: 0.5, z: 10.0 }))
+            .with(RigidBody::fixed())
+            .build()
+    }
+
+    fn ball(world: &mut World, y: f32, speed: f32, restitution: f32) -> components::Entity {
+        let sphere = Collider::sphere(0.5);
+        let body = RigidBody {
+            restitution: restitution,
+            ..RigidBody::new(1.0, &sphere, cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 })
+        };
+        world.create_entity().with(at(y)).with(moving(speed)).with(sphere).with(body).build()
+    }
+
+    fn run(world: &mut World, ticks: usize) {
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, PhysicsSystem::new());
+        for _ in 0..ticks {
+            scheduler.run_stage(schedule::FIXED_UPDATE, world);
+        }
+    }
+
+    #[test]
+    fn bodies_fall_and_come_to_rest_on_a_static_box() {
+        let mut world = physics_world(-9.81);
+        let ground = ground(&mut world);
+        let ball = ball(&mut world, 3.0, 0.0, 0.0);
+
+        // --- semi-implicit Euler: the velocity gains g dt per tick before it moves the body
+        let dt = 1.0 / 60.0;
+        run(&mut world, 10);
+        let speed = world.get::<components::Velocity>(ball).unwrap().translation_speed.y;
+        assert!((speed + 9.81 * 10.0 * dt).abs() < 1e-4, "{}", speed);
+        let y = world.get::<components::Transform>(ball).unwrap().position.y;
+        assert!((y - (3.0 - 9.81 * dt * dt * 55.0)).abs() < 1e-4, "{}", y);
+
+        run(&mut world, 240);
+        let y = world.get::<components::Transform>(ball).unwrap().position.y;
+        assert!((y - 0.5).abs() < 0.02, "{}", y);
+        assert!(world.get::<components::Velocity>(ball).unwrap().translation_speed.magnitude() < 0.05);
+        assert_eq!(world.get::<components::Transform>(ground).unwrap().position.y, -0.5);
+    }
+
+    #[test]
+    fn restitution_decides_the_bounce() {
+        let bounce = |restitution: f32, speed: f32| {
+            let mut world = physics_world(0.0);
+            ground(&mut world);
+            let ball = ball(&mut world, 0.52, speed, restitution);
+            run(&mut world, 6);
+            world.get::<components::Velocity>(ball).unwrap().translation_speed.y
+        };
+        // --- the larger restitution of the two bodies is used, the ground's is 0.2
+        assert!((bounce(0.8, -5.0) - 4.0).abs() < 1e-3);
+        assert!((bounce(0.5, -5.0) - 2.5).abs() < 1e-3);
+        assert!((bounce(0.0, -5.0) - 1.0).abs() < 1e-3);
+        // --- slow impacts settle instead of bouncing
+        assert!(bounce(0.8, -0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn movement_moves_only_bodies_without_physics() {
+        let mut world = physics_world(-9.81);
+        let velocity = components::Velocity {
+            translation_speed: cgmath::Vector3 { x: 1.0, y: 2.0, z: -3.0 },
+            rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 1.2 },
+        };
+        let kinematic = world.create_entity().with(at(0.0)).with(velocity).build();
+        let sphere = Collider::sphere(0.5);
+        let simulated = world
+            .create_entity()
+            .with(at(0.0))
+            .with(velocity)
+            .with(RigidBody::new(1.0, &sphere, cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }))
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, MovementSystem);
+        for _ in 0..30 {
+            scheduler.run_stage(schedule::FIXED_UPDATE, &mut world);
+        }
+
+        let transform = *world.get::<components::Transform>(kinematic).unwrap();
+        assert!((transform.position - cgmath::Vector3 { x: 0.5, y: 1.0, z: -1.5 }).magnitude() < 1e-4);
+        let turned = cgmath::Quaternion::from_angle_z(cgmath::Rad(0.6));
+        assert!(transform.rotation.dot(turned).abs() > 1.0 - 1e-5);
+        assert_eq!(world.get::<components::Transform>(simulated).unwrap().position, at(0.0).position);
+    }
+}
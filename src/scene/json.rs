@@ -0,0 +1,395 @@
+use crate::scene::SceneError;
+
+// --- minimal JSON tree; objects keep their key order so saved files diff cleanly
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // --- goes through the shortest f32 representation, so 0.1f32 is written as 0.1
+    pub fn from_f32(value: f32) -> Value {
+        Value::Number(value.to_string().parse::<f64>().unwrap())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // --- None for numbers f32 can't hold, like 1e39, which would otherwise become infinite
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Value::Number(number) if (*number as f32).is_finite() => Some(*number as f32),
+            _ => None,
+        }
+    }
+
+    // --- non-negative whole numbers, e.g. references to other entries of a file
+    pub fn as_index(&self) -> Option<usize> {
+        match self {
+            Value::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        write_value(self, 0, &mut out);
+        out.push('\n');
+        out
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    match value {
+        Value::Array(_) | Value::Object(_) => false,
+        _ => true,
+    }
+}
+
+fn write_indent(depth: usize, out: &mut String) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// --- arrays of scalars (vectors, colors) stay on one line, everything else is indented.
+// --- JSON has no NaN or infinity, they are written as null and rejected where a number is read
+fn write_value(value: &Value, depth: usize, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(boolean) => out.push_str(if *boolean { "true" } else { "false" }),
+        Value::Number(number) if !number.is_finite() => out.push_str("null"),
+        Value::Number(number) => out.push_str(&number.to_string()),
+        Value::String(string) => write_string(string, out),
+        Value::Array(values) if values.iter().all(is_scalar) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(value, depth, out);
+            }
+            out.push(']');
+        },
+        Value::Array(values) => {
+            out.push_str("[\n");
+            for (i, value) in values.iter().enumerate() {
+                write_indent(depth + 1, out);
+                write_value(value, depth + 1, out);
+                out.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+            }
+            write_indent(depth, out);
+            out.push(']');
+        },
+        Value::Object(members) if members.is_empty() => out.push_str("{}"),
+        Value::Object(members) => {
+            out.push_str("{\n");
+            for (i, (name, value)) in members.iter().enumerate() {
+                write_indent(depth + 1, out);
+                write_string(name, out);
+                out.push_str(": ");
+                write_value(value, depth + 1, out);
+                out.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+            }
+            write_indent(depth, out);
+            out.push('}');
+        },
+    }
+}
+
+// --- scenes nest a handful of levels; the limit keeps hostile files from exhausting the stack
+const MAX_DEPTH: usize = 64;
+
+pub fn parse(text: &str) -> Result<Value, SceneError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        position: 0,
+        line: 1,
+        depth: 0,
+    };
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        return Err(parser.error("trailing characters after value"));
+    }
+
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> SceneError {
+        SceneError::Parse {
+            line: self.line,
+            message: String::from(message),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SceneError> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    // --- '//' line comments are accepted so hand-edited scenes can be annotated
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                },
+                Some('/') if self.chars.get(self.position + 1) == Some(&'/') => {
+                    while let Some(c) = self.next() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                },
+                _ => return,
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, SceneError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_nested(Parser::parse_object),
+            Some('[') => self.parse_nested(Parser::parse_array),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('t') => self.parse_keyword("true", Value::Bool(true)),
+            Some('f') => self.parse_keyword("false", Value::Bool(false)),
+            Some('n') => self.parse_keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_nested(&mut self, parse: fn(&mut Parser) -> Result<Value, SceneError>) -> Result<Value, SceneError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested deeper than {} levels", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Value) -> Result<Value, SceneError> {
+        for expected in keyword.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error(&format!("expected '{}'", keyword)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, SceneError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("invalid number '{}'", text)))
+    }
+
+    fn parse_string(&mut self) -> Result<String, SceneError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('u') => {
+                        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| self.error("invalid unicode escape"))?;
+                        string.push(c);
+                    },
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // --- a trailing comma before ']' or '}' is tolerated, it is an easy slip when editing by hand
+    fn parse_array(&mut self) -> Result<Value, SceneError> {
+        self.expect('[')?;
+        let mut values = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {},
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, SceneError> {
+        self.expect('{')?;
+        let mut members = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.next();
+                return Ok(Value::Object(members));
+            }
+
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((name, value));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {},
+                Some('}') => return Ok(Value::Object(members)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        let value = Value::Object(vec![
+            (String::from("name"), Value::String(String::from("a \"quoted\"\n\u{1}name"))),
+            (String::from("vector"), Value::Array(vec![Value::from_f32(0.1), Value::from_f32(-2.5), Value::from_f32(1e-7)])),
+            (String::from("flags"), Value::Array(vec![Value::Bool(true), Value::Bool(false), Value::Null])),
+            (String::from("nested"), Value::Array(vec![Value::Object(vec![]), Value::Array(vec![])])),
+        ]);
+        let text = value.to_pretty_string();
+        assert_eq!(parse(&text).unwrap(), value);
+        assert_eq!(parse(&text).unwrap().get("vector").unwrap().as_array().unwrap()[0].as_f32(), Some(0.1));
+    }
+
+    #[test]
+    fn non_finite_numbers_are_written_as_null() {
+        let value = Value::Array(vec![
+            Value::from_f32(std::f32::NAN),
+            Value::from_f32(std::f32::INFINITY),
+            Value::from_f32(std::f32::NEG_INFINITY),
+            Value::from_f32(1.5),
+        ]);
+        let text = value.to_pretty_string();
+        assert_eq!(text, "[null, null, null, 1.5]\n");
+        assert_eq!(parse(&text).unwrap(), Value::Array(vec![Value::Null, Value::Null, Value::Null, Value::Number(1.5)]));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        match parse(&nested(MAX_DEPTH + 1)) {
+            Err(SceneError::Parse { line, .. }) => assert_eq!(line, 1),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        // --- deep enough to overflow the stack without the limit
+        assert!(parse(&"{\"a\": ".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        match parse("{\n  \"a\": 1,\n  \"b\": tru }") {
+            Err(SceneError::Parse { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(parse("[1, 2] 3").is_err());
+        assert!(parse("\"unterminated").is_err());
+    }
+}
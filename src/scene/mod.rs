@@ -0,0 +1,697 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use cgmath::InnerSpace;
+
+use crate::components;
+use crate::geometry;
+use crate::material;
+use crate::world::name::NameError;
+use crate::world::{Without, World};
+
+pub mod json;
+pub mod prefab;
+
+use json::Value;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    Invalid(String),
+    UnknownMesh(String),
+    Name(NameError),
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        SceneError::Io(error)
+    }
+}
+
+impl From<NameError> for SceneError {
+    fn from(error: NameError) -> Self {
+        SceneError::Name(error)
+    }
+}
+
+// --- CPU-side description of an entity; GPU resources are referenced by name and
+// --- rebuilt when the scene is spawned
+#[derive(Clone, Debug, Default)]
+pub struct EntityDescription {
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub transform: Option<components::Transform>,
+    pub velocity: Option<components::Velocity>,
+    pub mesh: Option<String>,
+    pub material: Option<components::MaterialSource>,
+    pub pbr_material: Option<components::PBRMaterial>,
+    // --- index of the parent in SceneDescription::entities; prefab templates have none
+    pub parent: Option<usize>,
+}
+
+impl EntityDescription {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.tags.is_empty()
+            && self.transform.is_none()
+            && self.velocity.is_none()
+            && self.mesh.is_none()
+            && self.material.is_none()
+            && self.pbr_material.is_none()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SceneDescription {
+    pub entities: Vec<EntityDescription>,
+}
+
+// --- entities without any serializable component (e.g. the deferred light pass) are skipped,
+// --- their children are saved as roots; meshes and materials are only saved if they carry a
+// --- MeshSource / MaterialSource
+pub fn capture(world: &mut World) -> SceneDescription {
+    let described: Vec<(components::Entity, EntityDescription, Option<components::Tags>)> = world
+        .query::<(
+            components::Entity,
+            Option<&components::Name>,
+            Option<&components::Tags>,
+            Option<&components::Transform>,
+            Option<&components::Velocity>,
+            Option<&components::MeshSource>,
+            Option<&components::MaterialSource>,
+            Option<&components::PBRMaterial>,
+        )>()
+        .map(|(entity, name, tags, transform, velocity, mesh, material, pbr_material)| {
+            let description = EntityDescription {
+                name: name.map(|name| String::from(name.as_str())),
+                tags: vec![],
+                transform: transform.copied(),
+                velocity: velocity.copied(),
+                mesh: mesh.map(|mesh| mesh.name.clone()),
+                material: material.cloned(),
+                pbr_material: pbr_material.copied(),
+                parent: None,
+            };
+            (entity, description, tags.cloned())
+        })
+        .filter(|(_, description, _)| !description.is_empty())
+        .collect();
+
+    let indices: HashMap<components::Entity, usize> = described
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, _, _))| (*entity, index))
+        .collect();
+
+    // --- tag strings and parents live in the world, so they are resolved once the query is done
+    let entities = described
+        .into_iter()
+        .map(|(entity, mut description, tags)| {
+            if let Some(tags) = tags {
+                description.tags = tags
+                    .iter()
+                    .filter_map(|tag| world.tag_name(tag).map(String::from))
+                    .collect();
+            }
+            description.parent = world
+                .get::<components::Parent>(entity)
+                .and_then(|parent| indices.get(&parent.entity).copied());
+            description
+        })
+        .collect();
+
+    SceneDescription { entities: entities }
+}
+
+// --- builds the described entities with their CPU components, then parents them; meshes and
+// --- materials are attached as MeshSource / MaterialSource and turned into GPU resources by
+// --- build_gpu_resources. Stops at the first name that is already taken in the world or parent
+// --- that would close a loop (from_str rejects those); what was spawned until then stays
+pub fn spawn(world: &mut World, scene: &SceneDescription) -> Result<Vec<components::Entity>, SceneError> {
+    let spawned = scene
+        .entities
+        .iter()
+        .map(|description| spawn_entity(world, description))
+        .collect::<Result<Vec<Option<components::Entity>>, NameError>>()?;
+
+    for (index, (description, entity)) in scene.entities.iter().zip(spawned.iter()).enumerate() {
+        let parent = description.parent.and_then(|parent| spawned.get(parent).copied().flatten());
+        if let (Some(entity), Some(parent)) = (entity, parent) {
+            world
+                .set_parent(*entity, parent)
+                .map_err(|_| SceneError::Invalid(format!("entities[{}] is its own ancestor", index)))?;
+        }
+    }
+
+    Ok(spawned.into_iter().flatten().collect())
+}
+
+// --- None for an empty description
+pub fn spawn_entity(world: &mut World, description: &EntityDescription) -> Result<Option<components::Entity>, NameError> {
+    if description.is_empty() {
+        return Ok(None);
+    }
+
+    let tags: Vec<components::Tag> = description.tags.iter().map(|tag| world.tag(tag)).collect();
+
+    world.create_entity();
+    if let Some(name) = &description.name {
+        world.with(components::Name::new(name));
+    }
+    if !tags.is_empty() {
+        let mut set = components::Tags::default();
+        tags.into_iter().for_each(|tag| set.insert(tag));
+        world.with(set);
+    }
+    if let Some(transform) = description.transform {
+        world.with(transform);
+    }
+    if let Some(velocity) = description.velocity {
+        world.with(velocity);
+    }
+    if let Some(name) = &description.mesh {
+        world.with(components::MeshSource { name: name.clone() });
+        // --- unknown names are reported by build_gpu_resources
+        if let Some(geometry) = geometry::generate(name) {
+            world.with(geometry::bounds(&geometry));
+        }
+    }
+    if let Some(source) = &description.material {
+        world.with(source.clone());
+    }
+    if let Some(pbr_material) = description.pbr_material {
+        world.with(pbr_material);
+    }
+
+    world.try_build().map(Some)
+}
+
+// --- creates the Mesh / Material of every entity that has a source but no GPU resource yet;
+// --- create_mesh turns a mesh name into GPU buffers, create_material builds a Material from
+// --- shader paths. Stops at the first unknown mesh name, earlier entities keep their meshes
+pub fn build_gpu_resources<M, T>(world: &mut World, mut create_mesh: M, mut create_material: T) -> Result<(), SceneError>
+where
+    M: FnMut(&str) -> Option<components::Mesh>,
+    T: FnMut(&components::MaterialSource) -> components::Material,
+{
+    let pending_meshes: Vec<(components::Entity, String)> = world
+        .query_filtered::<(components::Entity, &components::MeshSource), Without<components::Mesh>>()
+        .map(|(entity, source)| (entity, source.name.clone()))
+        .collect();
+    for (entity, name) in pending_meshes {
+        let mesh = create_mesh(&name).ok_or_else(|| SceneError::UnknownMesh(name))?;
+        world.insert(entity, mesh);
+    }
+
+    let pending_materials: Vec<(components::Entity, components::MaterialSource)> = world
+        .query_filtered::<(components::Entity, &components::MaterialSource), Without<components::Material>>()
+        .map(|(entity, source)| (entity, source.clone()))
+        .collect();
+    for (entity, source) in pending_materials {
+        let material = create_material(&source);
+        world.insert(entity, material);
+    }
+
+    Ok(())
+}
+
+pub fn save(world: &mut World, path: &Path) -> Result<(), SceneError> {
+    fs::write(path, to_string(&capture(world)))?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<SceneDescription, SceneError> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+pub fn to_string(scene: &SceneDescription) -> String {
+    let entities = scene.entities.iter().map(entity_to_value).collect();
+    Value::Object(vec![(String::from("entities"), Value::Array(entities))]).to_pretty_string()
+}
+
+pub fn from_str(text: &str) -> Result<SceneDescription, SceneError> {
+    let root = json::parse(text)?;
+    check_fields(&root, "scene", &["entities"])?;
+
+    let entities = match root.get("entities") {
+        Some(entities) => entities
+            .as_array()
+            .ok_or_else(|| invalid("'entities' must be an array"))?
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| entity_from_value(entity, &format!("entities[{}]", index)))
+            .collect::<Result<Vec<EntityDescription>, SceneError>>()?,
+        None => vec![],
+    };
+
+    let mut names = HashSet::new();
+    if let Some(name) = entities.iter().filter_map(|entity| entity.name.as_ref()).find(|name| !names.insert(*name)) {
+        return Err(SceneError::Invalid(format!("entity name '{}' is used more than once", name)));
+    }
+
+    for (index, entity) in entities.iter().enumerate() {
+        // --- following at most len parents from any entity either ends at a root or is a loop
+        let mut current = entity.parent;
+        for _ in 0..entities.len() {
+            match current {
+                Some(parent) if parent >= entities.len() => {
+                    return Err(SceneError::Invalid(format!("entities[{}].parent {} is out of range", index, parent)))
+                },
+                Some(parent) => current = entities[parent].parent,
+                None => break,
+            }
+        }
+        if current.is_some() {
+            return Err(SceneError::Invalid(format!("entities[{}] is its own ancestor", index)));
+        }
+    }
+
+    Ok(SceneDescription { entities: entities })
+}
+
+fn invalid(message: &str) -> SceneError {
+    SceneError::Invalid(String::from(message))
+}
+
+fn vector_to_value(vector: cgmath::Vector3<f32>) -> Value {
+    Value::Array(vec![
+        Value::from_f32(vector.x),
+        Value::from_f32(vector.y),
+        Value::from_f32(vector.z),
+    ])
+}
+
+// --- quaternions are written as [x, y, z, w]
+fn quaternion_to_value(quaternion: cgmath::Quaternion<f32>) -> Value {
+    Value::Array(vec![
+        Value::from_f32(quaternion.v.x),
+        Value::from_f32(quaternion.v.y),
+        Value::from_f32(quaternion.v.z),
+        Value::from_f32(quaternion.s),
+    ])
+}
+
+fn material_type_name(material_type: components::PBRMaterialType) -> &'static str {
+    match material_type {
+        components::PBRMaterialType::Pure => "Pure",
+        components::PBRMaterialType::Coated => "Coated",
+        components::PBRMaterialType::PureEmissive => "PureEmissive",
+    }
+}
+
+fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(name, value)| (String::from(name), value))
+            .collect(),
+    )
+}
+
+fn entity_to_value(entity: &EntityDescription) -> Value {
+    let mut members = vec![];
+    if let Some(name) = &entity.name {
+        members.push(("name", Value::String(name.clone())));
+    }
+    if !entity.tags.is_empty() {
+        members.push(("tags", Value::Array(entity.tags.iter().cloned().map(Value::String).collect())));
+    }
+    if let Some(transform) = &entity.transform {
+        members.push(("transform", object(vec![
+            ("position", vector_to_value(transform.position)),
+            ("rotation", quaternion_to_value(transform.rotation)),
+            ("scale", vector_to_value(transform.scale)),
+        ])));
+    }
+    if let Some(velocity) = &entity.velocity {
+        members.push(("velocity", object(vec![
+            ("translation_speed", vector_to_value(velocity.translation_speed)),
+            ("rotation_speed", vector_to_value(velocity.rotation_speed)),
+        ])));
+    }
+    if let Some(mesh) = &entity.mesh {
+        members.push(("mesh", Value::String(mesh.clone())));
+    }
+    if let Some(material) = &entity.material {
+        members.push(("material", object(vec![
+            ("vertex_shader", Value::String(material.vertex_shader.clone())),
+            ("fragment_shader", Value::String(material.fragment_shader.clone())),
+        ])));
+    }
+    if let Some(pbr_material) = &entity.pbr_material {
+        members.push(("pbr_material", object(vec![
+            ("albedo", vector_to_value(pbr_material.albedo)),
+            ("f0_reflectance", vector_to_value(pbr_material.f0_reflectance)),
+            ("roughness", Value::from_f32(pbr_material.roughness)),
+            ("metalness", Value::from_f32(pbr_material.metalness)),
+            ("material_type", Value::String(String::from(material_type_name(pbr_material.material_type)))),
+            ("emissive_color", vector_to_value(pbr_material.emissive_color)),
+        ])));
+    }
+    if let Some(parent) = entity.parent {
+        members.push(("parent", Value::Number(parent as f64)));
+    }
+    object(members)
+}
+
+// --- unknown keys are rejected so a typo in a hand-edited file does not go unnoticed
+fn check_fields(value: &Value, context: &str, allowed: &[&str]) -> Result<(), SceneError> {
+    match value {
+        Value::Object(members) => match members.iter().find(|(name, _)| !allowed.contains(&name.as_str())) {
+            Some((name, _)) => Err(SceneError::Invalid(format!("unknown field '{}' in {}", name, context))),
+            None => Ok(()),
+        },
+        _ => Err(SceneError::Invalid(format!("{} must be an object", context))),
+    }
+}
+
+fn field<'a>(value: &'a Value, name: &str, context: &str) -> Result<&'a Value, SceneError> {
+    value
+        .get(name)
+        .ok_or_else(|| SceneError::Invalid(format!("missing field '{}' in {}", name, context)))
+}
+
+fn vector_from_value(value: &Value, context: &str) -> Result<cgmath::Vector3<f32>, SceneError> {
+    if let Some([x, y, z]) = value.as_array() {
+        if let (Some(x), Some(y), Some(z)) = (x.as_f32(), y.as_f32(), z.as_f32()) {
+            return Ok(cgmath::Vector3 { x: x, y: y, z: z });
+        }
+    }
+    Err(SceneError::Invalid(format!("{} must be an array of 3 numbers", context)))
+}
+
+// --- a quaternion [x, y, z, w], or Euler angles [x, y, z] in radians as older scenes store them
+fn rotation_from_value(value: &Value, context: &str) -> Result<cgmath::Quaternion<f32>, SceneError> {
+    if let Some([x, y, z, w]) = value.as_array() {
+        if let (Some(x), Some(y), Some(z), Some(w)) = (x.as_f32(), y.as_f32(), z.as_f32(), w.as_f32()) {
+            // --- saved rotations read back bit for bit, hand-written ones get normalized
+            let quaternion = cgmath::Quaternion::new(w, x, y, z);
+            if (quaternion.magnitude2() - 1.0).abs() < 1e-6 {
+                return Ok(quaternion);
+            }
+            if quaternion.magnitude2() > 1e-12 {
+                return Ok(quaternion.normalize());
+            }
+        }
+    }
+    vector_from_value(value, context)
+        .map(components::Transform::euler)
+        .map_err(|_| SceneError::Invalid(format!("{} must be a quaternion [x, y, z, w] or 3 Euler angles", context)))
+}
+
+fn optional_rotation(value: &Value, name: &str, context: &str) -> Result<Option<cgmath::Quaternion<f32>>, SceneError> {
+    match value.get(name) {
+        Some(rotation) => rotation_from_value(rotation, &format!("{}.{}", context, name)).map(Some),
+        None => Ok(None),
+    }
+}
+
+// --- missing vectors fall back to the given default, e.g. a scale of one
+fn optional_vector(value: &Value, name: &str, context: &str, default: f32) -> Result<cgmath::Vector3<f32>, SceneError> {
+    match value.get(name) {
+        Some(vector) => vector_from_value(vector, &format!("{}.{}", context, name)),
+        None => Ok(cgmath::Vector3 { x: default, y: default, z: default }),
+    }
+}
+
+fn number_from_value(value: &Value, name: &str, context: &str) -> Result<f32, SceneError> {
+    field(value, name, context)?
+        .as_f32()
+        .ok_or_else(|| SceneError::Invalid(format!("{}.{} must be a number", context, name)))
+}
+
+fn string_from_value(value: &Value, name: &str, context: &str) -> Result<String, SceneError> {
+    field(value, name, context)?
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| SceneError::Invalid(format!("{}.{} must be a string", context, name)))
+}
+
+fn velocity_from_value(value: &Value, context: &str) -> Result<components::Velocity, SceneError> {
+    check_fields(value, context, &["translation_speed", "rotation_speed"])?;
+    Ok(components::Velocity {
+        translation_speed: optional_vector(value, "translation_speed", context, 0.0)?,
+        rotation_speed: optional_vector(value, "rotation_speed", context, 0.0)?,
+    })
+}
+
+fn material_from_value(value: &Value, context: &str) -> Result<components::MaterialSource, SceneError> {
+    check_fields(value, context, &["vertex_shader", "fragment_shader"])?;
+    Ok(components::MaterialSource {
+        vertex_shader: string_from_value(value, "vertex_shader", context)?,
+        fragment_shader: string_from_value(value, "fragment_shader", context)?,
+    })
+}
+
+// --- either a full description or the name of a preset from material::Materials, e.g. "RoughCopper"
+fn pbr_material_from_value(value: &Value, context: &str) -> Result<components::PBRMaterial, SceneError> {
+    if let Some(preset) = value.as_str() {
+        return material::Materials::from_name(preset)
+            .map(|preset| preset.get())
+            .ok_or_else(|| SceneError::Invalid(format!("{} '{}' is not a material preset", context, preset)));
+    }
+
+    check_fields(value, context, &[
+        "albedo", "f0_reflectance", "roughness", "metalness", "material_type", "emissive_color",
+    ])?;
+
+    let material_type = match string_from_value(value, "material_type", context)?.as_str() {
+        "Pure" => components::PBRMaterialType::Pure,
+        "Coated" => components::PBRMaterialType::Coated,
+        "PureEmissive" => components::PBRMaterialType::PureEmissive,
+        other => {
+            return Err(SceneError::Invalid(format!(
+                "{}.material_type '{}' is not one of Pure, Coated, PureEmissive",
+                context, other
+            )))
+        },
+    };
+
+    Ok(components::PBRMaterial {
+        albedo: vector_from_value(field(value, "albedo", context)?, &format!("{}.albedo", context))?,
+        f0_reflectance: vector_from_value(
+            field(value, "f0_reflectance", context)?,
+            &format!("{}.f0_reflectance", context),
+        )?,
+        roughness: number_from_value(value, "roughness", context)?,
+        metalness: number_from_value(value, "metalness", context)?,
+        material_type: material_type,
+        emissive_color: optional_vector(value, "emissive_color", context, 0.0)?,
+    })
+}
+
+fn entity_from_value(value: &Value, context: &str) -> Result<EntityDescription, SceneError> {
+    check_fields(value, context, &[
+        "name", "tags", "transform", "velocity", "mesh", "material", "pbr_material", "parent",
+    ])?;
+
+    let mut entity = EntityDescription::default();
+
+    if value.get("name").is_some() {
+        entity.name = Some(string_from_value(value, "name", context)?);
+    }
+
+    if let Some(tags) = value.get("tags") {
+        entity.tags = tags
+            .as_array()
+            .and_then(|tags| tags.iter().map(|tag| tag.as_str().map(String::from)).collect::<Option<Vec<String>>>())
+            .ok_or_else(|| SceneError::Invalid(format!("{}.tags must be an array of strings", context)))?;
+    }
+
+    if let Some(transform) = value.get("transform") {
+        let context = format!("{}.transform", context);
+        check_fields(transform, &context, &["position", "rotation", "scale"])?;
+        entity.transform = Some(components::Transform {
+            position: optional_vector(transform, "position", &context, 0.0)?,
+            rotation: optional_rotation(transform, "rotation", &context)?.unwrap_or(cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+            scale: optional_vector(transform, "scale", &context, 1.0)?,
+        });
+    }
+
+    if let Some(velocity) = value.get("velocity") {
+        entity.velocity = Some(velocity_from_value(velocity, &format!("{}.velocity", context))?);
+    }
+
+    if value.get("mesh").is_some() {
+        entity.mesh = Some(string_from_value(value, "mesh", context)?);
+    }
+
+    if let Some(material) = value.get("material") {
+        entity.material = Some(material_from_value(material, &format!("{}.material", context))?);
+    }
+
+    if let Some(pbr_material) = value.get("pbr_material") {
+        entity.pbr_material = Some(pbr_material_from_value(pbr_material, &format!("{}.pbr_material", context))?);
+    }
+
+    if let Some(parent) = value.get("parent") {
+        entity.parent = Some(
+            parent
+                .as_index()
+                .ok_or_else(|| SceneError::Invalid(format!("{}.parent must be an entity index", context)))?,
+        );
+    }
+
+    Ok(entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(x: f32, angle: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: -2.5, z: 0.1 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: angle, y: 0.0, z: 1e-7 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 2.0, z: 3.0 },
+        }
+    }
+
+    fn named(world: &World, name: &str) -> components::Entity {
+        world.find_by_name(name).expect("missing entity")
+    }
+
+    #[test]
+    fn capture_save_load_spawn_round_trip() {
+        let mut world = World::new();
+        let root = world
+            .create_entity()
+            .with(components::Name::new("root"))
+            .with(transform(1.0, 0.3))
+            .with(components::Velocity {
+                translation_speed: cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 },
+                rotation_speed: cgmath::Vector3 { x: 0.0, y: 1.5, z: 0.0 },
+            })
+            .with(components::MeshSource { name: String::from("cube") })
+            .with(components::MaterialSource {
+                vertex_shader: String::from("gbuffer_vert.spv"),
+                fragment_shader: String::from("gbuffer \"frag\".spv"),
+            })
+            .with(material::Materials::RoughCopper.get())
+            .build();
+        let child = world.create_entity().with(components::Name::new("child")).with(transform(2.0, 0.0)).build();
+        let grandchild = world.create_entity().with(components::Name::new("grandchild")).with(transform(3.0, -1.0)).build();
+        // --- nothing of it is saved, so its child is saved as a root
+        let unsaved = world.create_entity().with(7u32).build();
+        let orphan = world.create_entity().with(components::Name::new("orphan")).build();
+        world.add_tag(child, "enemy");
+        world.add_tag(child, "boss");
+        world.set_parent(child, root).unwrap();
+        world.set_parent(grandchild, child).unwrap();
+        world.set_parent(orphan, unsaved).unwrap();
+
+        let text = to_string(&capture(&mut world));
+        let scene = from_str(&text).unwrap();
+        assert_eq!(scene.entities.len(), 4);
+        assert_eq!(to_string(&scene), text);
+
+        let mut loaded = World::new();
+        let spawned = spawn(&mut loaded, &scene).unwrap();
+        assert_eq!(spawned.len(), 4);
+
+        let (root, child, grandchild, orphan) = (
+            named(&loaded, "root"),
+            named(&loaded, "child"),
+            named(&loaded, "grandchild"),
+            named(&loaded, "orphan"),
+        );
+        assert!(loaded.get::<components::Parent>(root).is_none());
+        assert_eq!(loaded.get::<components::Parent>(child), Some(&components::Parent { entity: root }));
+        assert_eq!(loaded.get::<components::Parent>(grandchild), Some(&components::Parent { entity: child }));
+        assert_eq!(loaded.children_of(root), &[child]);
+        assert!(loaded.get::<components::Parent>(orphan).is_none());
+
+        let saved = world.get::<components::Transform>(world.find_by_name("grandchild").unwrap()).unwrap();
+        let restored = loaded.get::<components::Transform>(grandchild).unwrap();
+        assert_eq!(restored.position, saved.position);
+        assert_eq!(restored.rotation, saved.rotation);
+        assert_eq!(restored.scale, saved.scale);
+        assert_eq!(loaded.get::<components::Velocity>(root).unwrap().rotation_speed.y, 1.5);
+        assert_eq!(loaded.get::<components::MeshSource>(root).unwrap().name, "cube");
+        assert_eq!(loaded.get::<components::MaterialSource>(root).unwrap().fragment_shader, "gbuffer \"frag\".spv");
+        assert_eq!(
+            loaded.get::<components::PBRMaterial>(root).unwrap().roughness,
+            material::Materials::RoughCopper.get().roughness
+        );
+        assert!(loaded.get::<components::Bounds>(root).is_some());
+        assert!(loaded.has_tag(child, "enemy") && loaded.has_tag(child, "boss"));
+    }
+
+    #[test]
+    fn non_finite_values_do_not_load() {
+        let mut world = World::new();
+        let mut broken = transform(0.0, 0.0);
+        broken.position.x = std::f32::NAN;
+        world.create_entity().with(broken).build();
+
+        let text = to_string(&capture(&mut world));
+        assert!(text.contains("null"));
+        match from_str(&text) {
+            Err(SceneError::Invalid(_)) => {},
+            other => panic!("expected an invalid scene, got {:?}", other),
+        }
+
+        // --- valid JSON numbers that overflow f32
+        for overflow in ["1e39", "-1e39", "1e999"].iter() {
+            match from_str(&text.replacen("null", overflow, 1)) {
+                Err(SceneError::Invalid(_)) => {},
+                other => panic!("expected an invalid scene for {}, got {:?}", overflow, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parents_must_exist_and_not_loop() {
+        assert!(from_str(r#"{ "entities": [ { "name": "a" }, { "name": "b", "parent": 0 } ] }"#).is_ok());
+        for text in [
+            r#"{ "entities": [ { "name": "a", "parent": 1 } ] }"#,
+            r#"{ "entities": [ { "name": "a", "parent": 0 } ] }"#,
+            r#"{ "entities": [ { "name": "a", "parent": 1 }, { "name": "b", "parent": 0 } ] }"#,
+            r#"{ "entities": [ { "name": "a", "parent": -1 } ] }"#,
+            r#"{ "entities": [ { "name": "a", "parent": 0.5 } ] }"#,
+        ]
+        .iter()
+        {
+            match from_str(text) {
+                Err(SceneError::Invalid(_)) => {},
+                other => panic!("expected an invalid scene for {}, got {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn taken_names_fail_the_spawn() {
+        let mut world = World::new();
+        let existing = world.create_entity().with(components::Name::new("taken")).build();
+
+        let scene = from_str(r#"{ "entities": [ { "name": "free" }, { "name": "taken" }, { "name": "later" } ] }"#).unwrap();
+        match spawn(&mut world, &scene) {
+            Err(SceneError::Name(NameError::Taken(owner))) => assert_eq!(owner, existing),
+            other => panic!("expected a taken name, got {:?}", other),
+        }
+        // --- entities before the failing one stay, the failing one leaves nothing behind
+        assert!(world.find_by_name("free").is_some());
+        assert!(world.find_by_name("later").is_none());
+        assert_eq!(world.find_by_name("taken"), Some(existing));
+        assert_eq!(world.entity_count(), 2);
+
+        world.register_prefab("thing", prefab::Prefab::new(EntityDescription {
+            mesh: Some(String::from("cube")),
+            ..EntityDescription::default()
+        }));
+        assert_eq!(
+            world.spawn_prefab("thing", prefab::PrefabOverrides::new().name("taken")),
+            Err(NameError::Taken(existing))
+        );
+        assert!(world.spawn_prefab("thing", prefab::PrefabOverrides::new().name("other")).unwrap().is_some());
+        assert_eq!(world.spawn_prefab("missing", prefab::PrefabOverrides::new()), Ok(None));
+
+        let file = prefab::from_str(r#"{ "instances": [ { "prefab": "thing", "name": "other" } ] }"#).unwrap();
+        match prefab::spawn(&mut world, &file) {
+            Err(SceneError::Name(NameError::Taken(_))) => {},
+            other => panic!("expected a taken name, got {:?}", other),
+        }
+    }
+}
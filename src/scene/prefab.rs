@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::components;
+use crate::scene::json::{self, Value};
+use crate::scene::{
+    check_fields, entity_from_value, material_from_value, optional_rotation, pbr_material_from_value,
+    spawn_entity, string_from_value, vector_from_value, velocity_from_value, EntityDescription, SceneError,
+};
+use crate::world::name::NameError;
+use crate::world::World;
+
+// --- template for many similar entities; instances start from a copy and apply overrides
+#[derive(Clone, Debug, Default)]
+pub struct Prefab {
+    pub template: EntityDescription,
+}
+
+// --- per-instance changes; transform parts can be overridden separately
+#[derive(Clone, Debug, Default)]
+pub struct PrefabOverrides {
+    pub name: Option<String>,
+    pub position: Option<cgmath::Vector3<f32>>,
+    pub rotation: Option<cgmath::Quaternion<f32>>,
+    pub scale: Option<cgmath::Vector3<f32>>,
+    pub velocity: Option<components::Velocity>,
+    pub mesh: Option<String>,
+    pub material: Option<components::MaterialSource>,
+    pub pbr_material: Option<components::PBRMaterial>,
+}
+
+impl PrefabOverrides {
+    pub fn new() -> PrefabOverrides {
+        PrefabOverrides::default()
+    }
+
+    pub fn name(mut self, name: &str) -> PrefabOverrides {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    pub fn position(mut self, position: cgmath::Vector3<f32>) -> PrefabOverrides {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn rotation(mut self, rotation: cgmath::Quaternion<f32>) -> PrefabOverrides {
+        self.rotation = Some(rotation);
+        self
+    }
+
+    pub fn scale(mut self, scale: cgmath::Vector3<f32>) -> PrefabOverrides {
+        self.scale = Some(scale);
+        self
+    }
+
+    pub fn velocity(mut self, velocity: components::Velocity) -> PrefabOverrides {
+        self.velocity = Some(velocity);
+        self
+    }
+
+    pub fn mesh(mut self, mesh: &str) -> PrefabOverrides {
+        self.mesh = Some(String::from(mesh));
+        self
+    }
+
+    pub fn material(mut self, material: components::MaterialSource) -> PrefabOverrides {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn pbr_material(mut self, pbr_material: components::PBRMaterial) -> PrefabOverrides {
+        self.pbr_material = Some(pbr_material);
+        self
+    }
+}
+
+impl Prefab {
+    pub fn new(template: EntityDescription) -> Prefab {
+        Prefab { template: template }
+    }
+
+    // --- templates are usually unnamed, a name given to the template makes it a one-off.
+    // --- A transform override on a template without one starts from the identity transform
+    pub fn instantiate(&self, overrides: &PrefabOverrides) -> EntityDescription {
+        let mut description = self.template.clone();
+        if let Some(name) = &overrides.name {
+            description.name = Some(name.clone());
+        }
+
+        if overrides.position.is_some() || overrides.rotation.is_some() || overrides.scale.is_some() {
+            let mut transform = description.transform.unwrap_or(components::Transform {
+                position: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+            });
+            transform.position = overrides.position.unwrap_or(transform.position);
+            transform.rotation = overrides.rotation.unwrap_or(transform.rotation);
+            transform.scale = overrides.scale.unwrap_or(transform.scale);
+            description.transform = Some(transform);
+        }
+        if let Some(velocity) = overrides.velocity {
+            description.velocity = Some(velocity);
+        }
+        if let Some(mesh) = &overrides.mesh {
+            description.mesh = Some(mesh.clone());
+        }
+        if let Some(material) = &overrides.material {
+            description.material = Some(material.clone());
+        }
+        if let Some(pbr_material) = overrides.pbr_material {
+            description.pbr_material = Some(pbr_material);
+        }
+
+        description
+    }
+}
+
+// --- registered prefabs, kept as a world resource
+#[derive(Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl Prefabs {
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.prefabs.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+impl World {
+    // --- registering a name again replaces the template; existing instances are not touched
+    pub fn register_prefab(&mut self, name: &str, prefab: Prefab) {
+        if !self.has_resource::<Prefabs>() {
+            self.insert_resource(Prefabs::default());
+        }
+        self.resource_mut::<Prefabs>()
+            .unwrap()
+            .prefabs
+            .insert(String::from(name), prefab);
+    }
+
+    pub fn prefab(&self, name: &str) -> Option<&Prefab> {
+        self.resource::<Prefabs>()?.get(name)
+    }
+
+    // --- None for an unknown name or an empty template; like scene::spawn the instance only gets
+    // --- MeshSource / MaterialSource, scene::build_gpu_resources creates the GPU side
+    pub fn spawn_prefab(&mut self, name: &str, overrides: PrefabOverrides) -> Result<Option<components::Entity>, NameError> {
+        let description = match self.prefab(name) {
+            Some(prefab) => prefab.instantiate(&overrides),
+            None => return Ok(None),
+        };
+        spawn_entity(self, &description)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PrefabInstance {
+    pub prefab: String,
+    pub overrides: PrefabOverrides,
+}
+
+// --- a data file of named templates plus a list of instances to place
+#[derive(Clone, Debug, Default)]
+pub struct PrefabFile {
+    pub prefabs: Vec<(String, Prefab)>,
+    pub instances: Vec<PrefabInstance>,
+}
+
+// --- registers the file's prefabs, then spawns its instances in order; instances may also
+// --- use prefabs registered earlier
+pub fn spawn(world: &mut World, file: &PrefabFile) -> Result<Vec<components::Entity>, SceneError> {
+    for (name, prefab) in &file.prefabs {
+        world.register_prefab(name, prefab.clone());
+    }
+
+    let mut entities = vec![];
+    for instance in &file.instances {
+        let description = world
+            .prefab(&instance.prefab)
+            .ok_or_else(|| SceneError::Invalid(format!("unknown prefab '{}'", instance.prefab)))?
+            .instantiate(&instance.overrides);
+        entities.extend(spawn_entity(world, &description)?);
+    }
+
+    Ok(entities)
+}
+
+pub fn load(path: &Path) -> Result<PrefabFile, SceneError> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+// --- {
+// ---   "prefabs": { "dodeca": { "mesh": "dodecahedron", "pbr_material": "RoughCopper", ... } },
+// ---   "instances": [ { "prefab": "dodeca", "position": [0, 1, 0], "pbr_material": "Gold" } ]
+// --- }
+pub fn from_str(text: &str) -> Result<PrefabFile, SceneError> {
+    let root = json::parse(text)?;
+    check_fields(&root, "prefab file", &["prefabs", "instances"])?;
+
+    let mut file = PrefabFile::default();
+
+    match root.get("prefabs") {
+        Some(Value::Object(members)) => {
+            for (name, template) in members {
+                let context = format!("prefabs.{}", name);
+                let template = entity_from_value(template, &context)?;
+                if template.parent.is_some() {
+                    return Err(SceneError::Invalid(format!("{}.parent is only valid in scenes", context)));
+                }
+                file.prefabs.push((name.clone(), Prefab::new(template)));
+            }
+        },
+        Some(_) => return Err(SceneError::Invalid(String::from("'prefabs' must be an object"))),
+        None => {},
+    }
+
+    if let Some(instances) = root.get("instances") {
+        let instances = instances
+            .as_array()
+            .ok_or_else(|| SceneError::Invalid(String::from("'instances' must be an array")))?;
+        for (index, instance) in instances.iter().enumerate() {
+            file.instances.push(instance_from_value(instance, &format!("instances[{}]", index))?);
+        }
+    }
+
+    Ok(file)
+}
+
+fn instance_from_value(value: &Value, context: &str) -> Result<PrefabInstance, SceneError> {
+    check_fields(value, context, &[
+        "prefab", "name", "position", "rotation", "scale", "velocity", "mesh", "material", "pbr_material",
+    ])?;
+
+    let optional = |name: &str| -> Result<Option<cgmath::Vector3<f32>>, SceneError> {
+        match value.get(name) {
+            Some(vector) => vector_from_value(vector, &format!("{}.{}", context, name)).map(Some),
+            None => Ok(None),
+        }
+    };
+
+    let mut overrides = PrefabOverrides {
+        position: optional("position")?,
+        rotation: optional_rotation(value, "rotation", context)?,
+        scale: optional("scale")?,
+        ..PrefabOverrides::default()
+    };
+    if value.get("name").is_some() {
+        overrides.name = Some(string_from_value(value, "name", context)?);
+    }
+    if let Some(velocity) = value.get("velocity") {
+        overrides.velocity = Some(velocity_from_value(velocity, &format!("{}.velocity", context))?);
+    }
+    if value.get("mesh").is_some() {
+        overrides.mesh = Some(string_from_value(value, "mesh", context)?);
+    }
+    if let Some(material) = value.get("material") {
+        overrides.material = Some(material_from_value(material, &format!("{}.material", context))?);
+    }
+    if let Some(pbr_material) = value.get("pbr_material") {
+        overrides.pbr_material = Some(pbr_material_from_value(pbr_material, &format!("{}.pbr_material", context))?);
+    }
+
+    Ok(PrefabInstance {
+        prefab: string_from_value(value, "prefab", context)?,
+        overrides: overrides,
+    })
+}
@@ -0,0 +1,533 @@
+use std::any::{Any, TypeId};
+
+use scoped_threadpool::Pool;
+
+use crate::components;
+use crate::world::commands::EntityCommands;
+use crate::world::{Commands, Query, QueryFilter, QueryIter, Storable, World};
+
+pub const FIXED_UPDATE: &str = "fixed_update";
+pub const POST_UPDATE: &str = "post_update";
+pub const RENDER_PREPARE: &str = "render_prepare";
+
+pub trait System: Send {
+    fn name(&self) -> &str;
+    fn access(&self) -> SystemAccess;
+    fn run(&mut self, ctx: &mut SystemContext);
+
+    // --- state a system keeps between runs that later runs depend on, see Schedule::snapshot;
+    // --- restore gets back what snapshot returned
+    fn snapshot(&self) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+
+    fn restore(&mut self, _state: &dyn Any) {}
+}
+
+// --- components and resources a system declares up front; the scheduler only runs
+// --- systems side by side when none of them writes what another one touches
+#[derive(Clone, Default)]
+pub struct SystemAccess {
+    reads: Vec<(TypeId, fn(&mut World) -> components::ComponentId)>,
+    writes: Vec<(TypeId, fn(&mut World) -> components::ComponentId)>,
+    resource_reads: Vec<TypeId>,
+    resource_writes: Vec<TypeId>,
+    exclusive: bool,
+}
+
+fn register<T: Storable>(world: &mut World) -> components::ComponentId {
+    world.register_component::<T>()
+}
+
+impl SystemAccess {
+    pub fn new() -> SystemAccess {
+        SystemAccess::default()
+    }
+
+    // --- the system gets the whole world and always runs alone
+    pub fn exclusive() -> SystemAccess {
+        SystemAccess {
+            exclusive: true,
+            ..SystemAccess::default()
+        }
+    }
+
+    pub fn read<T: Storable>(mut self) -> SystemAccess {
+        self.reads.push((TypeId::of::<T>(), register::<T>));
+        self
+    }
+
+    pub fn write<T: Storable>(mut self) -> SystemAccess {
+        self.writes.push((TypeId::of::<T>(), register::<T>));
+        self
+    }
+
+    pub fn read_resource<T: Storable>(mut self) -> SystemAccess {
+        self.resource_reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write_resource<T: Storable>(mut self) -> SystemAccess {
+        self.resource_writes.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        if self.exclusive || other.exclusive {
+            return true;
+        }
+
+        let touches = |access: &SystemAccess, type_id: &TypeId| {
+            access.reads.iter().chain(access.writes.iter()).any(|(id, _)| id == type_id)
+        };
+        let touches_resource = |access: &SystemAccess, type_id: &TypeId| {
+            access.resource_reads.iter().chain(access.resource_writes.iter()).any(|id| id == type_id)
+        };
+        self.writes.iter().any(|(id, _)| touches(other, id))
+            || other.writes.iter().any(|(id, _)| touches(self, id))
+            || self.resource_writes.iter().any(|id| touches_resource(other, id))
+            || other.resource_writes.iter().any(|id| touches_resource(self, id))
+    }
+
+    fn signatures(&self, world: &mut World) -> (components::Signature, components::Signature) {
+        let mut reads = components::Signature::new();
+        let mut writes = components::Signature::new();
+        for (_, register) in self.reads.iter() {
+            reads.insert(register(world));
+        }
+        for (_, register) in self.writes.iter() {
+            writes.insert(register(world));
+        }
+        (reads, writes)
+    }
+}
+
+// --- a system's window onto the world, limited to what it declared
+pub struct SystemContext<'a> {
+    world: *mut World,
+    reads: components::Signature,
+    writes: components::Signature,
+    resource_reads: Vec<TypeId>,
+    resource_writes: Vec<TypeId>,
+    exclusive: bool,
+    // --- change tick of the system's previous run; Changed/Added filters compare against it
+    last_change_tick: u32,
+    commands: &'a mut Commands,
+}
+
+// --- the scheduler never hands the same storage or resource mutably to two contexts at once
+unsafe impl<'a> Send for SystemContext<'a> {}
+
+impl<'a> SystemContext<'a> {
+    pub fn query<Q: Query>(&mut self) -> QueryIter<'_, Q> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    pub fn query_filtered<Q: Query, F: QueryFilter>(&mut self) -> QueryIter<'_, Q> {
+        if self.exclusive {
+            let world = self.world_mut();
+            Q::register(world);
+            F::register(world);
+            return unsafe { QueryIter::new::<F>(self.world, self.last_change_tick) };
+        }
+
+        let world = unsafe { &*self.world };
+        let mut required = components::Signature::new();
+        let mut access = crate::world::query::Access::default();
+        Q::init(world, &mut required, &mut access);
+        F::init(world, self.last_change_tick, &mut access.reads);
+
+        assert!(
+            self.writes.contains_all(&access.writes),
+            "System queries a component it did not declare as written!"
+        );
+        let mut declared = self.reads.clone();
+        self.writes.ids().for_each(|id| declared.insert(id));
+        assert!(
+            declared.contains_all(&access.reads),
+            "System queries a component it did not declare as read!"
+        );
+
+        unsafe { QueryIter::new::<F>(self.world, self.last_change_tick) }
+    }
+
+    // --- panics if the resource was not declared or was never inserted
+    pub fn resource<T: Storable>(&self) -> &T {
+        let type_id = TypeId::of::<T>();
+        assert!(
+            self.exclusive || self.resource_reads.contains(&type_id) || self.resource_writes.contains(&type_id),
+            "System reads a resource it did not declare!"
+        );
+        unsafe { &*World::resource_ptr::<T>(self.world).expect("Resource was never inserted!") }
+    }
+
+    pub fn resource_mut<T: Storable>(&mut self) -> &mut T {
+        assert!(
+            self.exclusive || self.resource_writes.contains(&TypeId::of::<T>()),
+            "System writes a resource it did not declare as written!"
+        );
+        unsafe { &mut *World::resource_mut_ptr::<T>(self.world).expect("Resource was never inserted!") }
+    }
+
+    // --- structural changes; applied once the whole stage has run, in system order
+    pub fn commands(&mut self) -> &mut Commands {
+        self.commands
+    }
+
+    // --- queues a new entity; the returned handle is valid for later commands right away
+    pub fn create_entity(&mut self) -> EntityCommands<'_> {
+        // --- reserving only reads the entity tables, which no system changes during a stage
+        let world = unsafe { &*self.world };
+        self.commands.create_entity(world)
+    }
+
+    pub fn world(&self) -> &World {
+        assert!(self.exclusive, "Only exclusive systems can access the whole world!");
+        unsafe { &*self.world }
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        assert!(self.exclusive, "Only exclusive systems can access the whole world!");
+        unsafe { &mut *self.world }
+    }
+}
+
+struct SystemEntry {
+    system: Box<dyn System>,
+    access: SystemAccess,
+    last_run: u32,
+    commands: Commands,
+}
+
+struct Stage {
+    name: String,
+    systems: Vec<SystemEntry>,
+}
+
+pub struct Schedule {
+    stages: Vec<Stage>,
+    pool: Pool,
+    removed: Vec<Box<dyn Any>>,
+}
+
+// --- last run tick and own state of every system, stage by stage in insertion order; taken
+// --- next to a World snapshot so Changed filters and system state rewind together
+pub struct ScheduleSnapshot {
+    systems: Vec<(u32, Option<Box<dyn Any + Send>>)>,
+}
+
+impl Schedule {
+    pub fn new() -> Schedule {
+        Schedule::with_threads(0)
+    }
+
+    // --- 0 picks one thread per core
+    pub fn with_threads(num_threads: usize) -> Schedule {
+        let num_threads = match num_threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+
+        let mut schedule = Schedule {
+            stages: vec![],
+            pool: Pool::new(num_threads as u32),
+            removed: vec![],
+        };
+        schedule.add_stage(FIXED_UPDATE);
+        schedule.add_stage(POST_UPDATE);
+        schedule.add_stage(RENDER_PREPARE);
+
+        schedule
+    }
+
+    // --- stages run in the order they were added
+    pub fn add_stage(&mut self, name: &str) -> &mut Schedule {
+        assert!(self.stage_index(name).is_none(), "Stage already exists!");
+
+        self.stages.push(Stage {
+            name: String::from(name),
+            systems: vec![],
+        });
+        self
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, stage: &str, system: S) -> &mut Schedule {
+        let index = self.stage_index(stage).expect("Unknown stage!");
+        let access = system.access();
+        self.stages[index].systems.push(SystemEntry {
+            system: Box::new(system),
+            access: access,
+            last_run: 0,
+            commands: Commands::new(),
+        });
+        self
+    }
+
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name.as_str()).collect()
+    }
+
+    // --- systems of a stage grouped into the batches they run in; every batch only starts
+    // --- after the previous one is done, so conflicting systems keep their insertion order
+    pub fn batches(&self, stage: &str) -> Vec<Vec<&str>> {
+        let index = self.stage_index(stage).expect("Unknown stage!");
+        let systems = &self.stages[index].systems;
+
+        batch_ranges(systems)
+            .into_iter()
+            .map(|(start, end)| systems[start..end].iter().map(|entry| entry.system.name()).collect())
+            .collect()
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        for index in 0..self.stages.len() {
+            self.run_stage_at(index, world);
+        }
+    }
+
+    pub fn run_stage(&mut self, stage: &str, world: &mut World) {
+        let index = self.stage_index(stage).expect("Unknown stage!");
+        self.run_stage_at(index, world);
+    }
+
+    // --- components taken off by system commands (destroyed entities, removed components);
+    // --- the caller drains them to release GPU resources such as meshes
+    pub fn take_removed(&mut self) -> Vec<Box<dyn Any>> {
+        std::mem::take(&mut self.removed)
+    }
+
+    pub fn snapshot(&self) -> ScheduleSnapshot {
+        ScheduleSnapshot {
+            systems: self
+                .stages
+                .iter()
+                .flat_map(|stage| stage.systems.iter())
+                .map(|entry| (entry.last_run, entry.system.snapshot()))
+                .collect(),
+        }
+    }
+
+    // --- the schedule has to hold the same systems as when the snapshot was taken
+    pub fn restore(&mut self, snapshot: &ScheduleSnapshot) {
+        let systems: Vec<&mut SystemEntry> = self
+            .stages
+            .iter_mut()
+            .flat_map(|stage| stage.systems.iter_mut())
+            .collect();
+        assert!(systems.len() == snapshot.systems.len(), "Snapshot is from a different schedule!");
+
+        for (entry, (last_run, state)) in systems.into_iter().zip(snapshot.systems.iter()) {
+            entry.last_run = *last_run;
+            if let Some(state) = state {
+                entry.system.restore(state.as_ref());
+            }
+        }
+    }
+
+    fn run_stage_at(&mut self, index: usize, world: &mut World) {
+        let pool = &mut self.pool;
+        let systems = &mut self.stages[index].systems;
+
+        for (start, end) in batch_ranges(systems) {
+            // --- systems of one batch touch disjoint data, so they can share a tick; the tick
+            // --- moves on afterwards so later batches see their writes as changes
+            let tick = world.change_tick();
+            let mut batch: Vec<(&mut Box<dyn System>, SystemContext)> = systems[start..end]
+                .iter_mut()
+                .map(|entry| {
+                    let SystemEntry { system, access, last_run, commands } = entry;
+                    let (reads, writes) = access.signatures(world);
+                    let ctx = SystemContext {
+                        world: world as *mut World,
+                        reads: reads,
+                        writes: writes,
+                        resource_reads: access.resource_reads.clone(),
+                        resource_writes: access.resource_writes.clone(),
+                        exclusive: access.exclusive,
+                        last_change_tick: *last_run,
+                        commands: commands,
+                    };
+                    *last_run = tick;
+                    (system, ctx)
+                })
+                .collect();
+
+            if batch.len() == 1 {
+                let (system, ctx) = &mut batch[0];
+                system.run(ctx);
+            } else {
+                pool.scoped(|scope| {
+                    for (system, mut ctx) in batch.into_iter() {
+                        scope.execute(move || system.run(&mut ctx));
+                    }
+                });
+            }
+
+            world.increment_change_tick();
+        }
+
+        // --- sync point: queued commands see the stage's final state and run in the
+        // --- order the systems were added, whatever order they finished in
+        for entry in systems.iter_mut() {
+            let removed = entry.commands.apply(world);
+            self.removed.extend(removed);
+        }
+    }
+
+    fn stage_index(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name == name)
+    }
+}
+
+// --- greedy and order-preserving: a system joins the open batch unless it conflicts with
+// --- something already in it, which keeps the plan identical from run to run
+fn batch_ranges(systems: &[SystemEntry]) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    for end in 0..systems.len() {
+        let conflicts = systems[start..end]
+            .iter()
+            .any(|entry| entry.access.conflicts_with(&systems[end].access));
+        if conflicts {
+            ranges.push((start, end));
+            start = end;
+        }
+    }
+    if start < systems.len() {
+        ranges.push((start, systems.len()));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Input(u32);
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Output(u32);
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Marker(&'static str);
+
+    // --- Input = value
+    struct SetInput(u32);
+
+    impl System for SetInput {
+        fn name(&self) -> &str {
+            "set_input"
+        }
+
+        fn access(&self) -> SystemAccess {
+            SystemAccess::new().write::<Input>()
+        }
+
+        fn run(&mut self, ctx: &mut SystemContext) {
+            let value = self.0;
+            ctx.query::<&mut Input>().for_each(|mut input| input.0 = value);
+        }
+    }
+
+    // --- Output = Input + 1
+    struct CopyInput;
+
+    impl System for CopyInput {
+        fn name(&self) -> &str {
+            "copy_input"
+        }
+
+        fn access(&self) -> SystemAccess {
+            SystemAccess::new().read::<Input>().write::<Output>()
+        }
+
+        fn run(&mut self, ctx: &mut SystemContext) {
+            ctx.query::<(&Input, &mut Output)>().for_each(|(input, mut output)| output.0 = input.0 + 1);
+        }
+    }
+
+    // --- only reads Input and queues a Marker insert; the delay makes it finish late
+    struct Tag {
+        name: &'static str,
+        delay: u64,
+    }
+
+    impl System for Tag {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn access(&self) -> SystemAccess {
+            SystemAccess::new().read::<Input>()
+        }
+
+        fn run(&mut self, ctx: &mut SystemContext) {
+            std::thread::sleep(std::time::Duration::from_millis(self.delay));
+            let entities: Vec<components::Entity> = ctx.query::<(components::Entity, &Input)>().map(|(entity, _)| entity).collect();
+            for entity in entities {
+                ctx.commands().insert(entity, Marker(self.name));
+            }
+        }
+    }
+
+    fn input_world() -> (World, components::Entity) {
+        let mut world = World::new();
+        let entity = world.create_entity().with(Input(0)).with(Output(0)).build();
+        (world, entity)
+    }
+
+    #[test]
+    fn conflicting_systems_keep_insertion_order() {
+        let (mut world, entity) = input_world();
+        let mut schedule = Schedule::with_threads(4);
+        schedule.add_system(FIXED_UPDATE, SetInput(5)).add_system(FIXED_UPDATE, CopyInput);
+        assert_eq!(schedule.batches(FIXED_UPDATE), vec![vec!["set_input"], vec!["copy_input"]]);
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        assert_eq!(world.get::<Output>(entity), Some(&Output(6)));
+
+        let (mut world, entity) = input_world();
+        let mut schedule = Schedule::with_threads(4);
+        schedule.add_system(FIXED_UPDATE, CopyInput).add_system(FIXED_UPDATE, SetInput(5));
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        assert_eq!(world.get::<Output>(entity), Some(&Output(1)));
+        assert_eq!(world.get::<Input>(entity), Some(&Input(5)));
+    }
+
+    #[test]
+    fn readers_share_a_batch() {
+        let mut schedule = Schedule::with_threads(4);
+        schedule
+            .add_system(FIXED_UPDATE, SetInput(1))
+            .add_system(FIXED_UPDATE, Tag { name: "a", delay: 0 })
+            .add_system(FIXED_UPDATE, Tag { name: "b", delay: 0 })
+            .add_system(FIXED_UPDATE, CopyInput);
+        assert_eq!(
+            schedule.batches(FIXED_UPDATE),
+            vec![vec!["set_input"], vec!["a", "b", "copy_input"]]
+        );
+    }
+
+    #[test]
+    fn commands_apply_in_system_order() {
+        let (mut world, entity) = input_world();
+        let mut schedule = Schedule::with_threads(4);
+        // --- both run in one batch; "first" finishes last but its command is applied first
+        schedule
+            .add_system(FIXED_UPDATE, Tag { name: "first", delay: 50 })
+            .add_system(FIXED_UPDATE, Tag { name: "second", delay: 0 });
+        assert_eq!(schedule.batches(FIXED_UPDATE).len(), 1);
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        assert_eq!(world.get::<Marker>(entity), Some(&Marker("second")));
+
+        // --- commands wait for the sync point at the end of the stage
+        let (mut world, entity) = input_world();
+        let mut schedule = Schedule::with_threads(1);
+        schedule.add_system(FIXED_UPDATE, Tag { name: "tag", delay: 0 }).add_system(FIXED_UPDATE, SetInput(3));
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        assert_eq!(world.get::<Marker>(entity), Some(&Marker("tag")));
+        assert_eq!(world.get::<Input>(entity), Some(&Input(3)));
+    }
+}
@@ -0,0 +1,821 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use cgmath::{InnerSpace, Matrix};
+
+use crate::components;
+use crate::schedule;
+use crate::world::{Changed, World};
+
+// --- axis aligned box in world space
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: cgmath::Vector3<f32>,
+    pub max: cgmath::Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: cgmath::Vector3<f32>, max: cgmath::Vector3<f32>) -> Aabb {
+        Aabb { min: min, max: max }
+    }
+
+    pub fn from_point(point: cgmath::Vector3<f32>) -> Aabb {
+        Aabb { min: point, max: point }
+    }
+
+    // --- world box of local bounds under matrix; entities without Bounds become a point
+    pub fn from_bounds(bounds: Option<&components::Bounds>, matrix: &cgmath::Matrix4<f32>) -> Aabb {
+        let bounds = match bounds {
+            Some(bounds) => bounds,
+            None => return Aabb::from_point(matrix.w.truncate()),
+        };
+
+        // --- transform the center and project the half extents onto every world axis
+        let center = (bounds.min + bounds.max) * 0.5;
+        let extent = (bounds.max - bounds.min) * 0.5;
+        let world_center = (matrix * center.extend(1.0)).truncate();
+        let mut world_extent = cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+        for axis in 0..3 {
+            let row = matrix.row(axis).truncate();
+            world_extent[axis] = row.x.abs() * extent.x + row.y.abs() * extent.y + row.z.abs() * extent.z;
+        }
+
+        Aabb {
+            min: world_center - world_extent,
+            max: world_center + world_extent,
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|axis| self.min[axis].is_finite() && self.max[axis].is_finite())
+    }
+
+    pub fn center(&self) -> cgmath::Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: cgmath::Vector3 {
+                x: self.min.x.min(other.min.x),
+                y: self.min.y.min(other.min.y),
+                z: self.min.z.min(other.min.z),
+            },
+            max: cgmath::Vector3 {
+                x: self.max.x.max(other.max.x),
+                y: self.max.y.max(other.max.y),
+                z: self.max.z.max(other.max.z),
+            },
+        }
+    }
+
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let margin = cgmath::Vector3 { x: margin, y: margin, z: margin };
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y && self.max.z >= other.max.z
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    // --- half the surface area; only compared against itself when picking tree siblings
+    pub fn area(&self) -> f32 {
+        let d = self.max - self.min;
+        d.x * d.y + d.y * d.z + d.z * d.x
+    }
+
+    pub fn distance_squared(&self, point: cgmath::Vector3<f32>) -> f32 {
+        let closest = cgmath::Vector3 {
+            x: point.x.max(self.min.x).min(self.max.x),
+            y: point.y.max(self.min.y).min(self.max.y),
+            z: point.z.max(self.min.z).min(self.max.z),
+        };
+        (closest - point).magnitude2()
+    }
+
+    // --- slab test; returns the entry distance along the ray (0 if the origin is inside)
+    pub fn ray_distance(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = max_distance;
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // --- NaN (origin on a slab plane of a parallel ray) must not widen the interval
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+}
+
+#[derive(Clone, Debug, Copy)]
+pub struct Ray {
+    pub origin: cgmath::Vector3<f32>,
+    // --- normalized by Ray::new, so hit distances are in world units
+    pub direction: cgmath::Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: cgmath::Vector3<f32>, direction: cgmath::Vector3<f32>) -> Ray {
+        Ray {
+            origin: origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> cgmath::Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+// --- inward facing planes (normal, distance); a point p is inside when dot(n, p) + d >= 0
+#[derive(Clone, Debug, Copy)]
+pub struct Frustum {
+    pub planes: [(cgmath::Vector3<f32>, f32); 6],
+}
+
+impl Frustum {
+    // --- planes of projection * view (Gribb / Hartmann); expects the -1..1 clip depth of
+    // --- cgmath::perspective, which is what ViewData uses. Order: left, right, bottom, top, near, far
+    pub fn from_matrix(view_projection: &cgmath::Matrix4<f32>) -> Frustum {
+        let r0 = view_projection.row(0);
+        let r1 = view_projection.row(1);
+        let r2 = view_projection.row(2);
+        let r3 = view_projection.row(3);
+
+        let plane = |p: cgmath::Vector4<f32>| {
+            let normal = p.truncate();
+            let length = normal.magnitude();
+            (normal / length, p.w / length)
+        };
+
+        Frustum {
+            planes: [
+                plane(r3 + r0),
+                plane(r3 - r0),
+                plane(r3 + r1),
+                plane(r3 - r1),
+                plane(r3 + r2),
+                plane(r3 - r2),
+            ],
+        }
+    }
+
+    // --- conservative: boxes near a frustum corner may pass although they are outside
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|(normal, distance)| {
+            let farthest = cgmath::Vector3 {
+                x: if normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                y: if normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                z: if normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            };
+            normal.dot(farthest) + distance >= 0.0
+        })
+    }
+
+    pub fn near_distance(&self, point: cgmath::Vector3<f32>) -> f32 {
+        let (normal, distance) = self.planes[4];
+        normal.dot(point) + distance
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct RayHit {
+    pub entity: components::Entity,
+    pub distance: f32,
+    pub point: cgmath::Vector3<f32>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    // --- leaves hold the fattened entity box, inner nodes the union of their children
+    aabb: Aabb,
+    parent: Option<usize>,
+    children: Option<[usize; 2]>,
+    entity: Option<components::Entity>,
+}
+
+#[derive(Clone, Debug)]
+struct Leaf {
+    node: usize,
+    aabb: Aabb,
+}
+
+// --- dynamic bounding volume hierarchy over world boxes of entities, kept as a world resource.
+// --- Leaves are enlarged by `margin` so small movements only update the stored box; the tree
+// --- is only restructured once an entity leaves its fattened box
+#[derive(Clone)]
+pub struct SpatialIndex {
+    nodes: Vec<Node>,
+    free_nodes: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<components::Entity, Leaf>,
+    margin: f32,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> SpatialIndex {
+        SpatialIndex::new(0.1)
+    }
+}
+
+// --- the node layout decides the order of query results, so snapshots compare all of it;
+// --- leaves are listed by entity since the map order is not stable
+impl fmt::Debug for SpatialIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut leaves: Vec<(&components::Entity, &Leaf)> = self.leaves.iter().collect();
+        leaves.sort_by_key(|(entity, _)| (entity.index, entity.generation));
+
+        f.debug_struct("SpatialIndex")
+            .field("nodes", &self.nodes)
+            .field("free_nodes", &self.free_nodes)
+            .field("root", &self.root)
+            .field("leaves", &leaves)
+            .field("margin", &self.margin)
+            .finish()
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(margin: f32) -> SpatialIndex {
+        SpatialIndex {
+            nodes: vec![],
+            free_nodes: vec![],
+            root: None,
+            leaves: HashMap::new(),
+            margin: margin,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn contains(&self, entity: components::Entity) -> bool {
+        self.leaves.contains_key(&entity)
+    }
+
+    pub fn aabb(&self, entity: components::Entity) -> Option<Aabb> {
+        self.leaves.get(&entity).map(|leaf| leaf.aabb)
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free_nodes.clear();
+        self.root = None;
+        self.leaves.clear();
+    }
+
+    // --- inserts the entity or moves it if it is already indexed. A box with NaN or infinite
+    // --- corners (e.g. from a degenerate transform) has no place in the tree, the entity is
+    // --- left out until it gets a finite one again
+    pub fn update(&mut self, entity: components::Entity, aabb: Aabb) {
+        if !aabb.is_finite() {
+            self.remove(entity);
+            return;
+        }
+        if let Some(leaf) = self.leaves.get_mut(&entity) {
+            leaf.aabb = aabb;
+            if self.nodes[leaf.node].aabb.contains(&aabb) {
+                return;
+            }
+            let node = leaf.node;
+            self.remove_leaf(node);
+            self.nodes[node].aabb = aabb.expanded(self.margin);
+            self.insert_leaf(node);
+            return;
+        }
+
+        let node = self.allocate(Node {
+            aabb: aabb.expanded(self.margin),
+            parent: None,
+            children: None,
+            entity: Some(entity),
+        });
+        self.insert_leaf(node);
+        self.leaves.insert(entity, Leaf { node: node, aabb: aabb });
+    }
+
+    pub fn remove(&mut self, entity: components::Entity) -> bool {
+        match self.leaves.remove(&entity) {
+            Some(leaf) => {
+                self.remove_leaf(leaf.node);
+                self.free_nodes.push(leaf.node);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // --- entities whose box touches the sphere, nearest first
+    pub fn query_sphere(&self, center: cgmath::Vector3<f32>, radius: f32) -> Vec<components::Entity> {
+        let bounds = Aabb::from_point(center).expanded(radius);
+        let mut hits = vec![];
+        self.visit(|aabb| aabb.intersects(&bounds), |entity, aabb| {
+            let distance = aabb.distance_squared(center);
+            if distance <= radius * radius {
+                hits.push((entity, distance));
+            }
+        });
+        sorted(hits)
+    }
+
+    // --- entities whose box overlaps the given box, ordered by distance to its center
+    pub fn query_aabb(&self, bounds: &Aabb) -> Vec<components::Entity> {
+        let center = bounds.center();
+        let mut hits = vec![];
+        self.visit(|aabb| aabb.intersects(bounds), |entity, aabb| {
+            if aabb.intersects(bounds) {
+                hits.push((entity, aabb.distance_squared(center)));
+            }
+        });
+        sorted(hits)
+    }
+
+    // --- entities inside or crossing the frustum, ordered by distance from the near plane
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<components::Entity> {
+        let mut hits = vec![];
+        self.visit(|aabb| frustum.intersects(aabb), |entity, aabb| {
+            if frustum.intersects(aabb) {
+                hits.push((entity, frustum.near_distance(aabb.center())));
+            }
+        });
+        sorted(hits)
+    }
+
+    // --- every entity box the ray enters within max_distance, nearest first; hits are
+    // --- against world boxes, not the triangles of the mesh
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Vec<RayHit> {
+        let mut hits = vec![];
+        self.visit(|aabb| aabb.ray_distance(ray, max_distance).is_some(), |entity, aabb| {
+            if let Some(distance) = aabb.ray_distance(ray, max_distance) {
+                hits.push(RayHit {
+                    entity: entity,
+                    distance: distance,
+                    point: ray.at(distance),
+                });
+            }
+        });
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    // --- depth first walk; `enter` prunes subtrees by their box, `hit` sees the tight box of each leaf
+    fn visit<E, H>(&self, enter: E, mut hit: H)
+    where
+        E: Fn(&Aabb) -> bool,
+        H: FnMut(components::Entity, &Aabb),
+    {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !enter(&node.aabb) {
+                continue;
+            }
+            match (node.children, node.entity) {
+                (Some(children), _) => stack.extend_from_slice(&children),
+                (None, Some(entity)) => hit(entity, &self.leaves[&entity].aabb),
+                (None, None) => {},
+            }
+        }
+    }
+
+    fn allocate(&mut self, node: Node) -> usize {
+        match self.free_nodes.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+
+    // --- descends towards the child whose box grows least, then pairs the leaf with the node found
+    fn insert_leaf(&mut self, leaf: usize) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes[leaf].parent = None;
+                self.root = Some(leaf);
+                return;
+            },
+        };
+
+        let aabb = self.nodes[leaf].aabb;
+        let mut sibling = root;
+        while let Some([left, right]) = self.nodes[sibling].children {
+            let combined = self.nodes[sibling].aabb.union(&aabb).area();
+            // --- cost of making a new parent here vs. pushing the leaf further down
+            let here = 2.0 * combined;
+            let inherited = 2.0 * (combined - self.nodes[sibling].aabb.area());
+            let descend = |child: usize| {
+                let child_aabb = &self.nodes[child].aabb;
+                let grown = child_aabb.union(&aabb).area();
+                match self.nodes[child].children {
+                    Some(_) => grown - child_aabb.area() + inherited,
+                    None => grown + inherited,
+                }
+            };
+            let (left_cost, right_cost) = (descend(left), descend(right));
+            if here < left_cost && here < right_cost {
+                break;
+            }
+            sibling = if left_cost < right_cost { left } else { right };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.union(&aabb),
+            parent: old_parent,
+            children: Some([sibling, leaf]),
+            entity: None,
+        });
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+        match old_parent {
+            Some(old_parent) => self.replace_child(old_parent, sibling, parent),
+            None => self.root = Some(parent),
+        }
+
+        self.refit(self.nodes[parent].parent);
+    }
+
+    // --- unlinks the leaf and collapses its parent into the sibling; the leaf node itself stays allocated
+    fn remove_leaf(&mut self, leaf: usize) {
+        let parent = match self.nodes[leaf].parent {
+            Some(parent) => parent,
+            None => {
+                self.root = None;
+                return;
+            },
+        };
+
+        let [left, right] = self.nodes[parent].children.unwrap();
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+        match grandparent {
+            Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+            None => self.root = Some(sibling),
+        }
+        self.nodes[parent].children = None;
+        self.free_nodes.push(parent);
+        self.nodes[leaf].parent = None;
+
+        self.refit(grandparent);
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let Some(children) = self.nodes[parent].children.as_mut() {
+            for child in children.iter_mut() {
+                if *child == old {
+                    *child = new;
+                }
+            }
+        }
+    }
+
+    fn refit(&mut self, mut node: Option<usize>) {
+        while let Some(index) = node {
+            let [left, right] = self.nodes[index].children.unwrap();
+            self.nodes[index].aabb = self.nodes[left].aabb.union(&self.nodes[right].aabb);
+            node = self.nodes[index].parent;
+        }
+    }
+}
+
+// --- total order, so a NaN distance from a degenerate box sorts last instead of panicking
+fn sorted(mut hits: Vec<(components::Entity, f32)>) -> Vec<components::Entity> {
+    hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+    hits.into_iter().map(|(entity, _)| entity).collect()
+}
+
+pub fn register_components(world: &mut World) {
+    world.register_resource_snapshot::<SpatialIndex>();
+}
+
+// --- keeps the SpatialIndex resource in sync with GlobalTransform and Bounds; runs after
+// --- transform propagation and only re-inserts what changed since its last run
+pub struct SpatialIndexSystem;
+
+impl schedule::System for SpatialIndexSystem {
+    fn name(&self) -> &str {
+        "spatial_index"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<components::GlobalTransform>()
+            .read::<components::Bounds>()
+            .write_resource::<SpatialIndex>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let mut moved: Vec<(components::Entity, Aabb)> = ctx
+            .query_filtered::<(
+                components::Entity,
+                &components::GlobalTransform,
+                Option<&components::Bounds>,
+            ), Changed<components::GlobalTransform>>()
+            .map(|(entity, global, bounds)| (entity, Aabb::from_bounds(bounds, &global.matrix)))
+            .collect();
+        moved.extend(
+            ctx.query_filtered::<(
+                components::Entity,
+                &components::GlobalTransform,
+                &components::Bounds,
+            ), Changed<components::Bounds>>()
+            .map(|(entity, global, bounds)| (entity, Aabb::from_bounds(Some(bounds), &global.matrix))),
+        );
+
+        // --- destroyed entities and removed transforms leave no change behind, so compare
+        // --- against everything that is still placed in the world
+        let placed: HashSet<components::Entity> = ctx
+            .query::<(components::Entity, &components::GlobalTransform)>()
+            .map(|(entity, _)| entity)
+            .collect();
+
+        let index = ctx.resource_mut::<SpatialIndex>();
+        let stale: Vec<components::Entity> = index
+            .leaves
+            .keys()
+            .filter(|entity| !placed.contains(entity))
+            .copied()
+            .collect();
+        for entity in stale {
+            index.remove(entity);
+        }
+        for (entity, aabb) in moved {
+            index.update(entity, aabb);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // --- xorshift, so the scenes are the same on every run without pulling in rand
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 % 10_000) as f32 / 10_000.0
+        }
+
+        fn vector(&mut self, scale: f32) -> cgmath::Vector3<f32> {
+            cgmath::Vector3 {
+                x: (self.next() - 0.5) * scale,
+                y: (self.next() - 0.5) * scale,
+                z: (self.next() - 0.5) * scale,
+            }
+        }
+
+        fn aabb(&mut self) -> Aabb {
+            let center = self.vector(100.0);
+            let extent = self.vector(4.0).map(f32::abs);
+            Aabb::new(center - extent, center + extent)
+        }
+    }
+
+    fn entity(index: u32) -> components::Entity {
+        components::Entity {
+            index: index,
+            generation: 0,
+        }
+    }
+
+    fn build(random: &mut Random, count: u32) -> (SpatialIndex, Vec<(components::Entity, Aabb)>) {
+        let mut index = SpatialIndex::new(0.5);
+        let boxes: Vec<(components::Entity, Aabb)> = (0..count).map(|i| (entity(i), random.aabb())).collect();
+        for (entity, aabb) in boxes.iter() {
+            index.update(*entity, *aabb);
+        }
+        (index, boxes)
+    }
+
+    // --- every inner node bounds its children, every leaf its entity, and links agree both ways
+    fn check_tree(index: &SpatialIndex) {
+        let mut leaves = 0;
+        let mut stack: Vec<usize> = index.root.into_iter().collect();
+        assert!(index.root.map_or(true, |root| index.nodes[root].parent.is_none()));
+        while let Some(node) = stack.pop() {
+            match (index.nodes[node].children, index.nodes[node].entity) {
+                (Some(children), _) => {
+                    for child in children.iter() {
+                        assert_eq!(index.nodes[*child].parent, Some(node));
+                        assert!(index.nodes[node].aabb.contains(&index.nodes[*child].aabb));
+                        stack.push(*child);
+                    }
+                },
+                (None, Some(entity)) => {
+                    let leaf = &index.leaves[&entity];
+                    assert_eq!(leaf.node, node);
+                    assert!(index.nodes[node].aabb.contains(&leaf.aabb));
+                    leaves += 1;
+                },
+                (None, None) => panic!("empty node in the tree"),
+            }
+        }
+        assert_eq!(leaves, index.len());
+    }
+
+    fn sorted_entities(mut entities: Vec<components::Entity>) -> Vec<components::Entity> {
+        entities.sort_by_key(|entity| entity.index);
+        entities
+    }
+
+    fn check_queries(index: &SpatialIndex, boxes: &[(components::Entity, Aabb)], random: &mut Random) {
+        for _ in 0..20 {
+            let bounds = random.aabb().expanded(5.0);
+            let expected: Vec<components::Entity> = boxes
+                .iter()
+                .filter(|(_, aabb)| aabb.intersects(&bounds))
+                .map(|(entity, _)| *entity)
+                .collect();
+            assert_eq!(sorted_entities(index.query_aabb(&bounds)), sorted_entities(expected));
+
+            let center = random.vector(100.0);
+            let radius = random.next() * 20.0;
+            let expected: Vec<components::Entity> = boxes
+                .iter()
+                .filter(|(_, aabb)| aabb.distance_squared(center) <= radius * radius)
+                .map(|(entity, _)| *entity)
+                .collect();
+            let found = index.query_sphere(center, radius);
+            let distances: Vec<f32> = found.iter().map(|entity| index.aabb(*entity).unwrap().distance_squared(center)).collect();
+            assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert_eq!(sorted_entities(found), sorted_entities(expected));
+
+            let ray = Ray::new(random.vector(150.0), random.vector(1.0));
+            let mut expected: Vec<(components::Entity, f32)> = boxes
+                .iter()
+                .filter_map(|(entity, aabb)| aabb.ray_distance(&ray, 200.0).map(|distance| (*entity, distance)))
+                .collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let hits = index.raycast(&ray, 200.0);
+            assert_eq!(
+                hits.iter().map(|hit| hit.distance).collect::<Vec<f32>>(),
+                expected.iter().map(|(_, distance)| *distance).collect::<Vec<f32>>()
+            );
+            assert_eq!(
+                sorted_entities(hits.iter().map(|hit| hit.entity).collect()),
+                sorted_entities(expected.iter().map(|(entity, _)| *entity).collect())
+            );
+        }
+    }
+
+    #[test]
+    fn queries_match_a_brute_force_scan() {
+        let mut random = Random(0x1234_5678);
+        let (index, boxes) = build(&mut random, 300);
+        check_tree(&index);
+        check_queries(&index, &boxes, &mut random);
+    }
+
+    #[test]
+    fn moved_and_removed_entities_are_refit() {
+        let mut random = Random(0x0bad_cafe);
+        let (mut index, mut boxes) = build(&mut random, 200);
+
+        for round in 0..5 {
+            for (i, (entity, aabb)) in boxes.iter_mut().enumerate() {
+                // --- small moves stay inside the fattened leaf, large ones reinsert it
+                let offset = if i % 2 == round % 2 { random.vector(0.4) } else { random.vector(30.0) };
+                *aabb = Aabb::new(aabb.min + offset, aabb.max + offset);
+                index.update(*entity, *aabb);
+            }
+            check_tree(&index);
+            check_queries(&index, &boxes, &mut random);
+        }
+
+        for (entity, _) in boxes.iter().step_by(3) {
+            assert!(index.remove(*entity));
+        }
+        assert!(!index.remove(boxes[0].0));
+        boxes = boxes.into_iter().enumerate().filter(|(i, _)| i % 3 != 0).map(|(_, entry)| entry).collect();
+        assert_eq!(index.len(), boxes.len());
+        check_tree(&index);
+        check_queries(&index, &boxes, &mut random);
+
+        // --- freed nodes are reused by later inserts
+        let nodes = index.nodes.len();
+        for i in 0..20 {
+            index.update(entity(1000 + i), random.aabb());
+        }
+        assert_eq!(index.nodes.len(), nodes);
+        check_tree(&index);
+    }
+
+    #[test]
+    fn frustum_queries_keep_boxes_inside_and_crossing() {
+        // --- 90 degrees wide, from z = -1 to z = -100 in front of the origin
+        let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, 1.0, 100.0);
+        let view = cgmath::Matrix4::look_at(
+            cgmath::Point3 { x: 0.0, y: 0.0, z: 0.0 },
+            cgmath::Point3 { x: 0.0, y: 0.0, z: -1.0 },
+            cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        );
+        let frustum = Frustum::from_matrix(&(projection * view));
+        let point = |x: f32, y: f32, z: f32| cgmath::Vector3 { x: x, y: y, z: z };
+        assert!(frustum.near_distance(point(0.0, 0.0, -1.0)).abs() < 1e-4);
+        assert!((frustum.near_distance(point(0.0, 0.0, -10.0)) - 9.0).abs() < 1e-4);
+        let (far_normal, far_distance) = frustum.planes[5];
+        assert!((far_normal.dot(point(0.0, 0.0, -100.0)) + far_distance).abs() < 1e-3);
+
+        let mut index = SpatialIndex::new(0.0);
+        let centers = [
+            point(0.0, 0.0, -10.0),
+            point(0.0, 0.0, -3.0),
+            // --- crossing the right plane x = -z
+            point(12.0, 0.0, -12.0),
+            // --- crossing the near plane
+            point(0.0, 0.0, -1.0),
+            // --- behind the camera, left of the view and past the far plane
+            point(0.0, 0.0, 5.0),
+            point(-20.0, 0.0, -10.0),
+            point(0.0, 0.0, -150.0),
+            // --- crossing the far plane
+            point(0.0, 0.0, -100.0),
+        ];
+        for (i, center) in centers.iter().enumerate() {
+            index.update(entity(i as u32), Aabb::from_point(*center).expanded(0.5));
+        }
+        assert_eq!(index.query_frustum(&frustum), vec![entity(3), entity(1), entity(0), entity(2), entity(7)]);
+
+        // --- a turned camera finds what a scan of its planes finds
+        let mut random = Random(0x5eed_f00d);
+        let (index, boxes) = build(&mut random, 300);
+        let view = cgmath::Matrix4::look_at(
+            cgmath::Point3 { x: -20.0, y: 10.0, z: 30.0 },
+            cgmath::Point3 { x: 10.0, y: 0.0, z: -5.0 },
+            cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        );
+        let frustum = Frustum::from_matrix(&(cgmath::perspective(cgmath::Deg(60.0), 1.5, 0.5, 60.0) * view));
+        let expected: Vec<components::Entity> = boxes
+            .iter()
+            .filter(|(_, aabb)| frustum.intersects(aabb))
+            .map(|(entity, _)| *entity)
+            .collect();
+        let found = index.query_frustum(&frustum);
+        assert!(!found.is_empty() && found.len() < boxes.len());
+        let distances: Vec<f32> = found.iter().map(|entity| frustum.near_distance(index.aabb(*entity).unwrap().center())).collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(sorted_entities(found), sorted_entities(expected));
+    }
+
+    #[test]
+    fn non_finite_boxes_are_left_out() {
+        let mut index = SpatialIndex::new(0.0);
+        let nan = cgmath::Vector3 { x: std::f32::NAN, y: 0.0, z: 0.0 };
+        index.update(entity(0), Aabb::from_point(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }));
+        index.update(entity(0), Aabb::from_point(nan));
+        assert!(!index.contains(entity(0)));
+        index.update(entity(1), Aabb::from_point(cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 }));
+        index.update(entity(2), Aabb::new(cgmath::Vector3 { x: -1.0, y: -1.0, z: -1.0 }, cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 }));
+
+        let bounds = Aabb::new(
+            cgmath::Vector3 { x: -10.0, y: -10.0, z: -10.0 },
+            cgmath::Vector3 { x: 10.0, y: 10.0, z: 10.0 },
+        );
+        assert_eq!(index.query_aabb(&bounds), vec![entity(2), entity(1)]);
+        let ray = Ray::new(cgmath::Vector3 { x: -5.0, y: 0.0, z: 0.0 }, cgmath::Vector3 { x: 1.0, y: 0.0, z: 0.0 });
+        let hits = index.raycast(&ray, 100.0);
+        assert_eq!(hits.iter().map(|hit| hit.entity).collect::<Vec<_>>(), vec![entity(2), entity(1)]);
+        assert_eq!(hits[0].distance, 4.0);
+        // --- NaN distances sort instead of panicking
+        assert!(index.query_sphere(cgmath::Vector3 { x: std::f32::INFINITY, y: 0.0, z: 0.0 }, 1.0).is_empty());
+        assert_eq!(sorted(vec![(entity(0), std::f32::NAN), (entity(1), 1.0)]), vec![entity(1), entity(0)]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::any::Any;
+
+use crate::components;
+use crate::world::{Storable, World};
+
+type Command = Box<dyn FnOnce(&mut World, &mut Vec<Box<dyn Any>>) + Send>;
+
+// --- structural changes recorded while storages are borrowed (e.g. inside a query loop)
+// --- and applied later in the order they were recorded
+#[derive(Default)]
+pub struct Commands {
+    commands: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Commands {
+        Commands::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // --- the entity is reserved right away, so it can be referred to by later commands; its
+    // --- components are only recorded by EntityCommands::build and added when the queue is applied
+    pub fn create_entity<'a>(&'a mut self, world: &World) -> EntityCommands<'a> {
+        EntityCommands {
+            entity: world.reserve_entity(),
+            commands: self,
+            components: vec![],
+        }
+    }
+
+    pub fn destroy_entity(&mut self, entity: components::Entity) {
+        self.commands.push(Box::new(move |world: &mut World, removed: &mut Vec<Box<dyn Any>>| {
+            if let Some(components) = world.destroy_entity(entity) {
+                removed.extend(components);
+            }
+        }));
+    }
+
+    pub fn insert<T: Storable>(&mut self, entity: components::Entity, component: T) {
+        self.commands.push(Box::new(move |world: &mut World, _removed: &mut Vec<Box<dyn Any>>| {
+            world.insert(entity, component);
+        }));
+    }
+
+    pub fn remove<T: Storable>(&mut self, entity: components::Entity) {
+        self.commands.push(Box::new(move |world: &mut World, removed: &mut Vec<Box<dyn Any>>| {
+            if let Some(component) = world.remove_component::<T>(entity) {
+                removed.push(Box::new(component));
+            }
+        }));
+    }
+
+    // --- runs every command in recording order; commands on entities that are dead by then
+    // --- are skipped. Components taken off by destroy/remove are handed back, like
+    // --- World::destroy_entity does, so GPU resources can be released
+    pub fn apply(&mut self, world: &mut World) -> Vec<Box<dyn Any>> {
+        let mut removed = vec![];
+        for command in self.commands.drain(..) {
+            command(world, &mut removed);
+        }
+        removed
+    }
+}
+
+#[must_use = "components are only queued by build()"]
+pub struct EntityCommands<'a> {
+    entity: components::Entity,
+    commands: &'a mut Commands,
+    components: Vec<Box<dyn FnOnce(&mut World, components::Entity) + Send>>,
+}
+
+impl<'a> EntityCommands<'a> {
+    pub fn entity(&self) -> components::Entity {
+        self.entity
+    }
+
+    pub fn with<T: Storable>(mut self, component: T) -> EntityCommands<'a> {
+        self.components.push(Box::new(move |world: &mut World, entity: components::Entity| {
+            world.insert(entity, component);
+        }));
+        self
+    }
+
+    // --- the reserved entity comes alive when the queue is applied, even without components
+    pub fn build(self) -> components::Entity {
+        let entity = self.entity;
+        let components = self.components;
+        self.commands.commands.push(Box::new(move |world: &mut World, _removed: &mut Vec<Box<dyn Any>>| {
+            world.flush_entities();
+            for with in components {
+                with(world, entity);
+            }
+        }));
+        entity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Marker(u32);
+
+    #[test]
+    fn built_entities_can_be_used_before_the_queue_is_applied() {
+        let mut world = World::new();
+        let mut commands = Commands::new();
+
+        let first = commands.create_entity(&world).with(Marker(1)).build();
+        let second = commands.create_entity(&world).with(Marker(2)).build();
+        assert_ne!(first, second);
+        assert!(!world.is_alive(first));
+        commands.insert(first, Marker(10));
+
+        // --- entities created directly in the meantime do not take the reserved indices
+        let direct = world.create_entity().with(Marker(3)).build();
+        assert!(direct != first && direct != second);
+
+        commands.apply(&mut world);
+        assert_eq!(world.get::<Marker>(first), Some(&Marker(10)));
+        assert_eq!(world.get::<Marker>(second), Some(&Marker(2)));
+        assert_eq!(world.get::<Marker>(direct), Some(&Marker(3)));
+        assert_eq!(world.entity_count(), 3);
+    }
+
+    #[test]
+    fn reserved_entities_reuse_free_slots() {
+        let mut world = World::new();
+        let old = world.create_entity().with(Marker(0)).build();
+        world.destroy_entity(old);
+
+        let mut commands = Commands::new();
+        let reused = commands.create_entity(&world).with(Marker(1)).build();
+        let fresh = commands.create_entity(&world).build();
+        assert_eq!(reused.index, old.index);
+        assert_ne!(reused.generation, old.generation);
+        assert_ne!(fresh.index, old.index);
+
+        commands.apply(&mut world);
+        assert!(!world.is_alive(old));
+        assert_eq!(world.get::<Marker>(reused), Some(&Marker(1)));
+        // --- an entity built without components is alive all the same
+        assert!(world.is_alive(fresh));
+        assert!(world.signature(fresh).unwrap().is_empty());
+    }
+
+    #[test]
+    fn destroying_a_queued_entity_drops_its_components() {
+        let mut world = World::new();
+        let mut commands = Commands::new();
+        let entity = commands.create_entity(&world).build();
+        commands.destroy_entity(entity);
+        commands.insert(entity, Marker(1));
+
+        commands.apply(&mut world);
+        assert!(!world.is_alive(entity));
+        assert_eq!(world.entity_count(), 0);
+        assert!(world.storage::<Marker>().map_or(true, |storage| storage.len() == 0));
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use cgmath::SquareMatrix;
+
+use crate::components;
+use crate::schedule;
+use crate::world::{With, Without, World};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum HierarchyError {
+    DeadEntity,
+    Cycle,
+}
+
+impl World {
+    // --- attaches child under parent, detaching it from any previous parent first;
+    // --- rejected if parent is child itself or one of its descendants
+    pub fn set_parent(&mut self, child: components::Entity, parent: components::Entity) -> Result<(), HierarchyError> {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return Err(HierarchyError::DeadEntity);
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            if entity == child {
+                return Err(HierarchyError::Cycle);
+            }
+            ancestor = self.get::<components::Parent>(entity).map(|parent| parent.entity);
+        }
+
+        self.remove_parent(child);
+        self.insert(child, components::Parent { entity: parent });
+        match self.get_mut::<components::Children>(parent) {
+            Some(children) => children.entities.push(child),
+            None => {
+                self.insert(parent, components::Children { entities: vec![child] });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_parent(&mut self, child: components::Entity) -> Option<components::Entity> {
+        let parent = self.remove_component::<components::Parent>(child)?.entity;
+
+        let now_empty = match self.get_mut::<components::Children>(parent) {
+            Some(children) => {
+                children.entities.retain(|entity| *entity != child);
+                children.entities.is_empty()
+            },
+            None => false,
+        };
+        if now_empty {
+            self.remove_component::<components::Children>(parent);
+        }
+
+        Some(parent)
+    }
+
+    pub fn children_of(&self, parent: components::Entity) -> &[components::Entity] {
+        match self.get::<components::Children>(parent) {
+            Some(children) => &children.entities,
+            None => &[],
+        }
+    }
+
+    // --- called on destroy: the entity leaves its parent and its children become roots
+    pub(crate) fn detach_hierarchy(&mut self, entity: components::Entity) {
+        self.remove_parent(entity);
+
+        if let Some(children) = self.remove_component::<components::Children>(entity) {
+            for child in children.entities {
+                self.remove_component::<components::Parent>(child);
+            }
+        }
+    }
+}
+
+// --- computes GlobalTransform = parent GlobalTransform * local Transform for every entity
+// --- with a Transform, adding the GlobalTransform component where it is missing
+pub fn propagate_transforms(world: &mut World) {
+    let missing: Vec<components::Entity> = world
+        .query_filtered::<components::Entity, (
+            With<components::Transform>,
+            Without<components::GlobalTransform>,
+        )>()
+        .collect();
+    for entity in missing {
+        world.insert(entity, components::GlobalTransform {
+            matrix: cgmath::Matrix4::identity(),
+        });
+    }
+
+    let locals: HashMap<components::Entity, (cgmath::Matrix4<f32>, Option<components::Entity>)> = world
+        .query::<(components::Entity, &components::Transform, Option<&components::Parent>)>()
+        .map(|(entity, transform, parent)| (entity, (transform.to_matrix(), parent.map(|parent| parent.entity))))
+        .collect();
+
+    let mut globals: HashMap<components::Entity, cgmath::Matrix4<f32>> = HashMap::with_capacity(locals.len());
+    for entity in locals.keys() {
+        resolve_global(*entity, &locals, &mut globals);
+    }
+
+    world
+        .query::<(components::Entity, &mut components::GlobalTransform)>()
+        .for_each(|(entity, mut global)| {
+            // --- only touch matrices that moved, so Changed<GlobalTransform> stays meaningful
+            match globals.get(&entity) {
+                Some(matrix) if global.matrix != *matrix => global.matrix = *matrix,
+                _ => {},
+            }
+        });
+}
+
+// --- same as propagate_transforms, but every local transform is first blended from its
+// --- PreviousTransform towards its Transform by alpha, and the result goes to RenderTransform
+pub fn interpolate_transforms(world: &mut World, alpha: f32) {
+    let missing: Vec<components::Entity> = world
+        .query_filtered::<components::Entity, (
+            With<components::Transform>,
+            Without<components::RenderTransform>,
+        )>()
+        .collect();
+    for entity in missing {
+        world.insert(entity, components::RenderTransform {
+            matrix: cgmath::Matrix4::identity(),
+        });
+    }
+
+    let locals: HashMap<components::Entity, (cgmath::Matrix4<f32>, Option<components::Entity>)> = world
+        .query::<(
+            components::Entity,
+            &components::Transform,
+            Option<&components::PreviousTransform>,
+            Option<&components::Parent>,
+        )>()
+        .map(|(entity, transform, previous, parent)| {
+            let local = match previous {
+                Some(previous) => previous.transform.slerp(transform, alpha).to_matrix(),
+                None => transform.to_matrix(),
+            };
+            (entity, (local, parent.map(|parent| parent.entity)))
+        })
+        .collect();
+
+    let mut globals: HashMap<components::Entity, cgmath::Matrix4<f32>> = HashMap::with_capacity(locals.len());
+    for entity in locals.keys() {
+        resolve_global(*entity, &locals, &mut globals);
+    }
+
+    world
+        .query::<(components::Entity, &mut components::RenderTransform)>()
+        .for_each(|(entity, mut render)| {
+            match globals.get(&entity) {
+                Some(matrix) if render.matrix != *matrix => render.matrix = *matrix,
+                _ => {},
+            }
+        });
+}
+
+// --- walks up to the first resolved ancestor (or root), then back down. set_parent keeps
+// --- parents acyclic, but a Parent inserted directly or restored from a snapshot can close a
+// --- loop; the loop is then cut above its lowest entity index, which resolves like a root
+fn resolve_global(
+    entity: components::Entity,
+    locals: &HashMap<components::Entity, (cgmath::Matrix4<f32>, Option<components::Entity>)>,
+    globals: &mut HashMap<components::Entity, cgmath::Matrix4<f32>>,
+) {
+    let mut chain: Vec<components::Entity> = vec![];
+    let mut positions = HashMap::new();
+    let mut current = Some(entity);
+    let mut base = cgmath::Matrix4::identity();
+    while let Some(e) = current {
+        if let Some(global) = globals.get(&e) {
+            base = *global;
+            break;
+        }
+        if let Some(start) = positions.get(&e) {
+            // --- every entity of the loop has the next one as parent, the last one the first
+            let cycle = chain.split_off(*start);
+            let root = (0..cycle.len()).min_by_key(|i| cycle[*i].index).unwrap();
+            let mut global = cgmath::Matrix4::identity();
+            for step in 0..cycle.len() {
+                let e = cycle[(root + cycle.len() - step) % cycle.len()];
+                global = global * locals[&e].0;
+                globals.insert(e, global);
+            }
+            base = globals[&cycle[0]];
+            break;
+        }
+        match locals.get(&e) {
+            Some((_, parent)) => {
+                positions.insert(e, chain.len());
+                chain.push(e);
+                current = *parent;
+            },
+            // --- parent without a Transform acts as the world origin
+            None => break,
+        }
+    }
+
+    for e in chain.into_iter().rev() {
+        base = base * locals[&e].0;
+        globals.insert(e, base);
+    }
+}
+
+pub struct TransformPropagationSystem;
+
+impl schedule::System for TransformPropagationSystem {
+    fn name(&self) -> &str {
+        "transform_propagation"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::exclusive()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        propagate_transforms(ctx.world_mut());
+    }
+}
+
+// --- first system of the fixed update: remembers every Transform before the tick moves it
+pub struct TransformHistorySystem;
+
+impl schedule::System for TransformHistorySystem {
+    fn name(&self) -> &str {
+        "transform_history"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::new()
+            .read::<components::Transform>()
+            .write::<components::PreviousTransform>()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let mut missing = vec![];
+        ctx.query::<(components::Entity, &components::Transform, Option<&mut components::PreviousTransform>)>()
+            .for_each(|(entity, transform, previous)| match previous {
+                Some(mut previous) => previous.transform = *transform,
+                None => missing.push((entity, *transform)),
+            });
+        for (entity, transform) in missing {
+            ctx.commands().insert(entity, components::PreviousTransform { transform: transform });
+        }
+    }
+}
+
+// --- runs before rendering, after all fixed ticks of the frame; reads Time::alpha
+pub struct TransformInterpolationSystem;
+
+impl schedule::System for TransformInterpolationSystem {
+    fn name(&self) -> &str {
+        "transform_interpolation"
+    }
+
+    fn access(&self) -> schedule::SystemAccess {
+        schedule::SystemAccess::exclusive()
+    }
+
+    fn run(&mut self, ctx: &mut schedule::SystemContext) {
+        let alpha = ctx.world().resource::<components::Time>().map_or(1.0, |time| time.alpha());
+        interpolate_transforms(ctx.world_mut(), alpha);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: 0.0, z: 0.0 },
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn global_x(world: &World, entity: components::Entity) -> f32 {
+        world.get::<components::GlobalTransform>(entity).unwrap().matrix.w.x
+    }
+
+    #[test]
+    fn reparenting_moves_the_child() {
+        let mut world = World::new();
+        let a = world.create_entity().with(at(1.0)).build();
+        let b = world.create_entity().with(at(10.0)).build();
+        let child = world.create_entity().with(at(100.0)).build();
+
+        world.set_parent(child, a).unwrap();
+        propagate_transforms(&mut world);
+        assert_eq!(global_x(&world, child), 101.0);
+
+        world.set_parent(child, b).unwrap();
+        assert_eq!(world.get::<components::Parent>(child).map(|parent| parent.entity), Some(b));
+        assert_eq!(world.children_of(b), &[child]);
+        // --- the old parent loses its emptied Children component
+        assert!(world.get::<components::Children>(a).is_none());
+        propagate_transforms(&mut world);
+        assert_eq!(global_x(&world, child), 110.0);
+
+        assert_eq!(world.remove_parent(child), Some(b));
+        assert!(world.children_of(b).is_empty());
+        propagate_transforms(&mut world);
+        assert_eq!(global_x(&world, child), 100.0);
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut world = World::new();
+        let root = world.create_entity().with(at(0.0)).build();
+        let middle = world.create_entity().with(at(0.0)).build();
+        let leaf = world.create_entity().with(at(0.0)).build();
+        world.set_parent(middle, root).unwrap();
+        world.set_parent(leaf, middle).unwrap();
+
+        assert_eq!(world.set_parent(root, leaf), Err(HierarchyError::Cycle));
+        assert_eq!(world.set_parent(middle, middle), Err(HierarchyError::Cycle));
+        assert!(world.get::<components::Parent>(root).is_none());
+        assert_eq!(world.get::<components::Parent>(middle).map(|parent| parent.entity), Some(root));
+
+        world.destroy_entity(root);
+        assert_eq!(world.set_parent(leaf, root), Err(HierarchyError::DeadEntity));
+    }
+
+    #[test]
+    fn destroy_turns_children_into_roots() {
+        let mut world = World::new();
+        let grandparent = world.create_entity().with(at(1.0)).build();
+        let parent = world.create_entity().with(at(10.0)).build();
+        let first = world.create_entity().with(at(100.0)).build();
+        let second = world.create_entity().with(at(200.0)).build();
+        world.set_parent(parent, grandparent).unwrap();
+        world.set_parent(first, parent).unwrap();
+        world.set_parent(second, parent).unwrap();
+
+        world.destroy_entity(parent).unwrap();
+        assert!(world.get::<components::Parent>(first).is_none());
+        assert!(world.get::<components::Parent>(second).is_none());
+        assert!(world.get::<components::Children>(grandparent).is_none());
+
+        propagate_transforms(&mut world);
+        assert_eq!(global_x(&world, first), 100.0);
+        assert_eq!(global_x(&world, second), 200.0);
+    }
+
+    #[test]
+    fn inserted_cycles_resolve_from_their_lowest_entity() {
+        let mut world = World::new();
+        let a = world.create_entity().with(at(1.0)).build();
+        let b = world.create_entity().with(at(10.0)).build();
+        let c = world.create_entity().with(at(100.0)).build();
+        let outside = world.create_entity().with(at(1000.0)).build();
+        world.set_parent(b, a).unwrap();
+        world.set_parent(c, b).unwrap();
+        world.set_parent(outside, c).unwrap();
+        // --- bypasses set_parent, as a command or a restore could
+        world.insert(a, components::Parent { entity: c });
+
+        propagate_transforms(&mut world);
+        assert_eq!(global_x(&world, a), 1.0);
+        assert_eq!(global_x(&world, b), 11.0);
+        assert_eq!(global_x(&world, c), 111.0);
+        assert_eq!(global_x(&world, outside), 1111.0);
+
+        interpolate_transforms(&mut world, 1.0);
+        assert_eq!(world.get::<components::RenderTransform>(c).unwrap().matrix.w.x, 111.0);
+    }
+}
@@ -0,0 +1,244 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::components;
+use crate::world::{Storable, World};
+
+#[derive(Clone, Debug)]
+pub struct SignatureStats {
+    pub signature: components::Signature,
+    pub components: Vec<&'static str>,
+    pub entities: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct StorageStats {
+    pub id: components::ComponentId,
+    pub name: &'static str,
+    pub entities: usize,
+    pub entry_size: usize,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorldStats {
+    pub entities: usize,
+    // --- slots of destroyed entities waiting to be reused
+    pub free_slots: usize,
+    pub signatures: Vec<SignatureStats>,
+    pub storages: Vec<StorageStats>,
+    pub resources: usize,
+}
+
+impl WorldStats {
+    pub fn storage_bytes(&self) -> usize {
+        self.storages.iter().map(|storage| storage.bytes).sum()
+    }
+}
+
+impl fmt::Display for WorldStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} entities, {} free slots, {} resources, {} bytes in storages",
+            self.entities,
+            self.free_slots,
+            self.resources,
+            self.storage_bytes()
+        )?;
+        writeln!(f, "signatures:")?;
+        for signature in self.signatures.iter() {
+            writeln!(f, "  {:>6}  [{}]", signature.entities, signature.components.join(", "))?;
+        }
+        writeln!(f, "storages:")?;
+        for storage in self.storages.iter() {
+            writeln!(
+                f,
+                "  {:>6}  {:<24} {:>4} bytes/entry {:>10} bytes",
+                storage.entities, storage.name, storage.entry_size, storage.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl World {
+    // --- lets describe() print components of type T; components without a formatter
+    // --- are listed by name and size only
+    pub fn register_component_debug<T: Storable + fmt::Debug>(&mut self) -> components::ComponentId {
+        let id = self.register_component::<T>();
+        self.component_infos[id].debug = Some(debug_component::<T>);
+        id
+    }
+
+    // --- signatures are sorted by entity count, storages by component id
+    pub fn stats(&self) -> WorldStats {
+        let mut counts: HashMap<&components::Signature, usize> = HashMap::new();
+        for (index, signature) in self.entity_signatures.iter().enumerate() {
+            if self.entity_alive[index] {
+                *counts.entry(signature).or_insert(0) += 1;
+            }
+        }
+
+        let mut signatures: Vec<SignatureStats> = counts
+            .into_iter()
+            .map(|(signature, entities)| SignatureStats {
+                signature: signature.clone(),
+                components: signature.ids().map(|id| short_name(self.component_infos[id].name)).collect(),
+                entities: entities,
+            })
+            .collect();
+        signatures.sort_by(|a, b| b.entities.cmp(&a.entities).then_with(|| a.components.cmp(&b.components)));
+
+        let storages = self
+            .component_infos
+            .iter()
+            .map(|info| {
+                let storage = self.storages[info.id].get();
+                StorageStats {
+                    id: info.id,
+                    name: short_name(info.name),
+                    entities: storage.len(),
+                    entry_size: info.entry_size,
+                    bytes: storage.bytes(),
+                }
+            })
+            .collect();
+
+        WorldStats {
+            entities: self.entity_count(),
+            free_slots: self.free_entities.len(),
+            signatures: signatures,
+            storages: storages,
+            resources: self.resources.len(),
+        }
+    }
+
+    // --- one line per component with the ticks from its StorageEntry, e.g.
+    // ---   Entity { index: 3, generation: 0 } "pillar_light"
+    // ---     Transform (added 1, changed 4): Transform { position: ... }
+    pub fn describe(&self, entity: components::Entity) -> Option<String> {
+        let signature = self.signature(entity)?;
+
+        let mut text = format!("{:?}", entity);
+        if let Some(name) = self.name(entity) {
+            text += &format!(" {:?}", name);
+        }
+        for id in signature.ids() {
+            let info = &self.component_infos[id];
+            let storage = self.storages[id].get();
+            let (added, changed) = storage.ticks(entity).expect("Entity signature out of sync with storage!");
+            let value = match (info.debug, storage.get_any(entity)) {
+                (Some(debug), Some(component)) => debug(component),
+                _ => format!("<{} bytes>", info.size),
+            };
+            text += &format!("\n  {} (added {}, changed {}): {}", short_name(info.name), added, changed, value);
+        }
+
+        Some(text)
+    }
+}
+
+fn debug_component<T: fmt::Debug + 'static>(component: &dyn Any) -> String {
+    format!("{:?}", component.downcast_ref::<T>().unwrap())
+}
+
+// --- "electrum::components::Transform" -> "Transform"; generic names are kept whole
+fn short_name(name: &'static str) -> &'static str {
+    if name.contains('<') {
+        return name;
+    }
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Health(u32);
+
+    // --- no Debug, so describe() only knows its size
+    struct Opaque([u8; 12]);
+
+    fn transform(x: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: 0.0, z: 0.0 },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    #[test]
+    fn stats_count_signatures_slots_and_bytes() {
+        let mut world = World::new();
+        let health = world.register_component_debug::<Health>();
+        let opaque = world.register_component::<Opaque>();
+        let empty = world.stats();
+        assert_eq!(empty.entities, 0);
+        assert!(empty.signatures.is_empty());
+        assert_eq!(empty.storage_bytes(), 0);
+
+        let mut entities = vec![];
+        for i in 0..3 {
+            entities.push(world.create_entity().with(transform(i as f32)).build());
+        }
+        for i in 0..2 {
+            entities.push(world.create_entity().with(transform(i as f32)).with(Health(10)).build());
+        }
+        entities.push(world.create_entity().with(Opaque([0; 12])).build());
+        world.destroy_entity(entities[0]);
+        world.destroy_entity(entities[5]);
+
+        let stats = world.stats();
+        assert_eq!(stats.entities, 4);
+        assert_eq!(stats.free_slots, 2);
+        let signatures: Vec<(Vec<&str>, usize)> = stats
+            .signatures
+            .iter()
+            .map(|signature| (signature.components.clone(), signature.entities))
+            .collect();
+        assert_eq!(signatures, vec![(vec!["Transform"], 2), (vec!["Transform", "Health"], 2)]);
+
+        assert!(stats.storages.windows(2).all(|pair| pair[0].id < pair[1].id));
+        let health = &stats.storages[health];
+        assert_eq!((health.name, health.entities), ("Health", 2));
+        assert_eq!(health.entry_size, std::mem::size_of::<components::StorageEntry<Health>>());
+        assert!(health.bytes >= 2 * health.entry_size);
+        // --- destroying the only Opaque keeps the storage's memory
+        let opaque = &stats.storages[opaque];
+        assert_eq!(opaque.entities, 0);
+        assert!(opaque.bytes >= opaque.entry_size);
+        assert_eq!(stats.storage_bytes(), stats.storages.iter().map(|storage| storage.bytes).sum::<usize>());
+        assert_eq!(stats.resources, 0);
+        world.insert_resource(Health(3));
+        assert_eq!(world.stats().resources, 1);
+        assert!(stats.to_string().starts_with("4 entities, 2 free slots, 0 resources"));
+    }
+
+    #[test]
+    fn describe_prints_names_ticks_and_debug_output() {
+        let mut world = World::new();
+        world.register_component_debug::<Health>();
+        world.register_component::<Opaque>();
+        let entity = world
+            .create_entity()
+            .with(components::Name::new("pillar"))
+            .with(Health(7))
+            .with(Opaque([1; 12]))
+            .build();
+
+        let text = world.describe(entity).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], format!("{:?} \"pillar\"", entity));
+        assert!(lines.contains(&"  Health (added 1, changed 1): Health(7)"), "{}", text);
+        assert!(lines.contains(&"  Opaque (added 1, changed 1): <12 bytes>"), "{}", text);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(world.get::<Health>(entity).unwrap().0, 7);
+        assert_eq!(world.get::<Opaque>(entity).unwrap().0, [1; 12]);
+
+        world.destroy_entity(entity);
+        assert!(world.describe(entity).is_none());
+    }
+}
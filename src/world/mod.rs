@@ -1,127 +1,472 @@
 
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::components;
 
+pub mod commands;
+pub mod hierarchy;
+pub mod inspect;
+pub mod name;
+pub mod query;
+pub mod resource;
+pub mod snapshot;
+pub mod storage;
+
+pub use commands::Commands;
+pub use query::{Changed, Query, QueryFilter, QueryIter, With, Without};
+pub use storage::{AnyStorage, Storable, Storage};
+
+use name::NameError;
+
+pub struct ComponentInfo {
+    pub id: components::ComponentId,
+    pub name: &'static str,
+    pub type_id: TypeId,
+    pub size: usize,
+    // --- size of a whole StorageEntry<T>, i.e. the component plus its bookkeeping
+    pub entry_size: usize,
+    // --- set by register_component_debug, used by describe()
+    pub debug: Option<fn(&dyn Any) -> String>,
+    // --- set by register_component_snapshot; components without it are not captured by snapshot()
+    pub clone: Option<fn(&dyn AnyStorage) -> Box<dyn AnyStorage>>,
+}
+
 pub struct World {
-    pending_mask: Option<u32>,
-    pending_components: Vec<components::Component>,
-    last_build_entity: Option<components::Entity>,
-
-    pub transform_storage: Vec<components::TransformStorageEntry>,
-    pub mesh_storage: Vec<components::MeshStorageEntry>,
-    pub velocity_storage: Vec<components::VelocityStorageEntry>,
-    pub material_storage: Vec<components::MaterialStorageEntry>,
-    pub pbr_material_storage: Vec<components::PBRMaterialStorageEntry>,
+    pending_mask: Option<components::Signature>,
+    pending_components: Vec<(components::ComponentId, Box<dyn Any + Send + Sync>)>,
+
+    // --- generation per entity slot; bumped on destroy so stale handles stop matching
+    entity_generations: Vec<u32>,
+    entity_alive: Vec<bool>,
+    entity_signatures: Vec<components::Signature>,
+    free_entities: Vec<u32>,
+    // --- handed out by reserve_entity since the last flush_entities; the first ones come off
+    // --- the end of free_entities, the rest are fresh indices past entity_generations
+    reserved_entities: AtomicUsize,
+
+    // --- component registry; a component id indexes both lists and is its signature bit
+    component_ids: HashMap<TypeId, components::ComponentId>,
+    component_infos: Vec<ComponentInfo>,
+    storages: Vec<StorageCell>,
+
+    resources: HashMap<TypeId, resource::ResourceCell>,
+    // --- resources registered with register_resource_snapshot, in registration order
+    resource_snapshots: Vec<snapshot::ResourceSnapshotInfo>,
+
+    // --- unique index over Name components, and the strings behind interned tags
+    names: HashMap<String, components::Entity>,
+    tag_names: Vec<String>,
+
+    // --- writes are stamped with change_tick; Changed/Added filters on world queries
+    // --- report what happened after last_change_tick. Ticks are not expected to wrap
+    change_tick: u32,
+    last_change_tick: u32,
+}
+
+// --- scheduler systems borrow disjoint storages from several threads at once,
+// --- so each storage sits behind its own cell instead of behind &mut World
+struct StorageCell(UnsafeCell<Box<dyn AnyStorage>>);
+
+impl StorageCell {
+    fn get(&self) -> &dyn AnyStorage {
+        unsafe { &**self.0.get() }
+    }
+
+    fn get_mut(&mut self) -> &mut dyn AnyStorage {
+        &mut **self.0.get_mut()
+    }
 }
 
+unsafe impl Sync for StorageCell {}
+
 impl World {
     pub fn new() -> World {
-        World {
+        let mut world = World {
             pending_mask: None,
             pending_components: vec![],
-            last_build_entity: None,
-            transform_storage: vec![],
-            mesh_storage: vec![],
-            velocity_storage: vec![],
-            material_storage: vec![],
-            pbr_material_storage: vec![],
+            entity_generations: vec![],
+            entity_alive: vec![],
+            entity_signatures: vec![],
+            free_entities: vec![],
+            reserved_entities: AtomicUsize::new(0),
+            component_ids: HashMap::new(),
+            component_infos: vec![],
+            storages: vec![],
+            resources: HashMap::new(),
+            resource_snapshots: vec![],
+            names: HashMap::new(),
+            tag_names: vec![],
+            change_tick: 1,
+            last_change_tick: 0,
+        };
+
+        world.register_component_snapshot::<components::Transform>();
+        world.register_component::<components::Mesh>();
+        world.register_component_snapshot::<components::Velocity>();
+        world.register_component::<components::Material>();
+        world.register_component_snapshot::<components::PBRMaterial>();
+        world.register_component_snapshot::<components::GlobalTransform>();
+        world.register_component_snapshot::<components::PreviousTransform>();
+        world.register_component_snapshot::<components::RenderTransform>();
+        world.register_component_snapshot::<components::Parent>();
+        world.register_component_snapshot::<components::Children>();
+        world.register_component_snapshot::<components::Name>();
+        world.register_component_snapshot::<components::Tags>();
+        world.register_component_snapshot::<components::Bounds>();
+        world.register_component_snapshot::<components::MeshSource>();
+        world.register_component_snapshot::<components::MaterialSource>();
+        world.register_resource_snapshot::<components::Time>();
+
+        world
+    }
+
+    // --- any 'static type can be a component; registering twice returns the same id
+    pub fn register_component<T: Storable>(&mut self) -> components::ComponentId {
+        if let Some(id) = self.component_ids.get(&TypeId::of::<T>()) {
+            return *id;
         }
+
+        let id = self.component_infos.len();
+        self.component_ids.insert(TypeId::of::<T>(), id);
+        self.component_infos.push(ComponentInfo {
+            id: id,
+            name: std::any::type_name::<T>(),
+            type_id: TypeId::of::<T>(),
+            size: std::mem::size_of::<T>(),
+            entry_size: std::mem::size_of::<components::StorageEntry<T>>(),
+            debug: None,
+            clone: None,
+        });
+        self.storages.push(StorageCell(UnsafeCell::new(Box::new(Storage::<T>::new()))));
+
+        id
+    }
+
+    pub fn component_id<T: Storable>(&self) -> Option<components::ComponentId> {
+        self.component_ids.get(&TypeId::of::<T>()).copied()
+    }
+
+    pub fn component_info(&self, id: components::ComponentId) -> Option<&ComponentInfo> {
+        self.component_infos.get(id)
     }
 
     pub fn create_entity(&mut self) -> &mut World {
         assert_eq!(self.pending_components.is_empty(), true);
 
-        self.pending_mask = Some(0);
+        self.pending_mask = Some(components::Signature::new());
         self
     }
 
     pub fn with_component(&mut self, component: components::Component) -> &mut World {
-        assert_eq!(self.pending_mask.is_some(), true);
-       
-        match &component {
-            components::Component::TransformComponent(_) => {
-                self.pending_mask = Some(self.pending_mask.unwrap() | components::ComponentType::TransformComponent as u32)
-            },
-            components::Component::MeshComponent(_) => {
-                self.pending_mask = Some(self.pending_mask.unwrap() | components::ComponentType::MeshComponent as u32)
-            },
-            components::Component::VelocityComponent(_) => {
-                self.pending_mask = Some(self.pending_mask.unwrap() | components::ComponentType::VelocityComponent as u32)
-            },
-            components::Component::MaterialComponent(_) => {
-                self.pending_mask = Some(self.pending_mask.unwrap() | components::ComponentType::MaterialComponent as u32)
-            },
-            components::Component::PBRMaterialComponent(_) => {
-                self.pending_mask = Some(self.pending_mask.unwrap() | components::ComponentType::PBRMaterialComponent as u32)
-            },
-            _ => println!("Component not supported!")
+        match component {
+            components::Component::TransformComponent(transform) => self.with(transform),
+            components::Component::MeshComponent(mesh) => self.with(mesh),
+            components::Component::VelocityComponent(velocity) => self.with(velocity),
+            components::Component::MaterialComponent(material) => self.with(material),
+            components::Component::PBRMaterialComponent(pbr_material) => self.with(pbr_material),
         }
-        self.pending_components.push(component);
+    }
+
+    pub fn with<T: Storable>(&mut self, component: T) -> &mut World {
+        assert_eq!(self.pending_mask.is_some(), true);
+
+        let id = self.register_component::<T>();
+        self.pending_mask.as_mut().unwrap().insert(id);
+        self.pending_components.push((id, Box::new(component)));
 
         self
     }
 
+    // --- panics if the entity's Name is already taken; use try_build for names read from data
     pub fn build(&mut self) -> components::Entity {
+        self.try_build().expect("Entity name is already taken!")
+    }
+
+    // --- like build, but a Name that is already taken fails the build and drops the pending components
+    pub fn try_build(&mut self) -> Result<components::Entity, NameError> {
         assert_eq!(self.pending_mask.is_some(), true);
         assert_eq!(self.pending_components.is_empty(), false);
 
-        let storage_type = self.pending_mask.unwrap();
-        let entity = match self.last_build_entity {
-            None => 0,
-            Some(x) => (x + 1) as components::Entity
-        };
-        while !self.pending_components.is_empty() {
-            let component = self.pending_components.pop().unwrap();
-            
-            match component {
-                components::Component::TransformComponent(transform) => {
-                   let entry = components::StorageEntry::<components::Transform> {
-                       storage_type: storage_type,
-                       entity: entity,
-                       component: transform
-                   };
-                   self.transform_storage.push(entry);
-                },
-                components::Component::MeshComponent(mesh) => {
-                    let entry = components::StorageEntry::<components::Mesh> {
-                        storage_type: storage_type,
-                        entity: entity,
-                        component: mesh
-                    };
-                    self.mesh_storage.push(entry);
-                },
-                components::Component::VelocityComponent(velocity) => {
-                    let entry = components::StorageEntry::<components::Velocity> {
-                        storage_type: storage_type,
-                        entity: entity,
-                        component: velocity
-                    };
-                    self.velocity_storage.push(entry);
-                },
-                components::Component::MaterialComponent(material) => {
-                    let entry = components::StorageEntry::<components::Material> {
-                        storage_type: storage_type,
-                        entity: entity,
-                        component: material
-                    };
-                    self.material_storage.push(entry);
-                },
-                components::Component::PBRMaterialComponent(pbr_material) => {
-                    let entry = components::StorageEntry::<components::PBRMaterial> {
-                        storage_type: storage_type,
-                        entity: entity,
-                        component: pbr_material
-                    };
-                    self.pbr_material_storage.push(entry);
-                },
-                _ => println!("Component not supported!")
+        let owner = self
+            .pending_components
+            .iter()
+            .filter_map(|(_, component)| component.downcast_ref::<components::Name>())
+            .find_map(|name| self.find_by_name(name.as_str()));
+        if let Some(owner) = owner {
+            self.pending_mask = None;
+            self.pending_components.clear();
+            return Err(NameError::Taken(owner));
+        }
+
+        let storage_type = self.pending_mask.take().unwrap();
+        let entity = self.allocate_entity();
+        for (id, component) in self.pending_components.drain(..) {
+            self.storages[id].get_mut().insert_any(entity, storage_type.clone(), component, self.change_tick);
+        }
+        self.entity_signatures[entity.index as usize] = storage_type;
+        self.index_name(entity);
+
+        Ok(entity)
+    }
+
+    // --- adds the component to a live entity, replacing any existing component of that type
+    pub fn insert_component(&mut self, entity: components::Entity, component: components::Component) -> bool {
+        match component {
+            components::Component::TransformComponent(transform) => self.insert(entity, transform),
+            components::Component::MeshComponent(mesh) => self.insert(entity, mesh),
+            components::Component::VelocityComponent(velocity) => self.insert(entity, velocity),
+            components::Component::MaterialComponent(material) => self.insert(entity, material),
+            components::Component::PBRMaterialComponent(pbr_material) => self.insert(entity, pbr_material),
+        }
+    }
+
+    // --- false when the entity is dead or the component is a Name another entity has;
+    // --- try_insert tells the two apart
+    pub fn insert<T: Storable>(&mut self, entity: components::Entity, component: T) -> bool {
+        self.try_insert(entity, component).is_ok()
+    }
+
+    pub fn try_insert<T: Storable>(&mut self, entity: components::Entity, component: T) -> Result<(), NameError> {
+        if !self.is_alive(entity) {
+            return Err(NameError::DeadEntity);
+        }
+
+        if let Some(name) = (&component as &dyn Any).downcast_ref::<components::Name>() {
+            match self.find_by_name(name.as_str()) {
+                Some(owner) if owner != entity => return Err(NameError::Taken(owner)),
+                _ => self.unindex_name(entity),
             }
+        }
 
+        let id = self.register_component::<T>();
+        let mut storage_type = self.entity_signatures[entity.index as usize].clone();
+        storage_type.insert(id);
+
+        let tick = self.change_tick;
+        self.storage_mut::<T>().unwrap().insert(entity, storage_type.clone(), component, tick);
+        self.update_signature(entity, storage_type);
+
+        if name::is_name::<T>() {
+            self.index_name(entity);
         }
 
-        self.pending_mask = None;
-        self.pending_components.clear();
-        self.last_build_entity = Some(entity);
+        Ok(())
+    }
+
+    pub fn remove_component<T: Storable>(&mut self, entity: components::Entity) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        if name::is_name::<T>() {
+            self.unindex_name(entity);
+        }
 
-        entity
+        let id = self.component_id::<T>()?;
+        let component = self.storage_mut::<T>()?.remove(entity)?;
+        let mut storage_type = self.entity_signatures[entity.index as usize].clone();
+        storage_type.remove(id);
+        self.update_signature(entity, storage_type);
+
+        Some(component)
+    }
+
+    // --- picks the entity the next allocation would get, without touching the entity tables, so
+    // --- Commands can hand it out while systems share the world. It is dead until flush_entities
+    // --- (called by every allocation and destroy) makes it a live entity without components.
+    // --- Systems running in parallel reserve in whatever order they get here
+    pub fn reserve_entity(&self) -> components::Entity {
+        let reserved = self.reserved_entities.fetch_add(1, Ordering::Relaxed);
+        match reserved.checked_sub(self.free_entities.len()) {
+            None => {
+                let index = self.free_entities[self.free_entities.len() - 1 - reserved];
+                components::Entity {
+                    index: index,
+                    generation: self.entity_generations[index as usize],
+                }
+            },
+            Some(fresh) => components::Entity {
+                index: (self.entity_generations.len() + fresh) as u32,
+                generation: 0,
+            },
+        }
+    }
+
+    pub fn flush_entities(&mut self) {
+        let reserved = std::mem::replace(self.reserved_entities.get_mut(), 0);
+        for _ in 0..reserved {
+            self.take_entity_slot();
+        }
+    }
+
+    pub fn is_alive(&self, entity: components::Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.entity_generations.len()
+            && self.entity_alive[index]
+            && self.entity_generations[index] == entity.generation
+    }
+
+    // --- removes every component of the entity and frees its slot for reuse; the removed
+    // --- components are handed back (downcast e.g. to Mesh) so GPU resources can be released.
+    // --- returns None when the handle is stale or was never built
+    pub fn destroy_entity(&mut self, entity: components::Entity) -> Option<Vec<Box<dyn Any>>> {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        self.detach_hierarchy(entity);
+        self.unindex_name(entity);
+
+        let index = entity.index as usize;
+        let storages = &mut self.storages;
+        let removed: Vec<Box<dyn Any>> = self.entity_signatures[index]
+            .ids()
+            .filter_map(|id| storages[id].get_mut().remove_any(entity))
+            .collect();
+
+        self.entity_alive[index] = false;
+        self.entity_signatures[index] = components::Signature::new();
+        self.entity_generations[index] = self.entity_generations[index].wrapping_add(1);
+        self.free_entities.push(entity.index);
+
+        Some(removed)
+    }
+
+    // --- joins components by entity, e.g. world.query::<(&mut Transform, &Velocity)>()
+    pub fn query<Q: Query>(&mut self) -> QueryIter<'_, Q> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    // --- same as query, restricted by With/Without/Changed/Added filters
+    pub fn query_filtered<Q: Query, F: QueryFilter>(&mut self) -> QueryIter<'_, Q> {
+        Q::register(self);
+        F::register(self);
+        let last_change_tick = self.last_change_tick;
+        unsafe { QueryIter::new::<F>(self, last_change_tick) }
+    }
+
+    pub fn get<T: Storable>(&self, entity: components::Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: Storable>(&mut self, entity: components::Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        let tick = self.change_tick;
+        let storage = self.storage_mut::<T>()?;
+        storage.set_changed(entity, tick);
+        storage.get_mut(entity)
+    }
+
+    pub fn storage<T: Storable>(&self) -> Option<&Storage<T>> {
+        let id = self.component_id::<T>()?;
+        self.storages[id].get().as_any().downcast_ref::<Storage<T>>()
+    }
+
+    pub fn storage_mut<T: Storable>(&mut self) -> Option<&mut Storage<T>> {
+        let id = self.component_id::<T>()?;
+        self.storages[id].get_mut().as_any_mut().downcast_mut::<Storage<T>>()
+    }
+
+    pub fn signature(&self, entity: components::Entity) -> Option<&components::Signature> {
+        if self.is_alive(entity) {
+            Some(&self.entity_signatures[entity.index as usize])
+        } else {
+            None
+        }
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entity_alive.len() - self.free_entities.len()
+    }
+
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+
+    // --- everything written so far stops counting as changed or added for world queries
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.change_tick += 1;
+    }
+
+    // --- later writes become distinguishable from the ones made so far
+    pub(crate) fn increment_change_tick(&mut self) {
+        self.change_tick += 1;
     }
-}
\ No newline at end of file
+
+    // --- shared raw access for queries and systems; systems of one batch may all read the
+    // --- same storage, so only shared references are made here. The caller guarantees nobody
+    // --- holds a mutable borrow of the same storage
+    pub(crate) unsafe fn storage_ptr<T: Storable>(world: *const World) -> *const Storage<T> {
+        let id = (*world)
+            .component_id::<T>()
+            .expect("Component type was never registered!");
+        let storage: &dyn AnyStorage = &**(&(*world).storages)[id].0.get();
+        storage
+            .as_any()
+            .downcast_ref::<Storage<T>>()
+            .unwrap() as *const Storage<T>
+    }
+
+    // --- exclusive raw access, for storages the caller writes; the caller guarantees nobody
+    // --- else borrows the same storage at all
+    pub(crate) unsafe fn storage_mut_ptr<T: Storable>(world: *const World) -> *mut Storage<T> {
+        let id = (*world)
+            .component_id::<T>()
+            .expect("Component type was never registered!");
+        let storage = &mut **(&(*world).storages)[id].0.get();
+        storage
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .unwrap() as *mut Storage<T>
+    }
+
+    // --- every entry keeps a copy of the entity mask, so all of them follow a change
+    fn update_signature(&mut self, entity: components::Entity, storage_type: components::Signature) {
+        for id in storage_type.ids() {
+            self.storages[id].get_mut().set_storage_type(entity, &storage_type);
+        }
+        self.entity_signatures[entity.index as usize] = storage_type;
+    }
+
+    fn allocate_entity(&mut self) -> components::Entity {
+        self.flush_entities();
+        self.take_entity_slot()
+    }
+
+    fn take_entity_slot(&mut self) -> components::Entity {
+        match self.free_entities.pop() {
+            Some(index) => {
+                self.entity_alive[index as usize] = true;
+                components::Entity {
+                    index: index,
+                    generation: self.entity_generations[index as usize],
+                }
+            },
+            None => {
+                self.entity_generations.push(0);
+                self.entity_alive.push(true);
+                self.entity_signatures.push(components::Signature::new());
+                components::Entity {
+                    index: (self.entity_generations.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        }
+    }
+}
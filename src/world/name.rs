@@ -0,0 +1,177 @@
+use std::any::TypeId;
+
+use crate::components;
+use crate::world::{Storable, World};
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum NameError {
+    DeadEntity,
+    // --- holds the entity that already has the name
+    Taken(components::Entity),
+}
+
+impl World {
+    // --- names or renames the entity; setting the name it already has is a no-op
+    pub fn set_name(&mut self, entity: components::Entity, name: &str) -> Result<(), NameError> {
+        if self.is_alive(entity) && self.find_by_name(name) == Some(entity) {
+            return Ok(());
+        }
+
+        self.try_insert(entity, components::Name::new(name))
+    }
+
+    pub fn name(&self, entity: components::Entity) -> Option<&str> {
+        self.get::<components::Name>(entity).map(|name| name.as_str())
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<components::Entity> {
+        self.names.get(name).copied()
+    }
+
+    // --- interns the tag string; the same string always gives the same Tag
+    pub fn tag(&mut self, name: &str) -> components::Tag {
+        match self.find_tag(name) {
+            Some(tag) => tag,
+            None => {
+                self.tag_names.push(String::from(name));
+                components::Tag(self.tag_names.len() - 1)
+            },
+        }
+    }
+
+    pub fn find_tag(&self, name: &str) -> Option<components::Tag> {
+        self.tag_names.iter().position(|tag| tag == name).map(components::Tag)
+    }
+
+    pub fn tag_name(&self, tag: components::Tag) -> Option<&str> {
+        self.tag_names.get(tag.0).map(|name| name.as_str())
+    }
+
+    pub fn add_tag(&mut self, entity: components::Entity, name: &str) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let tag = self.tag(name);
+        match self.get_mut::<components::Tags>(entity) {
+            Some(tags) => tags.insert(tag),
+            None => {
+                let mut tags = components::Tags::default();
+                tags.insert(tag);
+                self.insert(entity, tags);
+            },
+        }
+        true
+    }
+
+    // --- drops the Tags component along with the last tag
+    pub fn remove_tag(&mut self, entity: components::Entity, name: &str) -> bool {
+        let tag = match self.find_tag(name) {
+            Some(tag) if self.has_tag(entity, name) => tag,
+            _ => return false,
+        };
+
+        let tags = self.get_mut::<components::Tags>(entity).unwrap();
+        tags.remove(tag);
+        if tags.is_empty() {
+            self.remove_component::<components::Tags>(entity);
+        }
+        true
+    }
+
+    pub fn has_tag(&self, entity: components::Entity, name: &str) -> bool {
+        match (self.find_tag(name), self.get::<components::Tags>(entity)) {
+            (Some(tag), Some(tags)) => tags.contains(tag),
+            _ => false,
+        }
+    }
+
+    // --- every entity carrying the tag; inside systems, look the Tag up once and filter
+    // --- a query over &Tags with Tags::contains
+    pub fn tagged(&mut self, name: &str) -> Vec<components::Entity> {
+        let tag = match self.find_tag(name) {
+            Some(tag) => tag,
+            None => return vec![],
+        };
+
+        self.query::<(components::Entity, &components::Tags)>()
+            .filter(|(_, tags)| tags.contains(tag))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    // --- called after a Name was written to the entity; try_insert and try_build have made
+    // --- sure no other entity holds it
+    pub(crate) fn index_name(&mut self, entity: components::Entity) {
+        if let Some(name) = self.get::<components::Name>(entity) {
+            let name = String::from(name.as_str());
+            self.names.insert(name, entity);
+        }
+    }
+
+    // --- called before the entity's Name is replaced, removed or destroyed
+    pub(crate) fn unindex_name(&mut self, entity: components::Entity) {
+        let name = match self.get::<components::Name>(entity) {
+            Some(name) => name.as_str(),
+            None => return,
+        };
+
+        if self.names.get(name) == Some(&entity) {
+            let name = String::from(name);
+            self.names.remove(&name);
+        }
+    }
+}
+
+pub(crate) fn is_name<T: Storable>() -> bool {
+    TypeId::of::<T>() == TypeId::of::<components::Name>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taken_names_are_refused_before_anything_is_stored() {
+        let mut world = World::new();
+        let first = world.create_entity().with(components::Name::new("first")).build();
+        let second = world.create_entity().with(components::Name::new("second")).with(1u32).build();
+
+        assert_eq!(world.try_insert(second, components::Name::new("first")), Err(NameError::Taken(first)));
+        assert!(!world.insert(second, components::Name::new("first")));
+        assert_eq!(world.set_name(second, "first"), Err(NameError::Taken(first)));
+        assert_eq!(world.name(second), Some("second"));
+        assert_eq!(world.find_by_name("first"), Some(first));
+        assert_eq!(world.find_by_name("second"), Some(second));
+
+        // --- a failed build leaves neither an entity nor its pending components behind
+        let count = world.entity_count();
+        let result = world.create_entity().with(2u32).with(components::Name::new("second")).try_build();
+        assert_eq!(result, Err(NameError::Taken(second)));
+        assert_eq!(world.entity_count(), count);
+        assert_eq!(world.storage::<u32>().unwrap().len(), 1);
+        let third = world.create_entity().with(components::Name::new("third")).build();
+        assert_eq!(world.find_by_name("third"), Some(third));
+    }
+
+    #[test]
+    fn renaming_and_destroying_free_the_old_name() {
+        let mut world = World::new();
+        let entity = world.create_entity().with(components::Name::new("old")).build();
+        assert_eq!(world.set_name(entity, "old"), Ok(()));
+        assert_eq!(world.set_name(entity, "new"), Ok(()));
+        assert_eq!(world.find_by_name("old"), None);
+        assert_eq!(world.find_by_name("new"), Some(entity));
+
+        let other = world.create_entity().with(components::Name::new("old")).build();
+        assert_eq!(world.try_insert(other, components::Name::new("other")), Ok(()));
+        assert_eq!(world.find_by_name("old"), None);
+
+        world.destroy_entity(entity);
+        assert_eq!(world.find_by_name("new"), None);
+        assert_eq!(world.set_name(entity, "new"), Err(NameError::DeadEntity));
+        assert_eq!(world.set_name(other, "new"), Ok(()));
+        assert_eq!(world.remove_component::<components::Name>(other).map(|name| String::from(name.as_str())), Some(String::from("new")));
+        assert_eq!(world.find_by_name("new"), None);
+    }
+}
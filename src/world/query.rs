@@ -0,0 +1,536 @@
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::components;
+use crate::world::{Storable, Storage, World};
+
+// --- components a query reads and writes, used to reject aliasing terms such as (&mut T, &T)
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    pub reads: components::Signature,
+    pub writes: components::Signature,
+}
+
+impl Access {
+    pub fn add_read(&mut self, id: components::ComponentId) {
+        assert!(!self.writes.contains(id), "Query reads a component it also writes!");
+        self.reads.insert(id);
+    }
+
+    pub fn add_write(&mut self, id: components::ComponentId) {
+        assert!(
+            !self.reads.contains(id) && !self.writes.contains(id),
+            "Query writes a component it also accesses!"
+        );
+        self.writes.insert(id);
+    }
+}
+
+pub trait Query {
+    type Item<'w>;
+    type State;
+
+    fn register(world: &mut World);
+    // --- collects the components an entity must have and the ones the query touches
+    fn init(world: &World, required: &mut components::Signature, access: &mut Access);
+
+    unsafe fn prepare(world: *mut World) -> Self::State;
+    unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w>;
+}
+
+pub trait QueryFilter {
+    type State;
+
+    fn register(world: &mut World);
+    // --- last_change_tick is the point Changed/Added compare against; components whose
+    // --- storage the filter looks into are added to reads
+    fn init(world: &World, last_change_tick: u32, reads: &mut components::Signature) -> Self::State;
+    fn matches(state: &Self::State, entity: components::Entity, signature: &components::Signature) -> bool;
+}
+
+pub struct With<T>(PhantomData<T>);
+pub struct Without<T>(PhantomData<T>);
+// --- entities whose T was written, or inserted, after the last change tick
+pub struct Changed<T>(PhantomData<T>);
+pub struct Added<T>(PhantomData<T>);
+
+impl<T: Storable> QueryFilter for With<T> {
+    type State = components::ComponentId;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, _last_change_tick: u32, _reads: &mut components::Signature) -> Self::State {
+        component_id::<T>(world)
+    }
+
+    fn matches(state: &Self::State, _entity: components::Entity, signature: &components::Signature) -> bool {
+        signature.contains(*state)
+    }
+}
+
+impl<T: Storable> QueryFilter for Without<T> {
+    type State = components::ComponentId;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, _last_change_tick: u32, _reads: &mut components::Signature) -> Self::State {
+        component_id::<T>(world)
+    }
+
+    fn matches(state: &Self::State, _entity: components::Entity, signature: &components::Signature) -> bool {
+        !signature.contains(*state)
+    }
+}
+
+impl QueryFilter for () {
+    type State = ();
+
+    fn register(_world: &mut World) {}
+
+    fn init(_world: &World, _last_change_tick: u32, _reads: &mut components::Signature) -> Self::State {}
+
+    fn matches(_state: &Self::State, _entity: components::Entity, _signature: &components::Signature) -> bool {
+        true
+    }
+}
+
+pub struct TickFilterState<T> {
+    storage: *const Storage<T>,
+    last_change_tick: u32,
+}
+
+impl<T: Storable> TickFilterState<T> {
+    fn new(world: &World, last_change_tick: u32, reads: &mut components::Signature) -> Self {
+        reads.insert(component_id::<T>(world));
+        TickFilterState {
+            storage: unsafe { World::storage_ptr::<T>(world) },
+            last_change_tick: last_change_tick,
+        }
+    }
+}
+
+impl<T: Storable> QueryFilter for Changed<T> {
+    type State = TickFilterState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, last_change_tick: u32, reads: &mut components::Signature) -> Self::State {
+        TickFilterState::new(world, last_change_tick, reads)
+    }
+
+    fn matches(state: &Self::State, entity: components::Entity, _signature: &components::Signature) -> bool {
+        unsafe { (*state.storage).changed_tick(entity) }.map_or(false, |tick| tick > state.last_change_tick)
+    }
+}
+
+impl<T: Storable> QueryFilter for Added<T> {
+    type State = TickFilterState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, last_change_tick: u32, reads: &mut components::Signature) -> Self::State {
+        TickFilterState::new(world, last_change_tick, reads)
+    }
+
+    fn matches(state: &Self::State, entity: components::Entity, _signature: &components::Signature) -> bool {
+        unsafe { (*state.storage).added_tick(entity) }.map_or(false, |tick| tick > state.last_change_tick)
+    }
+}
+
+// --- mutable component handed out by queries; writing through it marks the component changed
+pub struct Mut<'w, T> {
+    component: &'w mut T,
+    changed_tick: &'w mut u32,
+    tick: u32,
+}
+
+impl<'w, T> Deref for Mut<'w, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.component
+    }
+}
+
+impl<'w, T> DerefMut for Mut<'w, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        *self.changed_tick = self.tick;
+        self.component
+    }
+}
+
+// --- state of a read term; holds no mutable borrow, so other readers may share the storage
+pub struct ReadState<T> {
+    storage: *const Storage<T>,
+}
+
+// --- state of a write term; the storage is borrowed mutably, so the query must be its only user
+pub struct StorageState<T> {
+    storage: *mut Storage<T>,
+    entries: *mut components::StorageEntry<T>,
+    tick: u32,
+}
+
+fn component_id<T: Storable>(world: &World) -> components::ComponentId {
+    world
+        .component_id::<T>()
+        .expect("Component type was never registered!")
+}
+
+impl<T: Storable> ReadState<T> {
+    unsafe fn new(world: *mut World) -> Self {
+        ReadState {
+            storage: World::storage_ptr::<T>(world),
+        }
+    }
+
+    unsafe fn get<'w>(&self, entity: components::Entity) -> Option<&'w T> {
+        let storage: &'w Storage<T> = &*self.storage;
+        storage.get(entity)
+    }
+}
+
+impl<T: Storable> StorageState<T> {
+    unsafe fn new(world: *mut World) -> Self {
+        let storage = World::storage_mut_ptr::<T>(world);
+        StorageState {
+            storage: storage,
+            entries: (*storage).entries_ptr(),
+            tick: (*world).change_tick(),
+        }
+    }
+
+    unsafe fn entry(&self, entity: components::Entity) -> Option<*mut components::StorageEntry<T>> {
+        (*self.storage)
+            .index_of(entity)
+            .map(|index| self.entries.add(index))
+    }
+}
+
+impl Query for components::Entity {
+    type Item<'w> = components::Entity;
+    type State = ();
+
+    fn register(_world: &mut World) {}
+
+    fn init(_world: &World, _required: &mut components::Signature, _access: &mut Access) {}
+
+    unsafe fn prepare(_world: *mut World) -> Self::State {}
+
+    unsafe fn fetch<'w>(_state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+        entity
+    }
+}
+
+impl<'a, T: Storable> Query for &'a T {
+    type Item<'w> = &'w T;
+    type State = ReadState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, required: &mut components::Signature, access: &mut Access) {
+        let id = component_id::<T>(world);
+        required.insert(id);
+        access.add_read(id);
+    }
+
+    unsafe fn prepare(world: *mut World) -> Self::State {
+        ReadState::new(world)
+    }
+
+    unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+        state.get(entity).expect("Entity signature out of sync with storage!")
+    }
+}
+
+impl<'a, T: Storable> Query for &'a mut T {
+    type Item<'w> = Mut<'w, T>;
+    type State = StorageState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, required: &mut components::Signature, access: &mut Access) {
+        let id = component_id::<T>(world);
+        required.insert(id);
+        access.add_write(id);
+    }
+
+    unsafe fn prepare(world: *mut World) -> Self::State {
+        StorageState::new(world)
+    }
+
+    unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+        let entry = state.entry(entity).expect("Entity signature out of sync with storage!");
+        Mut {
+            component: &mut (*entry).component,
+            changed_tick: &mut (*entry).changed_tick,
+            tick: state.tick,
+        }
+    }
+}
+
+impl<'a, T: Storable> Query for Option<&'a T> {
+    type Item<'w> = Option<&'w T>;
+    type State = ReadState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, _required: &mut components::Signature, access: &mut Access) {
+        access.add_read(component_id::<T>(world));
+    }
+
+    unsafe fn prepare(world: *mut World) -> Self::State {
+        ReadState::new(world)
+    }
+
+    unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+        state.get(entity)
+    }
+}
+
+impl<'a, T: Storable> Query for Option<&'a mut T> {
+    type Item<'w> = Option<Mut<'w, T>>;
+    type State = StorageState<T>;
+
+    fn register(world: &mut World) {
+        world.register_component::<T>();
+    }
+
+    fn init(world: &World, _required: &mut components::Signature, access: &mut Access) {
+        access.add_write(component_id::<T>(world));
+    }
+
+    unsafe fn prepare(world: *mut World) -> Self::State {
+        StorageState::new(world)
+    }
+
+    unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+        state.entry(entity).map(|entry| Mut {
+            component: &mut (*entry).component,
+            changed_tick: &mut (*entry).changed_tick,
+            tick: state.tick,
+        })
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Query),*> Query for ($($name,)*) {
+            type Item<'w> = ($($name::Item<'w>,)*);
+            type State = ($($name::State,)*);
+
+            fn register(world: &mut World) {
+                $($name::register(world);)*
+            }
+
+            fn init(world: &World, required: &mut components::Signature, access: &mut Access) {
+                $($name::init(world, required, access);)*
+            }
+
+            unsafe fn prepare(world: *mut World) -> Self::State {
+                ($($name::prepare(world),)*)
+            }
+
+            unsafe fn fetch<'w>(state: &Self::State, entity: components::Entity) -> Self::Item<'w> {
+                let ($($name,)*) = state;
+                ($($name::fetch($name, entity),)*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type State = ($($name::State,)*);
+
+            fn register(world: &mut World) {
+                $($name::register(world);)*
+            }
+
+            fn init(world: &World, last_change_tick: u32, reads: &mut components::Signature) -> Self::State {
+                ($($name::init(world, last_change_tick, reads),)*)
+            }
+
+            fn matches(state: &Self::State, entity: components::Entity, signature: &components::Signature) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches($name, entity, signature))*
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+// --- visits matching entities in slot order, independent of storage insertion order
+pub struct QueryIter<'w, Q: Query> {
+    state: Q::State,
+    entities: std::vec::IntoIter<components::Entity>,
+    _world: PhantomData<&'w mut World>,
+}
+
+impl<'w, Q: Query> QueryIter<'w, Q> {
+    // --- the query and filter components must be registered, and the caller must hold
+    // --- exclusive access to every storage the query writes and shared access to the rest
+    pub(crate) unsafe fn new<F: QueryFilter>(world: *mut World, last_change_tick: u32) -> Self {
+        let entities = {
+            let world = &*world;
+            let mut required = components::Signature::new();
+            let mut access = Access::default();
+            Q::init(world, &mut required, &mut access);
+            let filter = F::init(world, last_change_tick, &mut components::Signature::new());
+
+            world
+                .entity_signatures
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| world.entity_alive[*index])
+                .map(|(index, signature)| {
+                    let entity = components::Entity {
+                        index: index as u32,
+                        generation: world.entity_generations[index],
+                    };
+                    (entity, signature)
+                })
+                .filter(|(entity, signature)| {
+                    signature.contains_all(&required) && F::matches(&filter, *entity, signature)
+                })
+                .map(|(entity, _)| entity)
+                .collect::<Vec<components::Entity>>()
+        };
+
+        QueryIter {
+            state: Q::prepare(world),
+            entities: entities.into_iter(),
+            _world: PhantomData,
+        }
+    }
+}
+
+impl<'w, Q: Query> Iterator for QueryIter<'w, Q> {
+    type Item = Q::Item<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = self.entities.next()?;
+        // --- every entity is visited once and aliasing terms were rejected up front,
+        // --- so the references handed out never overlap
+        Some(unsafe { Q::fetch(&self.state, entity) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entities.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct A(u32);
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct B(u32);
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct C;
+
+    // --- e0: (A, B), e1: A, e2: B, e3: (A, B, C)
+    fn mixed_world() -> (World, Vec<components::Entity>) {
+        let mut world = World::new();
+        let entities = vec![
+            world.create_entity().with(A(0)).with(B(0)).build(),
+            world.create_entity().with(A(1)).build(),
+            world.create_entity().with(B(2)).build(),
+            world.create_entity().with(A(3)).with(B(3)).with(C).build(),
+        ];
+        (world, entities)
+    }
+
+    #[test]
+    fn tuples_join_mixed_component_sets() {
+        let (mut world, e) = mixed_world();
+
+        let pairs: Vec<(components::Entity, A, B)> = world
+            .query::<(components::Entity, &A, &B)>()
+            .map(|(entity, a, b)| (entity, *a, *b))
+            .collect();
+        assert_eq!(pairs, vec![(e[0], A(0), B(0)), (e[3], A(3), B(3))]);
+
+        let optional: Vec<(components::Entity, Option<B>)> = world
+            .query::<(components::Entity, &A, Option<&B>)>()
+            .map(|(entity, _, b)| (entity, b.copied()))
+            .collect();
+        assert_eq!(optional, vec![(e[0], Some(B(0))), (e[1], None), (e[3], Some(B(3)))]);
+
+        let with: Vec<components::Entity> = world.query_filtered::<components::Entity, With<C>>().collect();
+        assert_eq!(with, vec![e[3]]);
+        let without: Vec<components::Entity> = world.query_filtered::<components::Entity, (With<B>, Without<C>)>().collect();
+        assert_eq!(without, vec![e[0], e[2]]);
+    }
+
+    #[test]
+    fn pairs_survive_swap_remove() {
+        let (mut world, e) = mixed_world();
+
+        // --- e0's A entry is swapped out for e3's, e1's B entry never existed
+        assert_eq!(world.remove_component::<A>(e[0]), Some(A(0)));
+        world.destroy_entity(e[1]).unwrap();
+        let e4 = world.create_entity().with(A(4)).build();
+        assert_eq!(e4.index, e[1].index);
+
+        let pairs: Vec<(components::Entity, A, Option<B>)> = world
+            .query::<(components::Entity, &A, Option<&B>)>()
+            .map(|(entity, a, b)| (entity, *a, b.copied()))
+            .collect();
+        assert_eq!(pairs, vec![(e4, A(4), None), (e[3], A(3), Some(B(3)))]);
+
+        world.query::<(&mut A, &B)>().for_each(|(mut a, b)| a.0 += b.0 * 10);
+        assert_eq!(world.get::<A>(e[3]), Some(&A(33)));
+        assert_eq!(world.get::<A>(e4), Some(&A(4)));
+        assert_eq!(world.get::<A>(e[1]), None);
+    }
+
+    #[test]
+    fn change_filters_follow_moved_entries() {
+        let (mut world, e) = mixed_world();
+        world.clear_trackers();
+
+        world.remove_component::<A>(e[0]);
+        world
+            .query::<(components::Entity, &mut A)>()
+            .filter(|(entity, _)| *entity == e[3])
+            .for_each(|(_, mut a)| a.0 = 30);
+        world.insert(e[2], A(2));
+        // --- read through Mut without writing: not a change
+        world.query::<&mut A>().for_each(|a| assert!(a.0 < 100));
+
+        let changed: Vec<components::Entity> = world.query_filtered::<components::Entity, Changed<A>>().collect();
+        assert_eq!(changed, vec![e[2], e[3]]);
+        let added: Vec<(components::Entity, A)> = world
+            .query_filtered::<(components::Entity, &A), Added<A>>()
+            .map(|(entity, a)| (entity, *a))
+            .collect();
+        assert_eq!(added, vec![(e[2], A(2))]);
+
+        world.clear_trackers();
+        assert_eq!(world.query_filtered::<components::Entity, Changed<A>>().count(), 0);
+    }
+}
@@ -0,0 +1,184 @@
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+
+use crate::world::{Storable, World};
+
+// --- one boxed value per type; like storages, each sits behind its own cell so
+// --- systems running side by side can borrow different resources
+pub(crate) struct ResourceCell(UnsafeCell<Box<dyn Any + Send + Sync>>);
+
+impl ResourceCell {
+    fn get(&self) -> &(dyn Any + Send + Sync) {
+        unsafe { &**self.0.get() }
+    }
+
+    fn get_mut(&mut self) -> &mut (dyn Any + Send + Sync) {
+        &mut **self.0.get_mut()
+    }
+}
+
+unsafe impl Sync for ResourceCell {}
+
+impl World {
+    // --- singleton state such as time or the view matrices; replaces and returns the
+    // --- previous value of the same type
+    pub fn insert_resource<T: Storable>(&mut self, resource: T) -> Option<T> {
+        let previous = self.remove_resource::<T>();
+        self.resources
+            .insert(TypeId::of::<T>(), ResourceCell(UnsafeCell::new(Box::new(resource))));
+        previous
+    }
+
+    pub fn remove_resource<T: Storable>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|cell| *cell.0.into_inner().downcast::<T>().unwrap())
+    }
+
+    pub fn has_resource<T: Storable>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn resource<T: Storable>(&self) -> Option<&T> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .and_then(|cell| cell.get().downcast_ref::<T>())
+    }
+
+    pub fn resource_mut<T: Storable>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|cell| cell.get_mut().downcast_mut::<T>())
+    }
+
+    pub(crate) fn resource_any(&self, type_id: TypeId) -> Option<&(dyn Any + Send + Sync)> {
+        self.resources.get(&type_id).map(|cell| cell.get())
+    }
+
+    pub(crate) fn insert_resource_any(&mut self, type_id: TypeId, resource: Box<dyn Any + Send + Sync>) {
+        self.resources.insert(type_id, ResourceCell(UnsafeCell::new(resource)));
+    }
+
+    pub(crate) fn remove_resource_any(&mut self, type_id: TypeId) {
+        self.resources.remove(&type_id);
+    }
+
+    // --- shared raw access for systems; the caller guarantees nobody else holds a mutable
+    // --- borrow of the same resource
+    pub(crate) unsafe fn resource_ptr<T: Storable>(world: *const World) -> Option<*const T> {
+        let cell = (&(*world).resources).get(&TypeId::of::<T>())?;
+        let resource: &(dyn Any + Send + Sync) = &**cell.0.get();
+        resource.downcast_ref::<T>().map(|resource| resource as *const T)
+    }
+
+    // --- exclusive raw access; the caller guarantees nobody else borrows the same resource
+    pub(crate) unsafe fn resource_mut_ptr<T: Storable>(world: *const World) -> Option<*mut T> {
+        let cell = (&(*world).resources).get(&TypeId::of::<T>())?;
+        (**cell.0.get())
+            .downcast_mut::<T>()
+            .map(|resource| resource as *mut T)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::{Schedule, System, SystemAccess, SystemContext, FIXED_UPDATE};
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+    #[derive(Debug, PartialEq)]
+    struct Seen(u32);
+    #[derive(Debug, PartialEq)]
+    struct Settings(u32);
+
+    #[test]
+    fn resources_are_inserted_replaced_and_removed() {
+        let mut world = World::new();
+        assert!(!world.has_resource::<Score>());
+        assert_eq!(world.resource::<Score>(), None);
+        assert_eq!(world.resource_mut::<Score>(), None);
+        assert_eq!(world.remove_resource::<Score>(), None);
+
+        assert_eq!(world.insert_resource(Score(1)), None);
+        assert_eq!(world.insert_resource(Score(2)), Some(Score(1)));
+        world.resource_mut::<Score>().unwrap().0 += 3;
+        assert_eq!(world.resource::<Score>(), Some(&Score(5)));
+        // --- resources are kept apart by type
+        assert_eq!(world.resource::<Seen>(), None);
+
+        assert_eq!(world.remove_resource::<Score>(), Some(Score(5)));
+        assert!(!world.has_resource::<Score>());
+        assert_eq!(world.remove_resource::<Score>(), None);
+    }
+
+    // --- adds Settings to Score, or copies Score to Seen, depending on its access
+    struct Uses {
+        name: &'static str,
+        access: fn() -> SystemAccess,
+    }
+
+    impl System for Uses {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn access(&self) -> SystemAccess {
+            (self.access)()
+        }
+
+        fn run(&mut self, ctx: &mut SystemContext) {
+            match self.name {
+                "add" => {
+                    let step = ctx.resource::<Settings>().0;
+                    ctx.resource_mut::<Score>().0 += step;
+                },
+                "copy" => {
+                    let score = ctx.resource::<Score>().0;
+                    ctx.resource_mut::<Seen>().0 = score;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    #[test]
+    fn resource_writes_split_batches() {
+        let mut schedule = Schedule::with_threads(4);
+        schedule
+            .add_system(FIXED_UPDATE, Uses {
+                name: "read_settings",
+                access: || SystemAccess::new().read_resource::<Settings>(),
+            })
+            .add_system(FIXED_UPDATE, Uses {
+                name: "add",
+                access: || SystemAccess::new().read_resource::<Settings>().write_resource::<Score>(),
+            })
+            .add_system(FIXED_UPDATE, Uses {
+                name: "copy",
+                access: || SystemAccess::new().read_resource::<Score>().write_resource::<Seen>(),
+            })
+            .add_system(FIXED_UPDATE, Uses {
+                name: "write_settings",
+                access: || SystemAccess::new().write_resource::<Settings>(),
+            })
+            .add_system(FIXED_UPDATE, Uses {
+                name: "read_seen",
+                access: || SystemAccess::new().read_resource::<Seen>(),
+            });
+        // --- readers of one resource share a batch, writers of different ones too
+        assert_eq!(
+            schedule.batches(FIXED_UPDATE),
+            vec![vec!["read_settings", "add"], vec!["copy", "write_settings"], vec!["read_seen"]]
+        );
+
+        let mut world = World::new();
+        world.insert_resource(Score(0));
+        world.insert_resource(Seen(0));
+        world.insert_resource(Settings(4));
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        schedule.run_stage(FIXED_UPDATE, &mut world);
+        assert_eq!(world.resource::<Score>(), Some(&Score(8)));
+        assert_eq!(world.resource::<Seen>(), Some(&Seen(8)));
+    }
+}
@@ -0,0 +1,502 @@
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::components;
+use crate::world::{AnyStorage, Storable, Storage, StorageCell, World};
+
+struct CapturedStorage {
+    name: &'static str,
+    debug: fn(&dyn Any) -> String,
+    clone: fn(&dyn AnyStorage) -> Box<dyn AnyStorage>,
+    storage: Box<dyn AnyStorage>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ResourceSnapshotInfo {
+    type_id: TypeId,
+    name: &'static str,
+    debug: fn(&dyn Any) -> String,
+    clone: fn(&(dyn Any + Send + Sync)) -> Box<dyn Any + Send + Sync>,
+}
+
+struct CapturedResource {
+    info: ResourceSnapshotInfo,
+    // --- None when the resource was not inserted at snapshot time
+    resource: Option<Box<dyn Any + Send + Sync>>,
+}
+
+// --- copy of the entity slots, of every component registered with register_component_snapshot
+// --- and of every resource registered with register_resource_snapshot; GPU side components
+// --- such as Mesh are not captured. Systems keep their own state, see Schedule::snapshot
+pub struct Snapshot {
+    entity_generations: Vec<u32>,
+    entity_alive: Vec<bool>,
+    entity_signatures: Vec<components::Signature>,
+    free_entities: Vec<u32>,
+    names: HashMap<String, components::Entity>,
+    // --- indexed by component id, None for components that are not captured
+    storages: Vec<Option<CapturedStorage>>,
+    resources: Vec<CapturedResource>,
+}
+
+impl Snapshot {
+    pub fn entity_count(&self) -> usize {
+        self.entity_alive.len() - self.free_entities.len()
+    }
+
+    // --- stable across runs of the same build; equal checksums mean equal component values
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.values().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // --- one line per component that is missing, extra or different in other
+    pub fn differences(&self, other: &Snapshot) -> Vec<String> {
+        let ours = self.values();
+        let theirs = other.values();
+        let our_values: HashMap<&String, &String> = ours.iter().map(|(key, value)| (key, value)).collect();
+        let their_values: HashMap<&String, &String> = theirs.iter().map(|(key, value)| (key, value)).collect();
+        let mut differences = vec![];
+
+        for (key, value) in ours.iter() {
+            match their_values.get(key) {
+                Some(other_value) if *other_value == value => {},
+                Some(other_value) => differences.push(format!("{}: {} != {}", key, value, other_value)),
+                None => differences.push(format!("{}: {} is missing", key, value)),
+            }
+        }
+        for (key, value) in theirs.iter() {
+            if !our_values.contains_key(key) {
+                differences.push(format!("{}: unexpected {}", key, value));
+            }
+        }
+
+        differences
+    }
+
+    // --- components are compared by their Debug output, which prints floats with the
+    // --- shortest representation that reads back to the same bits, so equal text means
+    // --- bit-identical values (NaN payloads aside). Uncaptured components only count by presence
+    fn values(&self) -> Vec<(String, String)> {
+        let mut values = vec![];
+        for (index, alive) in self.entity_alive.iter().enumerate() {
+            if !alive {
+                continue;
+            }
+            let entity = components::Entity {
+                index: index as u32,
+                generation: self.entity_generations[index],
+            };
+
+            for id in self.entity_signatures[index].ids() {
+                let (key, value) = match self.storages.get(id).and_then(|captured| captured.as_ref()) {
+                    Some(captured) => {
+                        let component = captured
+                            .storage
+                            .get_any(entity)
+                            .expect("Entity signature out of sync with storage!");
+                        (format!("{:?} {}", entity, captured.name), (captured.debug)(component))
+                    },
+                    None => (format!("{:?} component {}", entity, id), String::from("<not captured>")),
+                };
+                values.push((key, value));
+            }
+        }
+        for captured in self.resources.iter() {
+            let value = match &captured.resource {
+                Some(resource) => (captured.info.debug)(resource.as_ref()),
+                None => String::from("<absent>"),
+            };
+            values.push((format!("resource {}", captured.info.name), value));
+        }
+        values
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("entities", &self.entity_count())
+            .field("checksum", &self.checksum())
+            .finish()
+    }
+}
+
+// --- world states after each recorded tick, see World::record
+#[derive(Debug)]
+pub struct Recording {
+    start: Snapshot,
+    states: Vec<Snapshot>,
+}
+
+impl Recording {
+    pub fn ticks(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn start(&self) -> &Snapshot {
+        &self.start
+    }
+
+    pub fn state(&self, tick: usize) -> Option<&Snapshot> {
+        self.states.get(tick)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    // --- first tick (counting from 0) whose result differs from the recording
+    Diverged { tick: usize, differences: Vec<String> },
+}
+
+impl World {
+    // --- snapshot() copies T with Clone, describe() and snapshot comparison print it with Debug
+    pub fn register_component_snapshot<T: Storable + Clone + fmt::Debug>(&mut self) -> components::ComponentId {
+        let id = self.register_component_debug::<T>();
+        self.component_infos[id].clone = Some(clone_storage::<T>);
+        id
+    }
+
+    // --- snapshot() copies the resource with Clone and compares it by its Debug output, so
+    // --- Debug has to print everything later ticks depend on
+    pub fn register_resource_snapshot<T: Storable + Clone + fmt::Debug>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if self.resource_snapshots.iter().any(|info| info.type_id == type_id) {
+            return;
+        }
+
+        self.resource_snapshots.push(ResourceSnapshotInfo {
+            type_id: type_id,
+            name: std::any::type_name::<T>(),
+            debug: debug_resource::<T>,
+            clone: clone_resource::<T>,
+        });
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        assert!(self.pending_mask.is_none(), "Cannot snapshot while an entity is being built!");
+
+        let storages = self
+            .component_infos
+            .iter()
+            .map(|info| match (info.clone, info.debug) {
+                (Some(clone), Some(debug)) => Some(CapturedStorage {
+                    name: info.name,
+                    debug: debug,
+                    clone: clone,
+                    storage: clone(self.storages[info.id].get()),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Snapshot {
+            entity_generations: self.entity_generations.clone(),
+            entity_alive: self.entity_alive.clone(),
+            entity_signatures: self.entity_signatures.clone(),
+            free_entities: self.free_entities.clone(),
+            names: self.names.clone(),
+            storages: storages,
+            resources: self
+                .resource_snapshots
+                .iter()
+                .map(|info| CapturedResource {
+                    info: *info,
+                    resource: self.resource_any(info.type_id).map(info.clone),
+                })
+                .collect(),
+        }
+    }
+
+    // --- rolls entities, captured components and captured resources back to the snapshot.
+    // --- Restored components count as added and changed now, so Changed filters and uploads
+    // --- pick them up. An uncaptured component (e.g. Mesh) survives if its entity still exists
+    // --- and had it at snapshot time; every other one is removed and handed back like
+    // --- destroy_entity does. Uncaptured resources are left as they are
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Vec<Box<dyn Any>> {
+        assert!(self.pending_mask.is_none(), "Cannot restore while an entity is being built!");
+        self.flush_entities();
+
+        self.entity_generations = snapshot.entity_generations.clone();
+        self.entity_alive = snapshot.entity_alive.clone();
+        self.entity_signatures = snapshot.entity_signatures.clone();
+        self.free_entities = snapshot.free_entities.clone();
+        self.names = snapshot.names.clone();
+
+        let tick = self.change_tick;
+        let mut removed = vec![];
+        for id in 0..self.storages.len() {
+            match snapshot.storages.get(id).and_then(|captured| captured.as_ref()) {
+                Some(captured) => {
+                    let mut storage = (captured.clone)(&*captured.storage);
+                    storage.reset_ticks(tick);
+                    self.storages[id] = StorageCell(UnsafeCell::new(storage));
+                },
+                None => {
+                    let stale: Vec<components::Entity> = self.storages[id]
+                        .get()
+                        .entities()
+                        .into_iter()
+                        .filter(|entity| !self.is_alive(*entity) || !self.entity_signatures[entity.index as usize].contains(id))
+                        .collect();
+                    let storage = self.storages[id].get_mut();
+                    removed.extend(stale.into_iter().filter_map(|entity| storage.remove_any(entity)));
+                },
+            }
+        }
+
+        // --- uncaptured components removed after the snapshot cannot come back, drop them
+        // --- from the signatures; every entry then gets the restored signature
+        for index in 0..self.entity_alive.len() {
+            if !self.entity_alive[index] {
+                continue;
+            }
+            let entity = components::Entity {
+                index: index as u32,
+                generation: self.entity_generations[index],
+            };
+            let mut signature = self.entity_signatures[index].clone();
+            let missing: Vec<components::ComponentId> = signature
+                .ids()
+                .filter(|id| !self.storages[*id].get().contains(entity))
+                .collect();
+            missing.into_iter().for_each(|id| signature.remove(id));
+            self.update_signature(entity, signature);
+        }
+
+        for captured in snapshot.resources.iter() {
+            match &captured.resource {
+                Some(resource) => self.insert_resource_any(captured.info.type_id, (captured.info.clone)(resource.as_ref())),
+                None => self.remove_resource_any(captured.info.type_id),
+            }
+        }
+
+        removed
+    }
+
+    // --- runs step (given the tick number, e.g. to feed recorded input) ticks times and
+    // --- keeps a snapshot after every tick; memory grows with ticks * captured components
+    pub fn record<F: FnMut(&mut World, usize)>(&mut self, ticks: usize, mut step: F) -> Recording {
+        let start = self.snapshot();
+        let mut states = Vec::with_capacity(ticks);
+        for tick in 0..ticks {
+            step(self, tick);
+            states.push(self.snapshot());
+        }
+
+        Recording {
+            start: start,
+            states: states,
+        }
+    }
+
+    // --- restores the recording's start and re-runs the same ticks; every tick has to
+    // --- reproduce the recorded state bit for bit. Components removed by the restore are
+    // --- handed back on success, see restore
+    pub fn replay<F: FnMut(&mut World, usize)>(&mut self, recording: &Recording, mut step: F) -> Result<Vec<Box<dyn Any>>, ReplayError> {
+        let removed = self.restore(&recording.start);
+        for (tick, expected) in recording.states.iter().enumerate() {
+            step(self, tick);
+            let actual = self.snapshot();
+            if actual.checksum() != expected.checksum() {
+                return Err(ReplayError::Diverged {
+                    tick: tick,
+                    differences: expected.differences(&actual),
+                });
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+fn clone_storage<T: Storable + Clone>(storage: &dyn AnyStorage) -> Box<dyn AnyStorage> {
+    Box::new(storage.as_any().downcast_ref::<Storage<T>>().unwrap().clone())
+}
+
+fn clone_resource<T: Storable + Clone>(resource: &(dyn Any + Send + Sync)) -> Box<dyn Any + Send + Sync> {
+    Box::new(resource.downcast_ref::<T>().unwrap().clone())
+}
+
+fn debug_resource<T: Storable + fmt::Debug>(resource: &dyn Any) -> String {
+    format!("{:?}", resource.downcast_ref::<T>().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles;
+    use crate::physics;
+    use crate::schedule;
+    use crate::spatial;
+    use crate::world::hierarchy;
+
+    fn transform(x: f32, y: f32, z: f32) -> components::Transform {
+        components::Transform {
+            position: cgmath::Vector3 { x: x, y: y, z: z },
+            rotation: components::Transform::euler(cgmath::Vector3 { x: 0.1 * x, y: 0.0, z: 0.2 * z }),
+            scale: cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn velocity() -> components::Velocity {
+        components::Velocity {
+            translation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation_speed: cgmath::Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+
+    // --- a stack of bodies falling onto a fixed floor, an emitter and the post update systems
+    fn scene() -> (World, schedule::Schedule) {
+        let mut world = World::new();
+        physics::register_components(&mut world);
+        particles::register_components(&mut world);
+        spatial::register_components(&mut world);
+
+        world.insert_resource(components::Time {
+            fixed_dt: 1.0 / 60.0,
+            frame_time: 0.0,
+            accumulator: 0.0,
+        });
+        world.insert_resource(physics::PhysicsSettings::default());
+        world.insert_resource(spatial::SpatialIndex::default());
+
+        let floor = physics::Collider::cuboid(cgmath::Vector3 { x: 10.0, y: 0.5, z: 10.0 });
+        world
+            .create_entity()
+            .with(transform(0.0, -0.5, 0.0))
+            .with(velocity())
+            .with(physics::RigidBody::fixed())
+            .with(floor)
+            .build();
+        for index in 0..6 {
+            let collider = match index % 2 {
+                0 => physics::Collider::sphere(0.5),
+                _ => physics::Collider::cuboid(cgmath::Vector3 { x: 0.5, y: 0.5, z: 0.5 }),
+            };
+            let scale = cgmath::Vector3 { x: 1.0, y: 1.0, z: 1.0 };
+            world
+                .create_entity()
+                .with(transform(0.3 * index as f32, 1.0 + 1.1 * index as f32, -0.2 * index as f32))
+                .with(velocity())
+                .with(physics::RigidBody::new(1.0, &collider, scale))
+                .with(collider)
+                .build();
+        }
+        world
+            .create_entity()
+            .with(transform(0.0, 2.0, 0.0))
+            .with(particles::ParticleEmitter::new(50.0, 7))
+            .build();
+
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, physics::PhysicsSystem::new());
+        scheduler.add_system(schedule::FIXED_UPDATE, particles::ParticleSystem);
+        scheduler.add_system(schedule::POST_UPDATE, hierarchy::TransformPropagationSystem);
+        scheduler.add_system(schedule::POST_UPDATE, spatial::SpatialIndexSystem);
+        (world, scheduler)
+    }
+
+    fn step(scheduler: &mut schedule::Schedule, world: &mut World) {
+        scheduler.run_stage(schedule::FIXED_UPDATE, world);
+        scheduler.run_stage(schedule::POST_UPDATE, world);
+    }
+
+    #[test]
+    fn headless_replay_matches_the_recording() {
+        let (mut world, mut scheduler) = scene();
+        // --- settle a few ticks first so the recording starts with warm system state
+        for _ in 0..10 {
+            step(&mut scheduler, &mut world);
+        }
+
+        let scheduler_start = scheduler.snapshot();
+        let recording = world.record(90, |world, _tick| step(&mut scheduler, world));
+        let recorded: Vec<u64> = (0..recording.ticks())
+            .map(|tick| recording.state(tick).unwrap().checksum())
+            .collect();
+        assert_ne!(recording.start().checksum(), recorded[recorded.len() - 1]);
+
+        scheduler.restore(&scheduler_start);
+        world.restore(recording.start());
+        assert_eq!(world.snapshot().checksum(), recording.start().checksum());
+        for expected in recorded.iter() {
+            step(&mut scheduler, &mut world);
+            assert_eq!(world.snapshot().checksum(), *expected);
+        }
+
+        scheduler.restore(&scheduler_start);
+        assert!(world.replay(&recording, |world, _tick| step(&mut scheduler, world)).is_ok());
+    }
+
+    #[derive(Clone, Debug)]
+    struct Score(u32);
+
+    // --- counts its own runs and adds them to the Score resource
+    struct Scorer {
+        runs: u32,
+    }
+
+    impl schedule::System for Scorer {
+        fn name(&self) -> &str {
+            "scorer"
+        }
+
+        fn access(&self) -> schedule::SystemAccess {
+            schedule::SystemAccess::new().write_resource::<Score>()
+        }
+
+        fn snapshot(&self) -> Option<Box<dyn Any + Send>> {
+            Some(Box::new(self.runs))
+        }
+
+        fn restore(&mut self, state: &dyn Any) {
+            self.runs = *state.downcast_ref::<u32>().unwrap();
+        }
+
+        fn run(&mut self, ctx: &mut schedule::SystemContext) {
+            self.runs += 1;
+            ctx.resource_mut::<Score>().0 += self.runs;
+        }
+    }
+
+    #[test]
+    fn resources_and_system_state_are_rolled_back() {
+        let mut world = World::new();
+        world.register_resource_snapshot::<Score>();
+        world.insert_resource(Score(0));
+        let mut scheduler = schedule::Schedule::new();
+        scheduler.add_system(schedule::FIXED_UPDATE, Scorer { runs: 0 });
+
+        let scheduler_start = scheduler.snapshot();
+        let recording = world.record(5, |world, _tick| scheduler.run(world));
+        assert_eq!(world.resource::<Score>().unwrap().0, 15);
+
+        // --- the Score resource comes back, but the scorer still counts on from 5
+        match world.replay(&recording, |world, _tick| scheduler.run(world)) {
+            Err(ReplayError::Diverged { tick, differences }) => {
+                assert_eq!(tick, 0);
+                assert_eq!(differences.len(), 1);
+                assert!(differences[0].contains("Score"));
+            },
+            _ => panic!("Replay without the system state has to diverge!"),
+        }
+
+        scheduler.restore(&scheduler_start);
+        assert!(world.replay(&recording, |world, _tick| scheduler.run(world)).is_ok());
+        assert_eq!(world.resource::<Score>().unwrap().0, 15);
+
+        // --- resources missing at snapshot time are removed again
+        let mut world = World::new();
+        world.register_resource_snapshot::<Score>();
+        let snapshot = world.snapshot();
+        world.insert_resource(Score(3));
+        world.restore(&snapshot);
+        assert!(!world.has_resource::<Score>());
+    }
+}
@@ -0,0 +1,198 @@
+use std::any::Any;
+
+use crate::components;
+
+const NO_ENTRY: usize = std::usize::MAX;
+
+// --- components are shared with scheduler worker threads, so they must be Send + Sync
+pub trait Storable: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Storable for T {}
+
+// --- dense entries plus a sparse entity slot -> entry index lookup
+#[derive(Clone)]
+pub struct Storage<T> {
+    entries: Vec<components::StorageEntry<T>>,
+    lookup: Vec<usize>,
+}
+
+impl<T: Storable> Storage<T> {
+    pub fn new() -> Storage<T> {
+        Storage {
+            entries: vec![],
+            lookup: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, components::StorageEntry<T>> {
+        self.entries.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, components::StorageEntry<T>> {
+        self.entries.iter_mut()
+    }
+
+    pub fn get(&self, entity: components::Entity) -> Option<&T> {
+        self.index_of(entity).map(|index| &self.entries[index].component)
+    }
+
+    pub fn get_mut(&mut self, entity: components::Entity) -> Option<&mut T> {
+        self.index_of(entity).map(move |index| &mut self.entries[index].component)
+    }
+
+    pub fn index_of(&self, entity: components::Entity) -> Option<usize> {
+        match self.lookup.get(entity.index as usize) {
+            Some(&index) if index != NO_ENTRY && self.entries[index].entity == entity => Some(index),
+            _ => None,
+        }
+    }
+
+    // --- replaces the component in place if the entity already has one; either way the
+    // --- entry counts as changed at tick
+    pub fn insert(&mut self, entity: components::Entity, storage_type: components::Signature, component: T, tick: u32) {
+        match self.index_of(entity) {
+            Some(index) => {
+                self.entries[index].component = component;
+                self.entries[index].changed_tick = tick;
+            },
+            None => {
+                let slot = entity.index as usize;
+                if slot >= self.lookup.len() {
+                    self.lookup.resize(slot + 1, NO_ENTRY);
+                }
+                self.lookup[slot] = self.entries.len();
+                self.entries.push(components::StorageEntry::<T> {
+                    storage_type: storage_type,
+                    entity: entity,
+                    component: component,
+                    added_tick: tick,
+                    changed_tick: tick,
+                });
+            }
+        }
+    }
+
+    pub fn added_tick(&self, entity: components::Entity) -> Option<u32> {
+        self.index_of(entity).map(|index| self.entries[index].added_tick)
+    }
+
+    pub fn changed_tick(&self, entity: components::Entity) -> Option<u32> {
+        self.index_of(entity).map(|index| self.entries[index].changed_tick)
+    }
+
+    // --- get_mut and iter_mut do not track writes; World::get_mut and queries call this
+    pub fn set_changed(&mut self, entity: components::Entity, tick: u32) -> bool {
+        match self.index_of(entity) {
+            Some(index) => {
+                self.entries[index].changed_tick = tick;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, entity: components::Entity) -> Option<T> {
+        let index = self.index_of(entity)?;
+        self.lookup[entity.index as usize] = NO_ENTRY;
+
+        let entry = self.entries.swap_remove(index);
+        if index < self.entries.len() {
+            let moved = self.entries[index].entity.index as usize;
+            self.lookup[moved] = index;
+        }
+
+        Some(entry.component)
+    }
+
+    pub(crate) fn entries_ptr(&mut self) -> *mut components::StorageEntry<T> {
+        self.entries.as_mut_ptr()
+    }
+}
+
+// --- type-erased view of a Storage<T>, so World can hold any component type
+pub trait AnyStorage: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn len(&self) -> usize;
+    fn contains(&self, entity: components::Entity) -> bool;
+    fn get_any(&self, entity: components::Entity) -> Option<&dyn Any>;
+    // --- (added_tick, changed_tick) of the entity's entry
+    fn ticks(&self, entity: components::Entity) -> Option<(u32, u32)>;
+    // --- allocated entry and lookup capacity; heap memory owned by components is not counted
+    fn bytes(&self) -> usize;
+    fn entities(&self) -> Vec<components::Entity>;
+    // --- stamps every entry as added and changed at tick, e.g. after a restore
+    fn reset_ticks(&mut self, tick: u32);
+    fn insert_any(&mut self, entity: components::Entity, storage_type: components::Signature, component: Box<dyn Any + Send + Sync>, tick: u32);
+    fn remove_any(&mut self, entity: components::Entity) -> Option<Box<dyn Any>>;
+    fn set_storage_type(&mut self, entity: components::Entity, storage_type: &components::Signature);
+}
+
+impl<T: Storable> AnyStorage for Storage<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains(&self, entity: components::Entity) -> bool {
+        self.index_of(entity).is_some()
+    }
+
+    fn get_any(&self, entity: components::Entity) -> Option<&dyn Any> {
+        self.get(entity).map(|component| component as &dyn Any)
+    }
+
+    fn ticks(&self, entity: components::Entity) -> Option<(u32, u32)> {
+        self.index_of(entity)
+            .map(|index| (self.entries[index].added_tick, self.entries[index].changed_tick))
+    }
+
+    fn bytes(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<components::StorageEntry<T>>()
+            + self.lookup.capacity() * std::mem::size_of::<usize>()
+    }
+
+    fn entities(&self) -> Vec<components::Entity> {
+        self.entries.iter().map(|entry| entry.entity).collect()
+    }
+
+    fn reset_ticks(&mut self, tick: u32) {
+        for entry in self.entries.iter_mut() {
+            entry.added_tick = tick;
+            entry.changed_tick = tick;
+        }
+    }
+
+    fn insert_any(&mut self, entity: components::Entity, storage_type: components::Signature, component: Box<dyn Any + Send + Sync>, tick: u32) {
+        let component = component
+            .downcast::<T>()
+            .expect("Component does not match storage type!");
+        self.insert(entity, storage_type, *component, tick);
+    }
+
+    fn remove_any(&mut self, entity: components::Entity) -> Option<Box<dyn Any>> {
+        self.remove(entity).map(|component| Box::new(component) as Box<dyn Any>)
+    }
+
+    fn set_storage_type(&mut self, entity: components::Entity, storage_type: &components::Signature) {
+        if let Some(index) = self.index_of(entity) {
+            self.entries[index].storage_type = storage_type.clone();
+        }
+    }
+}